@@ -0,0 +1,258 @@
+// Audit log of runs that write full PANs
+//
+// Every run that writes full PANs anywhere (unmasked output or syslog events,
+// the token vault, remediation backups) appends one JSON line recording who
+// ran it, where, with which arguments, every destination of full PANs and
+// how much was found. Values of options that carry secrets are redacted from
+// the arguments.
+//
+// Each entry carries the SHA-256 of the previous entry and its own hash over
+// its contents, so editing, removing or reordering entries before the last
+// one breaks the chain. The hashes are not keyed: dropping entries from the
+// end, or rewriting the whole chain, goes unnoticed unless the last hash is
+// kept somewhere the log's writer cannot change.
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::CardMatch;
+
+pub const DEFAULT_LOG: &str = "luhnoxide-audit.jsonl";
+
+// prev_hash of the first entry in a log
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// Options whose values are secrets, e.g. webhook URLs with embedded tokens
+const SECRET_OPTIONS: &[&str] = &["--webhook"];
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp: String,
+    pub user: String,
+    pub host: String,
+    pub arguments: Vec<String>,
+    pub output_path: Option<String>,
+    // Everywhere the run wrote full PANs. Left out when empty, so entries
+    // written before it existed still verify.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub destinations: Vec<String>,
+    pub output_format: String,
+    pub findings: usize,
+    pub unique_cards: usize,
+    pub files_with_cards: usize,
+    pub prev_hash: String,
+    // SHA-256 of the entry serialized with this field empty
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> io::Result<String> {
+        let mut unhashed = serde_json::to_value(self)?;
+        unhashed["hash"] = serde_json::Value::String(String::new());
+        let digest = Sha256::digest(serde_json::to_string(&unhashed)?.as_bytes());
+        Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+// Append an entry for this run, chained to the last entry in the log. The
+// existing chain is verified first; a log that fails verification is left
+// alone and the run is refused.
+pub fn record_run(log_path: &Path, results: &[CardMatch], output_path: Option<&str>,
+                  destinations: &[String], output_format: &str) -> io::Result<()> {
+    let (entries, prev_hash) = if log_path.exists() {
+        verify_log(log_path).map_err(|e| io::Error::new(
+            e.kind(),
+            format!("Audit log {} failed verification: {}", log_path.display(), e),
+        ))?
+    } else {
+        (0, GENESIS_HASH.to_string())
+    };
+
+    let mut entry = AuditEntry {
+        sequence: entries + 1,
+        timestamp: chrono::Local::now().to_rfc3339(),
+        user: current_user(),
+        host: gethostname::gethostname().to_string_lossy().to_string(),
+        arguments: redact_arguments(std::env::args()),
+        output_path: output_path.map(str::to_string),
+        destinations: destinations.to_vec(),
+        output_format: output_format.to_string(),
+        findings: results.len(),
+        unique_cards: results.iter().map(|card| &card.fingerprint).collect::<HashSet<_>>().len(),
+        files_with_cards: results.iter().map(|card| &card.file_path).collect::<HashSet<_>>().len(),
+        prev_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry.compute_hash()?;
+
+    let mut log = open_log(log_path)?;
+    writeln!(log, "{}", serde_json::to_string(&entry)?)?;
+    log.sync_all()
+}
+
+// Check every entry's hash and link to its predecessor. Returns the number of
+// entries and the hash of the last one.
+pub fn verify_log(log_path: &Path) -> io::Result<(u64, String)> {
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut count = 0;
+
+    for (index, line) in BufReader::new(File::open(log_path)?).lines().enumerate() {
+        let line = line?;
+        let line_number = index + 1;
+        let invalid = |reason: &str| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {}", line_number, reason),
+        );
+
+        let entry: AuditEntry = serde_json::from_str(&line).map_err(|_| invalid("not a valid audit entry"))?;
+        count += 1;
+        if entry.sequence != count {
+            return Err(invalid("entry out of sequence"));
+        }
+        if entry.prev_hash != prev_hash {
+            return Err(invalid("does not chain to the previous entry"));
+        }
+        if entry.compute_hash()? != entry.hash {
+            return Err(invalid("contents do not match the entry hash"));
+        }
+        prev_hash = entry.hash;
+    }
+
+    Ok((count, prev_hash))
+}
+
+// Replace the values of secret-bearing options, given as `--option value`
+// or `--option=value`
+fn redact_arguments(arguments: impl Iterator<Item = String>) -> Vec<String> {
+    let mut redacted = Vec::new();
+    let mut secret_follows = false;
+
+    for argument in arguments {
+        if secret_follows {
+            redacted.push(REDACTED.to_string());
+            secret_follows = false;
+        } else if SECRET_OPTIONS.contains(&argument.as_str()) {
+            redacted.push(argument);
+            secret_follows = true;
+        } else if let Some((option, _)) = argument.split_once('=') && SECRET_OPTIONS.contains(&option) {
+            redacted.push(format!("{}={}", option, REDACTED));
+        } else {
+            redacted.push(argument);
+        }
+    }
+
+    redacted
+}
+
+// Login name from the environment, falling back to the numeric user id
+fn current_user() -> String {
+    if let Some(user) = ["USER", "USERNAME", "LOGNAME"].iter()
+        .find_map(|var| std::env::var(var).ok().filter(|user| !user.is_empty()))
+    {
+        return user;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(meta) = fs::metadata("/proc/self") {
+            return format!("uid:{}", meta.uid());
+        }
+    }
+    "unknown".to_string()
+}
+
+// Open the log for appending, readable and writable by the owner only
+fn open_log(log_path: &Path) -> io::Result<File> {
+    if let Some(parent) = log_path.parent() && !parent.as_os_str().is_empty() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(log_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_log(dir: &Path, runs: usize) -> std::path::PathBuf {
+        let log_path = dir.join("audit.jsonl");
+        for run in 0..runs {
+            record_run(&log_path, &[], Some("out.json"), &[format!("output file out{}.json", run)], "json").unwrap();
+        }
+        log_path
+    }
+
+    fn lines(log_path: &Path) -> Vec<String> {
+        fs::read_to_string(log_path).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn verify_lines(log_path: &Path, lines: &[String]) -> io::Result<(u64, String)> {
+        fs::write(log_path, lines.iter().map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+        verify_log(log_path)
+    }
+
+    #[test]
+    fn appended_entries_form_a_verifiable_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = write_log(dir.path(), 3);
+
+        let (count, last_hash) = verify_log(&log_path).unwrap();
+        assert_eq!(count, 3);
+        let entries: Vec<AuditEntry> = lines(&log_path).iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries[0].prev_hash, GENESIS_HASH);
+        assert_eq!(entries[1].prev_hash, entries[0].hash);
+        assert_eq!(entries[2].hash, last_hash);
+        assert_eq!(entries[2].destinations, ["output file out2.json"]);
+    }
+
+    #[test]
+    fn edited_removed_or_reordered_entries_fail_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = write_log(dir.path(), 3);
+        let original = lines(&log_path);
+
+        let mut edited = original.clone();
+        edited[1] = edited[1].replace("out1.json", "elsewhere.json");
+        let mut removed = original.clone();
+        removed.remove(1);
+        let mut reordered = original.clone();
+        reordered.swap(0, 1);
+
+        for (tampered, reason) in [(edited, "line 2: contents"), (removed, "line 2: entry out of sequence"),
+                                   (reordered, "line 1: entry out of sequence")] {
+            let error = verify_lines(&log_path, &tampered).unwrap_err();
+            assert!(error.to_string().starts_with(reason), "{}", error);
+        }
+
+        // The chain is not keyed, so a truncated log still verifies
+        assert_eq!(verify_lines(&log_path, &original[..2]).unwrap().0, 2);
+
+        // A log that fails verification is not appended to
+        verify_lines(&log_path, &original[1..]).unwrap_err();
+        assert!(record_run(&log_path, &[], None, &[], "text").is_err());
+        assert_eq!(lines(&log_path).len(), 2);
+    }
+
+    #[test]
+    fn secret_option_values_are_redacted() {
+        let arguments = ["luhnoxide", "-i", "share", "--webhook", "https://hooks.slack.com/services/T0/B0/secret",
+                         "--webhook=https://hooks.slack.com/services/T0/B0/secret", "--no-mask"];
+        assert_eq!(redact_arguments(arguments.iter().map(|argument| argument.to_string())),
+                   ["luhnoxide", "-i", "share", "--webhook", REDACTED, "--webhook=<redacted>", "--no-mask"]);
+    }
+}
@@ -0,0 +1,597 @@
+// Incremental scanning cache
+//
+// `--cache FILE` remembers, per scanned file, its size, modification time, a
+// SHA-256 of its content and what the scan found in it, in a SQLite database.
+// On the next run a clean file whose size and mtime are unchanged is not read
+// at all and its stored results are reused; one whose mtime changed but size
+// did not, or that had findings, is hashed and reused if the content is the
+// same, so files rewritten in place by remediation are rescanned. Other files
+// are scanned as usual and their entries replaced, and entries of files that
+// are gone from the scanned inputs are dropped.
+//
+// Findings are stored masked exactly as --discard-pans leaves them, so the
+// cache never holds a full PAN. Runs that need full PANs (--no-mask,
+// --redact, --tokenize) rescan files with cards and reuse only the rest.
+// Reused fingerprints must match fresh ones, so a fingerprint key is
+// required. Entries depend on the fingerprint key, filter, mask policy and
+// context width, so a digest of those is stored and the cache starts over
+// when it changes, as it does with --rebuild-cache.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use filetime::FileTime;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::filters::{FilterRejection, RejectReason};
+use crate::masking::MaskPolicy;
+use crate::quarantine::sha256_file;
+use crate::{mask_line_results, CardMatch, ScanConfig, CARD_PATTERN};
+
+pub const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+-- Digest of the settings the entries were made with
+CREATE TABLE IF NOT EXISTS settings (
+    id     INTEGER PRIMARY KEY CHECK (id = 1),
+    digest TEXT NOT NULL
+);
+
+-- What the last scan of each file found
+CREATE TABLE IF NOT EXISTS files (
+    path         TEXT PRIMARY KEY,
+    size         INTEGER NOT NULL,
+    mtime_ns     INTEGER NOT NULL,          -- nanoseconds since the Unix epoch
+    content_hash TEXT,                      -- SHA-256; NULL for skipped files
+    status       TEXT NOT NULL,             -- cards, clean or skipped
+    findings     TEXT                       -- masked findings and filter rejections as JSON; NULL if none
+);
+";
+
+// Fingerprinted to tell fingerprint keys apart without storing them
+const CANARY_PAN: &str = "4111111111111111";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileStatus {
+    Cards,
+    Clean,
+    // Not text; findings before the first invalid line are kept
+    Skipped,
+}
+
+impl FileStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FileStatus::Cards => "cards",
+            FileStatus::Clean => "clean",
+            FileStatus::Skipped => "skipped",
+        }
+    }
+
+    fn from_str(status: &str) -> FileStatus {
+        match status {
+            "cards" => FileStatus::Cards,
+            "skipped" => FileStatus::Skipped,
+            _ => FileStatus::Clean,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    size: u64,
+    mtime_ns: i64,
+    content_hash: Option<String>,
+    status: FileStatus,
+    findings: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct StoredFindings {
+    cards: Vec<StoredCard>,
+    rejections: Vec<(StoredCard, Vec<RejectReason>)>,
+}
+
+// A masked match with every field, including the spans that CardMatch's own
+// serialized form leaves out
+#[derive(Serialize, Deserialize)]
+struct StoredCard {
+    brand: String,
+    masked_pan: String,
+    bin: String,
+    last_four: String,
+    length: usize,
+    file_path: String,
+    line_number: usize,
+    line_content: String,
+    fingerprint: String,
+    match_start: usize,
+    match_end: usize,
+    line_spans: Vec<(usize, usize)>,
+    byte_offset: u64,
+    column_start: usize,
+    column_end: usize,
+    context: String,
+    context_span: (usize, usize),
+}
+
+impl From<&CardMatch> for StoredCard {
+    fn from(card: &CardMatch) -> StoredCard {
+        StoredCard {
+            brand: card.brand.clone(),
+            masked_pan: card.full_pan.clone(),
+            bin: card.bin.clone(),
+            last_four: card.last_four.clone(),
+            length: card.length,
+            file_path: card.file_path.clone(),
+            line_number: card.line_number,
+            line_content: card.line_content.clone(),
+            fingerprint: card.fingerprint.clone(),
+            match_start: card.match_start,
+            match_end: card.match_end,
+            line_spans: card.line_spans.clone(),
+            byte_offset: card.byte_offset,
+            column_start: card.column_start,
+            column_end: card.column_end,
+            context: card.context.clone(),
+            context_span: card.context_span,
+        }
+    }
+}
+
+impl From<StoredCard> for CardMatch {
+    fn from(card: StoredCard) -> CardMatch {
+        CardMatch {
+            brand: card.brand,
+            full_pan: card.masked_pan,
+            bin: card.bin,
+            last_four: card.last_four,
+            length: card.length,
+            file_path: card.file_path,
+            line_number: card.line_number,
+            line_content: card.line_content,
+            fingerprint: card.fingerprint,
+            match_start: card.match_start,
+            match_end: card.match_end,
+            line_spans: card.line_spans,
+            byte_offset: card.byte_offset,
+            column_start: card.column_start,
+            column_end: card.column_end,
+            context: card.context,
+            context_span: card.context_span,
+        }
+    }
+}
+
+pub struct ScanCache {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+    // Entries as of the previous run
+    previous: HashMap<String, Entry>,
+    // Entries written or confirmed by this run
+    updates: Mutex<Vec<(String, Entry)>>,
+    seen: Mutex<HashSet<String>>,
+    policy: MaskPolicy,
+    // True if the scan already masks its matches
+    discarding: bool,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl ScanCache {
+    // Open or create the cache and load its entries. They are dropped if
+    // `rebuild` is set or the scan settings changed.
+    pub fn open(path: &Path, config: &ScanConfig, policy: &MaskPolicy, rebuild: bool) -> io::Result<ScanCache> {
+        config.fingerprinter.require_stable("--cache")?;
+        let connection = Connection::open(path).map_err(|e| cache_error(path, e))?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| cache_error(path, e))?;
+        if version > SCHEMA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Cache {} has schema version {}; this luhnoxide supports up to {}",
+                        path.display(), version, SCHEMA_VERSION),
+            ));
+        }
+        connection.execute_batch(SCHEMA)
+            .and_then(|_| connection.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION)))
+            .map_err(|e| cache_error(path, e))?;
+
+        let digest = settings_digest(config, policy);
+        let stored: Option<String> = connection
+            .query_row("SELECT digest FROM settings WHERE id = 1", [], |row| row.get(0))
+            .optional()
+            .map_err(|e| cache_error(path, e))?;
+        if rebuild || stored.as_deref() != Some(digest.as_str()) {
+            if !rebuild && stored.is_some() {
+                eprintln!("Note: scan settings changed since {} was written; rebuilding it", path.display());
+            }
+            connection.execute("DELETE FROM files", [])
+                .and_then(|_| connection.execute("INSERT OR REPLACE INTO settings (id, digest) VALUES (1, ?1)", [&digest]))
+                .map_err(|e| cache_error(path, e))?;
+        }
+
+        let previous = load_entries(&connection).map_err(|e| cache_error(path, e))?;
+        Ok(ScanCache {
+            path: path.to_path_buf(),
+            connection: Mutex::new(connection),
+            previous,
+            updates: Mutex::new(Vec::new()),
+            seen: Mutex::new(HashSet::new()),
+            policy: policy.clone(),
+            discarding: config.discard_pans.is_some(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Files answered from the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    // Files scanned and stored in the cache
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // Add the stored findings of an unchanged file to the scan's results, as
+    // scanning it would have. Returns false if the file has to be scanned.
+    pub fn reuse(&self, file_path: &Path, results: &Mutex<Vec<CardMatch>>,
+                 files_with_cards: &Mutex<HashSet<String>>, skipped_files: &Mutex<Vec<String>>,
+                 rejections: &Mutex<Vec<FilterRejection>>) -> bool {
+        let path = file_path.to_string_lossy().to_string();
+        let Some(entry) = self.previous.get(&path) else {
+            return false;
+        };
+        let Ok(metadata) = fs::metadata(file_path) else {
+            return false;
+        };
+        let (size, mtime_ns) = file_state(&metadata);
+        if size != entry.size {
+            return false;
+        }
+
+        let findings: StoredFindings = match &entry.findings {
+            Some(json) => match serde_json::from_str(json) {
+                Ok(findings) => findings,
+                Err(_) => return false,
+            },
+            None => StoredFindings::default(),
+        };
+        // Stored cards are masked, so they can't stand in for full PANs
+        if !findings.cards.is_empty() && !self.discarding {
+            return false;
+        }
+
+        // Touched or copied back files are reused only if the content is the
+        // same. So are files with findings whatever their mtime: --redact and
+        // --tokenize rewrite them keeping both size and mtime.
+        if mtime_ns != entry.mtime_ns || !findings.cards.is_empty() {
+            match (&entry.content_hash, sha256_file(file_path)) {
+                (Some(stored), Ok(hash)) if *stored == hash => {
+                    if mtime_ns != entry.mtime_ns && let Ok(mut updates) = self.updates.lock() {
+                        updates.push((path.clone(), Entry { mtime_ns, ..entry.clone() }));
+                    }
+                }
+                _ => return false,
+            }
+        }
+
+        if entry.status == FileStatus::Cards && let Ok(mut files_with_cards) = files_with_cards.lock() {
+            files_with_cards.insert(path.clone());
+        }
+        if let Ok(mut results) = results.lock() {
+            results.extend(findings.cards.into_iter().map(CardMatch::from));
+        }
+        if let Ok(mut rejections) = rejections.lock() {
+            rejections.extend(findings.rejections.into_iter().map(|(card, reasons)| FilterRejection {
+                card: card.into(),
+                reasons,
+            }));
+        }
+        if entry.status == FileStatus::Skipped && let Ok(mut skipped) = skipped_files.lock() {
+            skipped.push(path.clone());
+        }
+
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.insert(path);
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    // Start the entry for a file about to be scanned, from its state before
+    // it is read
+    pub fn start_entry(&self, file: &File) -> Option<PendingEntry<'_>> {
+        let (size, mtime_ns) = file_state(&file.metadata().ok()?);
+        Some(PendingEntry {
+            cache: self,
+            size,
+            mtime_ns,
+            findings: StoredFindings::default(),
+        })
+    }
+
+    // Write this run's entries and drop those of files under `inputs` that
+    // were not seen, in one transaction
+    pub fn save(&self, inputs: &[&str]) -> io::Result<()> {
+        let updates = self.updates.lock().map(|mut updates| std::mem::take(&mut *updates)).unwrap_or_default();
+        let seen = self.seen.lock().map(|seen| seen.clone()).unwrap_or_default();
+        let stale: Vec<&String> = self.previous.keys()
+            .filter(|path| !seen.contains(*path))
+            .filter(|path| inputs.iter().any(|input| Path::new(path).starts_with(input)))
+            .collect();
+
+        let mut connection = self.connection.lock().map_err(|_| io::Error::other("Cache connection poisoned"))?;
+        let write = || -> rusqlite::Result<()> {
+            let transaction = connection.transaction()?;
+            {
+                let mut delete = transaction.prepare("DELETE FROM files WHERE path = ?1")?;
+                for path in stale {
+                    delete.execute([path])?;
+                }
+
+                let mut insert = transaction.prepare(
+                    "INSERT OR REPLACE INTO files (path, size, mtime_ns, content_hash, status, findings)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+                for (path, entry) in &updates {
+                    insert.execute(params![
+                        path,
+                        entry.size as i64,
+                        entry.mtime_ns,
+                        entry.content_hash,
+                        entry.status.as_str(),
+                        entry.findings,
+                    ])?;
+                }
+            }
+            transaction.commit()
+        };
+        write().map_err(|e| cache_error(&self.path, e))
+    }
+
+    fn store(&self, path: String, entry: Entry) {
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.insert(path.clone());
+        }
+        if let Ok(mut updates) = self.updates.lock() {
+            updates.push((path, entry));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// A file's cache entry while it is being scanned
+pub struct PendingEntry<'a> {
+    cache: &'a ScanCache,
+    size: u64,
+    mtime_ns: i64,
+    findings: StoredFindings,
+}
+
+impl PendingEntry<'_> {
+    // Keep masked copies of a line's matches and rejected candidates
+    pub fn add_line(&mut self, matches: &[CardMatch], rejections: &[FilterRejection]) {
+        if matches.is_empty() && rejections.is_empty() {
+            return;
+        }
+        let masked;
+        let (matches, rejections) = if self.cache.discarding {
+            (matches, rejections)
+        } else {
+            masked = mask_line_results(&self.cache.policy, matches, rejections);
+            (&masked.0[..], &masked.1[..])
+        };
+        self.findings.cards.extend(matches.iter().map(StoredCard::from));
+        self.findings.rejections.extend(rejections.iter()
+            .map(|rejection| (StoredCard::from(&rejection.card), rejection.reasons.clone())));
+    }
+
+    // Store the entry. Files without a content hash were skipped as binary.
+    pub fn finish(self, path: String, content_hash: Option<String>) {
+        let status = match content_hash {
+            None => FileStatus::Skipped,
+            Some(_) if !self.findings.cards.is_empty() => FileStatus::Cards,
+            Some(_) => FileStatus::Clean,
+        };
+        let findings = if self.findings.cards.is_empty() && self.findings.rejections.is_empty() {
+            None
+        } else {
+            serde_json::to_string(&self.findings).ok()
+        };
+        self.cache.store(path, Entry {
+            size: self.size,
+            mtime_ns: self.mtime_ns,
+            content_hash,
+            status,
+            findings,
+        });
+    }
+}
+
+// Reader that hashes everything read through it, when enabled
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R, enabled: bool) -> Self {
+        HashingReader { inner, hasher: enabled.then(Sha256::new) }
+    }
+
+    // Hex-encoded SHA-256 of the bytes read, if hashing was enabled
+    pub fn finish(self) -> Option<String> {
+        self.hasher.map(|hasher| hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+// Hex-encoded SHA-256 of everything stored findings depend on
+fn settings_digest(config: &ScanConfig, policy: &MaskPolicy) -> String {
+    let settings = format!(
+        "{}\n{}\n{:?}\n{:?}\n{}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        CARD_PATTERN,
+        config.filter,
+        policy,
+        config.context_chars,
+        config.fingerprinter.fingerprint(CANARY_PAN),
+    );
+    Sha256::digest(settings.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn load_entries(connection: &Connection) -> rusqlite::Result<HashMap<String, Entry>> {
+    let mut statement = connection.prepare(
+        "SELECT path, size, mtime_ns, content_hash, status, findings FROM files",
+    )?;
+    statement.query_map([], |row| {
+        let size: i64 = row.get(1)?;
+        let status: String = row.get(4)?;
+        Ok((row.get(0)?, Entry {
+            size: size as u64,
+            mtime_ns: row.get(2)?,
+            content_hash: row.get(3)?,
+            status: FileStatus::from_str(&status),
+            findings: row.get(5)?,
+        }))
+    })?.collect()
+}
+
+// Size and modification time in nanoseconds since the Unix epoch
+fn file_state(metadata: &Metadata) -> (u64, i64) {
+    let mtime = FileTime::from_last_modification_time(metadata);
+    (metadata.len(), mtime.unix_seconds() * 1_000_000_000 + i64::from(mtime.nanoseconds()))
+}
+
+fn cache_error(path: &Path, e: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("Cannot use cache {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::remediate::{redact_files, RewriteOptions};
+    use crate::{scan_file, test_scan_config};
+
+    type Shared = (
+        Arc<Mutex<Vec<CardMatch>>>,
+        Arc<Mutex<HashSet<String>>>,
+        Arc<Mutex<Vec<String>>>,
+        Arc<Mutex<Vec<FilterRejection>>>,
+    );
+
+    fn shared() -> Shared {
+        Default::default()
+    }
+
+    #[test]
+    fn reuses_masked_findings_of_unchanged_files_only() {
+        let policy = MaskPolicy::from_config("first6last4", "X").unwrap();
+        // A --no-mask run keeps full PANs; the cache must not
+        let config = test_scan_config(None);
+        let dir = tempfile::tempdir().unwrap();
+        let (cards, clean) = (dir.path().join("cards.txt"), dir.path().join("clean.txt"));
+        fs::write(&cards, "pay 4532015112830366 now\n").unwrap();
+        fs::write(&clean, "nothing here\n").unwrap();
+        let cache_path = dir.path().join("cache.db");
+        let inputs = [dir.path().to_str().unwrap()];
+
+        let cache = ScanCache::open(&cache_path, &config, &policy, false).unwrap();
+        let (results, with_cards, skipped, rejections) = shared();
+        for file in [&cards, &clean] {
+            assert!(!cache.reuse(file, &results, &with_cards, &skipped, &rejections));
+            scan_file(file, &results, &with_cards, &skipped, &rejections, &config, Some(&cache)).unwrap();
+        }
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+        cache.save(&inputs).unwrap();
+        assert!(!fs::read(&cache_path).unwrap().windows(16).any(|window| window == b"4532015112830366"));
+        let masked = results.lock().unwrap()[0].masked_copy(&policy);
+        drop(cache);
+
+        // Full PANs can't come from the cache, masked output can
+        let cache = ScanCache::open(&cache_path, &config, &policy, false).unwrap();
+        let (results, with_cards, skipped, rejections) = shared();
+        assert!(cache.reuse(&clean, &results, &with_cards, &skipped, &rejections));
+        assert!(!cache.reuse(&cards, &results, &with_cards, &skipped, &rejections));
+        drop(cache);
+
+        let config = ScanConfig { discard_pans: Some(policy.clone()), ..config };
+        let cache = ScanCache::open(&cache_path, &config, &policy, false).unwrap();
+        assert!(cache.reuse(&cards, &results, &with_cards, &skipped, &rejections));
+        let reused = results.lock().unwrap()[0].masked_copy(&policy);
+        assert_eq!((reused.full_pan.as_str(), reused.context.as_str(), reused.line_content.as_str()),
+                   (masked.full_pan.as_str(), masked.context.as_str(), masked.line_content.as_str()));
+        assert!(with_cards.lock().unwrap().contains(cards.to_str().unwrap()));
+
+        // A changed file is scanned again
+        fs::write(&clean, "nothing here either\n").unwrap();
+        assert!(!cache.reuse(&clean, &results, &with_cards, &skipped, &rejections));
+        drop(cache);
+
+        let cache = ScanCache::open(&cache_path, &config, &policy, true).unwrap();
+        assert!(!cache.reuse(&cards, &results, &with_cards, &skipped, &rejections));
+    }
+
+    #[test]
+    fn remediated_files_are_scanned_again() {
+        let policy = MaskPolicy::from_config("first6last4", "X").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cards = dir.path().join("cards.txt");
+        fs::write(&cards, "pay 4532015112830366 now\n").unwrap();
+        let cache_path = dir.path().join("cache.db");
+        let inputs = [dir.path().to_str().unwrap()];
+
+        // A --redact run caches the file's findings, then rewrites it with
+        // the same size and mtime
+        let config = test_scan_config(None);
+        let cache = ScanCache::open(&cache_path, &config, &policy, false).unwrap();
+        let (results, with_cards, skipped, rejections) = shared();
+        scan_file(&cards, &results, &with_cards, &skipped, &rejections, &config, Some(&cache)).unwrap();
+        cache.save(&inputs).unwrap();
+        drop(cache);
+        let before = fs::metadata(&cards).unwrap();
+        let options = RewriteOptions { dry_run: false, backup_dir: None, show_full: false };
+        let stats = redact_files(&results.lock().unwrap(), &options, &policy).unwrap();
+        assert_eq!(stats.pans_replaced, 1);
+        let after = fs::metadata(&cards).unwrap();
+        assert_eq!(file_state(&before), file_state(&after));
+
+        let config = test_scan_config(Some(policy.clone()));
+        let cache = ScanCache::open(&cache_path, &config, &policy, false).unwrap();
+        let (results, with_cards, skipped, rejections) = shared();
+        assert!(!cache.reuse(&cards, &results, &with_cards, &skipped, &rejections));
+        scan_file(&cards, &results, &with_cards, &skipped, &rejections, &config, Some(&cache)).unwrap();
+        assert!(results.lock().unwrap().is_empty());
+        cache.save(&inputs).unwrap();
+        drop(cache);
+
+        // Once rescanned, the clean file is reused again
+        let cache = ScanCache::open(&cache_path, &config, &policy, false).unwrap();
+        let (results, with_cards, skipped, rejections) = shared();
+        assert!(cache.reuse(&cards, &results, &with_cards, &skipped, &rejections));
+        assert!(results.lock().unwrap().is_empty() && with_cards.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,364 @@
+// SQLite history of scans
+//
+// `--db FILE` appends each run to a SQLite database so results can be
+// queried across scans instead of living in one output file per run. Only
+// masked PANs, context and fingerprints are stored; with a fixed fingerprint
+// key the same card has the same fingerprint in every scan. The schema below
+// is documented in the README and versioned with `PRAGMA user_version`.
+// Stored scans can be read back, e.g. by `luhnoxide diff`.
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+use crate::masking::MaskPolicy;
+use crate::trend::TrendPoint;
+use crate::{CardMatch, ScanSummary};
+
+// First bytes of every SQLite database file
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+pub const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+-- One row per run
+CREATE TABLE IF NOT EXISTS scans (
+    id                  INTEGER PRIMARY KEY,
+    scan_date           TEXT NOT NULL,      -- local time, YYYY-MM-DD HH:MM:SS
+    host                TEXT NOT NULL,
+    inputs              TEXT NOT NULL,      -- input paths as given to -i
+    duration_seconds    REAL NOT NULL,
+    files_scanned       INTEGER NOT NULL,
+    directories_scanned INTEGER NOT NULL,
+    files_with_cards    INTEGER NOT NULL,
+    clean_files         INTEGER NOT NULL,
+    skipped_files       INTEGER NOT NULL,
+    cards_found         INTEGER NOT NULL,
+    unique_cards        INTEGER NOT NULL,
+    size_scanned_mb     REAL NOT NULL
+);
+
+-- Every file a scan looked at
+CREATE TABLE IF NOT EXISTS files (
+    scan_id      INTEGER NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+    path         TEXT NOT NULL,
+    status       TEXT NOT NULL,             -- cards, clean or skipped
+    risk         TEXT,                      -- high, medium or low; NULL unless status is cards
+    findings     INTEGER NOT NULL,
+    unique_cards INTEGER NOT NULL,
+    PRIMARY KEY (scan_id, path)
+);
+
+-- Every card number found, masked
+CREATE TABLE IF NOT EXISTS findings (
+    id           INTEGER PRIMARY KEY,
+    scan_id      INTEGER NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+    path         TEXT NOT NULL,
+    line         INTEGER NOT NULL,
+    column_start INTEGER NOT NULL,
+    column_end   INTEGER NOT NULL,
+    byte_offset  INTEGER NOT NULL,
+    brand        TEXT NOT NULL,
+    masked_pan   TEXT NOT NULL,
+    bin          TEXT NOT NULL,
+    last_four    TEXT NOT NULL,
+    pan_length   INTEGER NOT NULL,
+    fingerprint  TEXT NOT NULL,
+    context      TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS files_path ON files (path);
+CREATE INDEX IF NOT EXISTS findings_scan_path ON findings (scan_id, path);
+CREATE INDEX IF NOT EXISTS findings_fingerprint ON findings (fingerprint);
+";
+
+pub struct Database {
+    connection: Connection,
+}
+
+// A scan read back from the database
+pub struct StoredScan {
+    pub id: i64,
+    pub scan_date: String,
+    pub findings: Vec<CardMatch>,
+    // Risk tier of each file with findings
+    pub risk_of: HashMap<String, String>,
+}
+
+impl Database {
+    // Open or create the database, creating the tables if needed
+    pub fn open(path: &Path) -> io::Result<Database> {
+        let connection = Connection::open(path).map_err(|e| database_error(path, e))?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| database_error(path, e))?;
+        if version > SCHEMA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Database {} has schema version {}; this luhnoxide supports up to {}",
+                        path.display(), version, SCHEMA_VERSION),
+            ));
+        }
+
+        connection.execute_batch("PRAGMA foreign_keys = ON")
+            .and_then(|_| connection.execute_batch(SCHEMA))
+            .and_then(|_| connection.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION)))
+            .map_err(|e| database_error(path, e))?;
+        Ok(Database { connection })
+    }
+
+    // Open an existing database without changing it
+    pub fn open_read_only(path: &Path) -> io::Result<Database> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| database_error(path, e))?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| database_error(path, e))?;
+        if version == 0 || version > SCHEMA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a luhnoxide database this version can read (schema version {})",
+                        path.display(), version),
+            ));
+        }
+        Ok(Database { connection })
+    }
+
+    // Ids of the stored scans, oldest first
+    pub fn scan_ids(&self) -> io::Result<Vec<i64>> {
+        let mut statement = self.connection.prepare("SELECT id FROM scans ORDER BY id")
+            .map_err(io::Error::other)?;
+        let ids = statement.query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<i64>>>())
+            .map_err(io::Error::other)?;
+        Ok(ids)
+    }
+
+    // Headline figures of the stored scans, oldest first: all of them, or
+    // those of the given inputs, limited to the last `last`
+    pub fn scan_history(&self, inputs: Option<&str>, last: Option<usize>) -> io::Result<Vec<TrendPoint>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, scan_date, files_scanned, files_with_cards, cards_found, unique_cards, clean_files
+             FROM scans WHERE ?1 IS NULL OR inputs = ?1 ORDER BY id",
+        ).map_err(io::Error::other)?;
+        let mut points = statement.query_map([inputs], |row| {
+            let files_scanned: usize = row.get(2)?;
+            let clean_files: usize = row.get(6)?;
+            Ok(TrendPoint {
+                scan_id: Some(row.get(0)?),
+                scan_date: row.get(1)?,
+                files_scanned,
+                files_with_cards: row.get(3)?,
+                cards_found: row.get(4)?,
+                unique_cards: row.get(5)?,
+                clean_percentage: if files_scanned > 0 { clean_files as f64 / files_scanned as f64 * 100.0 } else { 0.0 },
+            })
+        }).and_then(|rows| rows.collect::<rusqlite::Result<Vec<TrendPoint>>>()).map_err(io::Error::other)?;
+
+        if let Some(last) = last {
+            points.drain(..points.len().saturating_sub(last));
+        }
+        Ok(points)
+    }
+
+    // Read a stored scan's findings back as (masked) matches
+    pub fn load_scan(&self, id: i64) -> io::Result<StoredScan> {
+        self.select_scan(id)
+            .map_err(io::Error::other)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No scan {} in the database", id)))
+    }
+
+    fn select_scan(&self, id: i64) -> rusqlite::Result<Option<StoredScan>> {
+        let scan_date: Option<String> = self.connection
+            .query_row("SELECT scan_date FROM scans WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        let Some(scan_date) = scan_date else {
+            return Ok(None);
+        };
+
+        let mut statement = self.connection.prepare(
+            "SELECT path, line, column_start, column_end, byte_offset, brand, masked_pan, bin, last_four,
+                    pan_length, fingerprint, context
+             FROM findings WHERE scan_id = ?1 ORDER BY id",
+        )?;
+        let findings = statement.query_map([id], |row| {
+            Ok(CardMatch {
+                file_path: row.get(0)?,
+                line_number: row.get(1)?,
+                column_start: row.get(2)?,
+                column_end: row.get(3)?,
+                byte_offset: row.get(4)?,
+                brand: row.get(5)?,
+                full_pan: row.get(6)?,
+                bin: row.get(7)?,
+                last_four: row.get(8)?,
+                length: row.get(9)?,
+                fingerprint: row.get(10)?,
+                context: row.get(11)?,
+                line_content: String::new(),
+                match_start: 0,
+                match_end: 0,
+                line_spans: Vec::new(),
+                context_span: (0, 0),
+            })
+        })?.collect::<rusqlite::Result<Vec<CardMatch>>>()?;
+
+        let mut statement = self.connection.prepare(
+            "SELECT path, risk FROM files WHERE scan_id = ?1 AND risk IS NOT NULL",
+        )?;
+        let risk_of = statement.query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<HashMap<String, String>>>()?;
+
+        Ok(Some(StoredScan { id, scan_date, findings, risk_of }))
+    }
+
+    // Store a scan in one transaction and return its id. Findings are masked
+    // with `policy` whatever the output options.
+    pub fn record_scan(&mut self, summary: &ScanSummary, results: &[CardMatch], policy: &MaskPolicy,
+                       inputs: &str) -> io::Result<i64> {
+        self.insert_scan(summary, results, policy, inputs).map_err(io::Error::other)
+    }
+
+    fn insert_scan(&mut self, summary: &ScanSummary, results: &[CardMatch], policy: &MaskPolicy,
+                   inputs: &str) -> rusqlite::Result<i64> {
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute(
+            "INSERT INTO scans (scan_date, host, inputs, duration_seconds, files_scanned, directories_scanned,
+                                files_with_cards, clean_files, skipped_files, cards_found, unique_cards, size_scanned_mb)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                summary.scan_date,
+                gethostname::gethostname().to_string_lossy(),
+                inputs,
+                summary.scan_duration_seconds,
+                summary.total_files_scanned,
+                summary.total_directories_scanned,
+                summary.total_files_with_cards,
+                summary.clean_files,
+                summary.skipped_files.len(),
+                summary.total_cards_found,
+                summary.unique_cards_found,
+                summary.total_size_scanned_mb,
+            ],
+        )?;
+        let scan_id = transaction.last_insert_rowid();
+
+        let risk_of: HashMap<&str, &str> = summary.files_by_risk.iter()
+            .flat_map(|(level, files)| files.iter().map(move |file| (file.as_str(), level.as_str())))
+            .collect();
+        let mut counts: HashMap<&str, (usize, HashSet<&str>)> = HashMap::new();
+        for card in results {
+            let entry = counts.entry(&card.file_path).or_default();
+            entry.0 += 1;
+            entry.1.insert(&card.fingerprint);
+        }
+        let skipped: HashSet<&str> = summary.skipped_files.iter().map(String::as_str).collect();
+
+        {
+            let mut insert_file = transaction.prepare(
+                "INSERT OR IGNORE INTO files (scan_id, path, status, risk, findings, unique_cards)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for file in &summary.all_scanned_files {
+                let (count, unique) = counts.get(file.as_str()).map_or((0, 0), |(count, cards)| (*count, cards.len()));
+                let (status, risk) = if count > 0 {
+                    ("cards", Some(risk_of.get(file.as_str()).copied().unwrap_or("low")))
+                } else if skipped.contains(file.as_str()) {
+                    ("skipped", None)
+                } else {
+                    ("clean", None)
+                };
+                insert_file.execute(params![scan_id, file, status, risk, count, unique])?;
+            }
+
+            let mut insert_finding = transaction.prepare(
+                "INSERT INTO findings (scan_id, path, line, column_start, column_end, byte_offset, brand,
+                                       masked_pan, bin, last_four, pan_length, fingerprint, context)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?;
+            for card in results {
+                let card = card.masked_copy(policy);
+                insert_finding.execute(params![
+                    scan_id,
+                    card.file_path,
+                    card.line_number,
+                    card.column_start,
+                    card.column_end,
+                    card.byte_offset,
+                    card.brand,
+                    card.full_pan,
+                    card.bin,
+                    card.last_four,
+                    card.length,
+                    card.fingerprint,
+                    card.context,
+                ])?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(scan_id)
+    }
+}
+
+// True if the file is a SQLite database, judging by its header
+pub fn is_database(path: &Path) -> io::Result<bool> {
+    let mut header = [0u8; 16];
+    let mut file = File::open(path)?;
+    let read = file.read(&mut header)?;
+    Ok(read == header.len() && header == SQLITE_HEADER)
+}
+
+fn database_error(path: &Path, e: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("Cannot open database {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_report;
+
+    #[test]
+    fn records_masked_scans_and_answers_history_queries() {
+        // Stored findings are masked with the database's policy, not the output's
+        let policy = MaskPolicy::from_config("last4", "#").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+
+        // a.txt has a card in all three scans, b.txt only in the first
+        let scans = [
+            vec![("a.txt", "4532015112830366"), ("b.txt", "5555555555554444")],
+            vec![("a.txt", "4532015112830366"), ("b.txt", "clean")],
+            vec![("a.txt", "x 4532015112830366"), ("b.txt", "clean")],
+        ];
+        for files in &scans {
+            let (summary, results) = test_report(files);
+            Database::open(&path).unwrap().record_scan(&summary, &results, &policy, "/data").unwrap();
+        }
+
+        let connection = Connection::open(&path).unwrap();
+        let persistent: Vec<String> = connection.prepare(
+            "SELECT path FROM files
+             WHERE status = 'cards' AND scan_id IN (SELECT id FROM scans ORDER BY id DESC LIMIT 3)
+             GROUP BY path HAVING COUNT(*) = 3",
+        ).unwrap().query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(persistent, ["a.txt"]);
+
+        let pans: Vec<String> = connection.prepare("SELECT masked_pan || ' ' || context FROM findings").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(pans.len(), 4);
+        assert!(pans.iter().all(|pan| pan.starts_with("######XXXXXX") && !pan.contains("453201")), "{:?}", pans);
+
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+
+        let database = Database::open_read_only(&path).unwrap();
+        let history = database.scan_history(Some("/data"), Some(2)).unwrap();
+        let figures: Vec<(Option<i64>, usize, f64)> = history.iter()
+            .map(|point| (point.scan_id, point.files_with_cards, point.clean_percentage))
+            .collect();
+        assert_eq!(figures, [(Some(2), 1, 50.0), (Some(3), 1, 50.0)]);
+        assert!(database.scan_history(Some("/elsewhere"), None).unwrap().is_empty());
+    }
+}
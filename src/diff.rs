@@ -0,0 +1,635 @@
+// Comparison of two scans
+//
+// `luhnoxide diff OLD NEW` loads two sets of results (JSON or NDJSON output
+// files, or scans stored with --db) and matches their findings by card
+// fingerprint and location. Findings are first paired on the same file, line
+// and column, then on the same file at any position, so a card that only
+// moved within a file counts as unchanged. Whatever is left over was added
+// (only in NEW) or resolved (only in OLD). Output is always masked, whatever
+// the inputs contain.
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::ArgMatches;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::db::{self, Database};
+use crate::encrypt::ReportWriter;
+use crate::masking::MaskPolicy;
+use crate::pdf::{self, PdfReport};
+use crate::{ecs, html, junit, markdown, siem, xlsx, CardMatch, OutputFormat};
+
+// Digit runs that could be a PAN, with the separators the scanner accepts
+const PAN_LIKE: &str = r"[0-9](?:[0-9.\s-]){11,18}[0-9]";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Added,
+    Resolved,
+    Unchanged,
+}
+
+impl Status {
+    pub const ALL: [Status; 3] = [Status::Added, Status::Resolved, Status::Unchanged];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Added => "added",
+            Status::Resolved => "resolved",
+            Status::Unchanged => "unchanged",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Status::Added => "Added",
+            Status::Resolved => "Resolved",
+            Status::Unchanged => "Unchanged",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Status::Added => "Findings only in the new scan:",
+            Status::Resolved => "Findings only in the old scan, no longer present:",
+            Status::Unchanged => "Findings present in both scans:",
+        }
+    }
+}
+
+// Findings loaded from one side of the comparison
+pub struct ScanResults {
+    // Where the results came from, for the report header
+    pub label: String,
+    pub findings: Vec<CardMatch>,
+    // Risk tier of each file with findings, where the source records it
+    pub risk_of: HashMap<String, String>,
+}
+
+pub struct Change {
+    pub status: Status,
+    // The finding as in the new scan, or as in the old one if resolved
+    pub card: CardMatch,
+    // Line in the old scan of an unchanged finding that moved
+    pub previous_line: Option<usize>,
+}
+
+// Added, resolved and unchanged findings per file
+#[derive(Default)]
+pub struct FileChanges {
+    pub added: usize,
+    pub resolved: usize,
+    pub unchanged: usize,
+}
+
+pub struct ScanDiff {
+    pub old: String,
+    pub new: String,
+    // Every finding of either scan, in file and line order
+    pub changes: Vec<Change>,
+    // Risk tier of each file, from the newest scan it had findings in
+    pub risk_of: HashMap<String, String>,
+}
+
+impl ScanDiff {
+    pub fn count(&self, status: Status) -> usize {
+        self.changes.iter().filter(|change| change.status == status).count()
+    }
+
+    pub fn with_status(&self, status: Status) -> impl Iterator<Item = &Change> {
+        self.changes.iter().filter(move |change| change.status == status)
+    }
+
+    pub fn risk(&self, file: &str) -> &str {
+        self.risk_of.get(file).map_or("low", String::as_str)
+    }
+
+    // Every file with a finding in either scan, in path order
+    pub fn files(&self) -> Vec<(&str, FileChanges)> {
+        let mut files: Vec<(&str, FileChanges)> = Vec::new();
+        for change in &self.changes {
+            let path = change.card.file_path.as_str();
+            if files.last().is_none_or(|(last, _)| *last != path) {
+                files.push((path, FileChanges::default()));
+            }
+            if let Some((_, counts)) = files.last_mut() {
+                match change.status {
+                    Status::Added => counts.added += 1,
+                    Status::Resolved => counts.resolved += 1,
+                    Status::Unchanged => counts.unchanged += 1,
+                }
+            }
+        }
+        files
+    }
+
+    // Headline figures, as (label, value)
+    pub fn key_metrics(&self) -> Vec<(&'static str, String)> {
+        let files = self.files();
+        vec![
+            ("Added Findings", self.count(Status::Added).to_string()),
+            ("Resolved Findings", self.count(Status::Resolved).to_string()),
+            ("Unchanged Findings", self.count(Status::Unchanged).to_string()),
+            ("Files Now Clean", files.iter().filter(|(_, c)| c.added + c.unchanged == 0).count().to_string()),
+            ("Files Still Containing Cards", files.iter().filter(|(_, c)| c.added + c.unchanged > 0).count().to_string()),
+        ]
+    }
+
+    fn to_pdf(&self) -> Vec<u8> {
+        let mut report = PdfReport::new("Luhnoxide Scan Comparison", &[
+            ("Old Scan", self.old.clone()),
+            ("New Scan", self.new.clone()),
+        ]);
+
+        report.heading("Key Metrics");
+        report.stats(&self.key_metrics());
+
+        report.heading("Files");
+        let file_rows: Vec<Vec<String>> = self.files().into_iter()
+            .map(|(file, counts)| vec![
+                file.to_string(),
+                counts.added.to_string(),
+                counts.resolved.to_string(),
+                counts.unchanged.to_string(),
+            ])
+            .collect();
+        report.table(&["File", "Added", "Resolved", "Unchanged"], &[0.58, 0.14, 0.14, 0.14], &file_rows);
+
+        let colors = [pdf::RISK_HIGH, pdf::RISK_LOW, pdf::RISK_MEDIUM];
+        for (status, color) in Status::ALL.into_iter().zip(colors) {
+            let rows: Vec<Vec<String>> = self.with_status(status)
+                .map(|change| vec![
+                    format!("{}:{}", change.card.file_path, change.card.line_number),
+                    change.card.brand.clone(),
+                    change.card.full_pan.clone(),
+                    change.card.fingerprint.clone(),
+                ])
+                .collect();
+            if !rows.is_empty() {
+                report.subheading(&format!("{} Findings", status.label()), color);
+                report.paragraph(status.description());
+                report.table(&["Location", "Brand", "Masked PAN", "Fingerprint"], &[0.4, 0.14, 0.18, 0.28], &rows);
+            }
+        }
+
+        report.finish()
+    }
+}
+
+// Run the diff subcommand
+pub fn run(matches: &ArgMatches) -> io::Result<()> {
+    let format_str = matches.value_of("format").unwrap_or("text");
+    let format = OutputFormat::from_str(format_str);
+    let output = matches.value_of("output");
+    if output.is_none() && matches!(format, OutputFormat::Html | OutputFormat::Pdf | OutputFormat::Xlsx) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "HTML/PDF/XLSX format requires an output file to be specified with -o/--output",
+        ));
+    }
+
+    let policy = MaskPolicy::from_config(
+        matches.value_of("mask-policy").unwrap_or("first6last4"),
+        matches.value_of("mask-char").unwrap_or("X"),
+    )?;
+    let old_path = Path::new(matches.value_of("old").unwrap());
+    let new_path = Path::new(matches.value_of("new").unwrap());
+    let old_scan = parse_scan_id(matches.value_of("old-scan"), "--old-scan")?;
+    let new_scan = parse_scan_id(matches.value_of("new-scan"), "--new-scan")?;
+
+    // Comparing a database with itself compares its last two scans
+    let (old, new) = if old_path == new_path && old_scan.is_none() && new_scan.is_none() && db::is_database(old_path)? {
+        let ids = Database::open_read_only(old_path)?.scan_ids()?;
+        if ids.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} holds fewer than two scans to compare", old_path.display()),
+            ));
+        }
+        (load(old_path, Some(ids[ids.len() - 2]))?, load(new_path, Some(ids[ids.len() - 1]))?)
+    } else {
+        (load(old_path, old_scan)?, load(new_path, new_scan)?)
+    };
+
+    let both_found = !old.findings.is_empty() && !new.findings.is_empty();
+    let diff = compare(old, new, &policy);
+    if both_found && diff.count(Status::Unchanged) == 0 {
+        eprintln!("Note: the scans have no findings in common; if that is unexpected, check that both \
+                   were made with the same fingerprint key");
+    }
+
+    let report = render(&format, &diff)?;
+    match output {
+        Some(path) => {
+            let mut writer = ReportWriter::create(Path::new(path), &[])?;
+            writer.write_all(&report)?;
+            writer.finish()?;
+            println!("Diff written to {} in {} format: {} added, {} resolved, {} unchanged",
+                     path, format_str, diff.count(Status::Added), diff.count(Status::Resolved),
+                     diff.count(Status::Unchanged));
+        }
+        None => io::stdout().write_all(&report)?,
+    }
+    Ok(())
+}
+
+fn parse_scan_id(value: Option<&str>, flag: &str) -> io::Result<Option<i64>> {
+    value.map(|id| id.parse::<i64>().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} must be a scan id", flag))
+    })).transpose()
+}
+
+// Load results from a SQLite database (scan `scan_id`, or the latest), a JSON
+// array of findings or NDJSON, either one finding per line or ECS documents
+pub fn load(path: &Path, scan_id: Option<i64>) -> io::Result<ScanResults> {
+    let context = |e: io::Error| io::Error::new(e.kind(), format!("Cannot load {}: {}", path.display(), e));
+
+    if db::is_database(path).map_err(context)? {
+        let database = Database::open_read_only(path)?;
+        let id = match scan_id {
+            Some(id) => id,
+            None => *database.scan_ids()?.last().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("{} holds no scans", path.display()))
+            })?,
+        };
+        let scan = database.load_scan(id).map_err(context)?;
+        return Ok(ScanResults {
+            label: format!("{} scan {} ({})", path.display(), scan.id, scan.scan_date),
+            findings: scan.findings,
+            risk_of: scan.risk_of,
+        });
+    }
+    if scan_id.is_some() {
+        return Err(context(io::Error::new(io::ErrorKind::InvalidInput, "scan ids only apply to --db databases")));
+    }
+
+    let text = fs::read_to_string(path).map_err(context)?;
+    let (findings, risk_of) = if text.trim_start().starts_with('[') {
+        let findings: Vec<CardMatch> = serde_json::from_str(&text)
+            .map_err(|e| context(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        (findings, HashMap::new())
+    } else {
+        parse_ndjson(&text).map_err(context)?
+    };
+    Ok(ScanResults { label: path.display().to_string(), findings, risk_of })
+}
+
+fn parse_ndjson(text: &str) -> io::Result<(Vec<CardMatch>, HashMap<String, String>)> {
+    let mut findings = Vec::new();
+    let mut risk_of = HashMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", index + 1, e));
+        let document: Value = serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+
+        match document.get("luhnoxide") {
+            // ECS: findings carry luhnoxide.brand, the summary document is skipped
+            Some(fields) if fields.get("brand").is_some() => {
+                let card = ecs_finding(&document, fields).ok_or_else(|| invalid("incomplete ECS finding".to_string()))?;
+                if let Some(risk) = fields.get("risk").and_then(Value::as_str) {
+                    risk_of.insert(card.file_path.clone(), risk.to_string());
+                }
+                findings.push(card);
+            }
+            Some(_) => {}
+            None => findings.push(serde_json::from_value(document).map_err(|e| invalid(e.to_string()))?),
+        }
+    }
+    Ok((findings, risk_of))
+}
+
+fn ecs_finding(document: &Value, fields: &Value) -> Option<CardMatch> {
+    let text = |value: &Value, key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+    let number = |key: &str| fields.get(key).and_then(Value::as_u64);
+
+    Some(CardMatch {
+        brand: text(fields, "brand")?,
+        full_pan: text(fields, "pan")?,
+        bin: text(fields, "bin").unwrap_or_default(),
+        last_four: text(fields, "last_four").unwrap_or_default(),
+        length: number("pan_length").unwrap_or_default() as usize,
+        file_path: text(document.get("file")?, "path")?,
+        line_number: number("line")? as usize,
+        line_content: String::new(),
+        fingerprint: text(fields, "fingerprint").unwrap_or_default(),
+        match_start: 0,
+        match_end: 0,
+        line_spans: Vec::new(),
+        byte_offset: number("byte_offset").unwrap_or_default(),
+        column_start: number("column_start").unwrap_or_default() as usize,
+        column_end: number("column_end").unwrap_or_default() as usize,
+        context: text(fields, "context").unwrap_or_default(),
+        context_span: (0, 0),
+    })
+}
+
+// Mask a loaded finding. Results written with --no-mask hold full PANs, and
+// their recorded spans are not saved, so every PAN-like digit run in the text
+// is masked instead. Already-masked PANs come out unchanged.
+fn mask(card: &CardMatch, policy: &MaskPolicy, pan_like: &Regex) -> CardMatch {
+    let mask_text = |text: &str| {
+        let spans: Vec<(usize, usize)> = pan_like.find_iter(text).map(|found| (found.start(), found.end())).collect();
+        policy.mask_spans(text, &spans)
+    };
+
+    let mut masked = card.clone();
+    masked.full_pan = policy.mask_formatted(&card.full_pan);
+    masked.bin = policy.visible_bin(&card.full_pan);
+    masked.last_four = policy.visible_last_four(&card.full_pan);
+    masked.line_content = mask_text(&card.line_content);
+    masked.context = mask_text(&card.context);
+    masked
+}
+
+// Cards are told apart by fingerprint, or by brand and masked PAN in results
+// from versions without fingerprints
+fn identity(card: &CardMatch) -> String {
+    if card.fingerprint.is_empty() {
+        format!("{}:{}", card.brand, card.full_pan)
+    } else {
+        card.fingerprint.clone()
+    }
+}
+
+pub fn compare(old: ScanResults, new: ScanResults, policy: &MaskPolicy) -> ScanDiff {
+    let pan_like = Regex::new(PAN_LIKE).expect("valid pattern");
+    let sorted = |findings: &[CardMatch]| {
+        let mut cards: Vec<CardMatch> = findings.iter().map(|card| mask(card, policy, &pan_like)).collect();
+        cards.sort_by(|a, b| {
+            (&a.file_path, a.line_number, a.column_start).cmp(&(&b.file_path, b.line_number, b.column_start))
+        });
+        cards
+    };
+    let old_cards = sorted(&old.findings);
+    let new_cards = sorted(&new.findings);
+
+    // Old findings by exact location, and by file alone
+    let mut at_location: HashMap<(String, &str, usize, usize), Vec<usize>> = HashMap::new();
+    let mut in_file: HashMap<(String, &str), Vec<usize>> = HashMap::new();
+    for (index, card) in old_cards.iter().enumerate() {
+        at_location.entry((identity(card), &card.file_path, card.line_number, card.column_start))
+            .or_default().push(index);
+        in_file.entry((identity(card), &card.file_path)).or_default().push(index);
+    }
+
+    let mut matched = vec![false; old_cards.len()];
+    let mut pairs: Vec<Option<usize>> = vec![None; new_cards.len()];
+    for (index, card) in new_cards.iter().enumerate() {
+        let key = (identity(card), card.file_path.as_str(), card.line_number, card.column_start);
+        if let Some(&found) = at_location.get(&key).and_then(|candidates| candidates.iter().find(|&&i| !matched[i])) {
+            matched[found] = true;
+            pairs[index] = Some(found);
+        }
+    }
+    for (index, card) in new_cards.iter().enumerate() {
+        if pairs[index].is_some() {
+            continue;
+        }
+        let key = (identity(card), card.file_path.as_str());
+        if let Some(&found) = in_file.get(&key).and_then(|candidates| candidates.iter().find(|&&i| !matched[i])) {
+            matched[found] = true;
+            pairs[index] = Some(found);
+        }
+    }
+
+    let mut changes: Vec<Change> = Vec::new();
+    for (card, pair) in new_cards.iter().zip(&pairs) {
+        let (status, previous_line) = match pair {
+            Some(found) => {
+                let line = old_cards[*found].line_number;
+                (Status::Unchanged, (line != card.line_number).then_some(line))
+            }
+            None => (Status::Added, None),
+        };
+        changes.push(Change { status, card: card.clone(), previous_line });
+    }
+    for (card, _) in old_cards.iter().zip(&matched).filter(|(_, matched)| !**matched) {
+        changes.push(Change { status: Status::Resolved, card: card.clone(), previous_line: None });
+    }
+    changes.sort_by(|a, b| {
+        (&a.card.file_path, a.card.line_number, a.card.column_start)
+            .cmp(&(&b.card.file_path, b.card.line_number, b.card.column_start))
+    });
+
+    let mut risk_of = old.risk_of;
+    risk_of.extend(new.risk_of);
+    ScanDiff { old: old.label, new: new.label, changes, risk_of }
+}
+
+fn render(format: &OutputFormat, diff: &ScanDiff) -> io::Result<Vec<u8>> {
+    Ok(match format {
+        OutputFormat::Text => text(diff).into_bytes(),
+        OutputFormat::Json => {
+            let mut json = serde_json::to_vec_pretty(&json(diff))?;
+            json.push(b'\n');
+            json
+        }
+        OutputFormat::Csv => csv(diff)?,
+        OutputFormat::Html => html::render_diff(diff)?.into_bytes(),
+        OutputFormat::Pdf => diff.to_pdf(),
+        OutputFormat::Markdown => markdown::render_diff(diff).into_bytes(),
+        OutputFormat::Xlsx => xlsx::render_diff(diff)?,
+        OutputFormat::Junit => junit::render_diff(diff).into_bytes(),
+        OutputFormat::Cef | OutputFormat::Leef => {
+            let event_format = if *format == OutputFormat::Cef { siem::EventFormat::Cef } else { siem::EventFormat::Leef };
+            let mut events = String::new();
+            for event in siem::diff_events(event_format, diff) {
+                events.push_str(&event.message);
+                events.push('\n');
+            }
+            events.into_bytes()
+        }
+        OutputFormat::Ecs => {
+            let mut ndjson = Vec::new();
+            ecs::write_diff_ndjson(&mut ndjson, diff)?;
+            ndjson
+        }
+    })
+}
+
+fn text(diff: &ScanDiff) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Old: {}", diff.old);
+    let _ = writeln!(out, "New: {}\n", diff.new);
+    for (label, value) in diff.key_metrics() {
+        let _ = writeln!(out, "{}: {}", label, value);
+    }
+
+    for status in Status::ALL {
+        let _ = writeln!(out, "\n=== {} ({}) ===", status.label().to_uppercase(), diff.count(status));
+        for change in diff.with_status(status) {
+            let card = &change.card;
+            let _ = write!(out, "{}:{}:{}  {}  {}  {}", card.file_path, card.line_number, card.column_start,
+                           card.brand, card.full_pan, card.fingerprint);
+            if let Some(line) = change.previous_line {
+                let _ = write!(out, "  (moved from line {})", line);
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[derive(Serialize)]
+struct JsonChange<'a> {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_line: Option<usize>,
+    risk: &'a str,
+    #[serde(flatten)]
+    card: &'a CardMatch,
+}
+
+fn json(diff: &ScanDiff) -> Value {
+    let changes: Vec<JsonChange> = diff.changes.iter()
+        .map(|change| JsonChange {
+            status: change.status,
+            previous_line: change.previous_line,
+            risk: diff.risk(&change.card.file_path),
+            card: &change.card,
+        })
+        .collect();
+    serde_json::json!({
+        "old": diff.old,
+        "new": diff.new,
+        "counts": {
+            "added": diff.count(Status::Added),
+            "resolved": diff.count(Status::Resolved),
+            "unchanged": diff.count(Status::Unchanged),
+        },
+        "changes": changes,
+    })
+}
+
+fn csv(diff: &ScanDiff) -> io::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Status", "Previous Line", "Risk", "Brand", "PAN Length", "BIN", "Last Four", "Masked PAN",
+        "File Path", "Line Number", "Column Start", "Column End", "Byte Offset", "Context", "Fingerprint",
+    ])?;
+    for change in &diff.changes {
+        let card = &change.card;
+        writer.write_record([
+            change.status.as_str(),
+            &change.previous_line.map(|line| line.to_string()).unwrap_or_default(),
+            diff.risk(&card.file_path),
+            &card.brand,
+            &card.length.to_string(),
+            &card.bin,
+            &card.last_four,
+            &card.full_pan,
+            &card.file_path,
+            &card.line_number.to_string(),
+            &card.column_start.to_string(),
+            &card.column_end.to_string(),
+            &card.byte_offset.to_string(),
+            &card.context,
+            &card.fingerprint,
+        ])?;
+    }
+    writer.into_inner().map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(file: &str, line: usize, pan: &str, fingerprint: &str) -> CardMatch {
+        CardMatch {
+            brand: "Visa".to_string(),
+            full_pan: pan.to_string(),
+            bin: pan[..6].to_string(),
+            last_four: pan[pan.len() - 4..].to_string(),
+            length: pan.len(),
+            file_path: file.to_string(),
+            line_number: line,
+            line_content: format!("card {}", pan),
+            fingerprint: fingerprint.to_string(),
+            match_start: 5,
+            match_end: 5 + pan.len(),
+            line_spans: Vec::new(),
+            byte_offset: 0,
+            column_start: 6,
+            column_end: 6 + pan.len(),
+            context: format!("card {}", pan),
+            context_span: (0, 0),
+        }
+    }
+
+    fn results(findings: Vec<CardMatch>) -> ScanResults {
+        ScanResults { label: "scan".to_string(), findings, risk_of: HashMap::new() }
+    }
+
+    #[test]
+    fn pairs_findings_by_fingerprint_and_location() {
+        let policy = MaskPolicy::from_config("first6last4", "X").unwrap();
+        let old = results(vec![
+            card("a.txt", 1, "4532015112830366", "f1"),
+            card("a.txt", 9, "4556737586899855", "f2"),
+            card("b.txt", 2, "4916338506082832", "f3"),
+        ]);
+        let new = results(vec![
+            card("a.txt", 1, "4532015112830366", "f1"),
+            // Moved within the file
+            card("a.txt", 12, "4556737586899855", "f2"),
+            // Same card, new file
+            card("c.txt", 2, "4916338506082832", "f3"),
+        ]);
+
+        let diff = compare(old, new, &policy);
+        let summary: Vec<(Status, &str, usize, Option<usize>)> = diff.changes.iter()
+            .map(|change| (change.status, change.card.file_path.as_str(), change.card.line_number, change.previous_line))
+            .collect();
+        assert_eq!(summary, [
+            (Status::Unchanged, "a.txt", 1, None),
+            (Status::Unchanged, "a.txt", 12, Some(9)),
+            (Status::Resolved, "b.txt", 2, None),
+            (Status::Added, "c.txt", 2, None),
+        ]);
+
+        // Unmasked inputs come out masked
+        let digits = Regex::new(r"\d{13}").unwrap();
+        assert!(diff.changes.iter().all(|change| {
+            change.card.full_pan.contains("XXXXXX")
+                && !digits.is_match(&change.card.context)
+                && !digits.is_match(&change.card.line_content)
+        }));
+    }
+
+    #[test]
+    fn reads_json_ndjson_and_ecs_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let json_path = dir.path().join("old.json");
+        let ndjson_path = dir.path().join("new.ndjson");
+        let ecs_path = dir.path().join("new.ecs.ndjson");
+
+        let finding = card("a.txt", 3, "4532015112830366", "f1");
+        fs::write(&json_path, serde_json::to_string_pretty(&vec![finding.clone()]).unwrap()).unwrap();
+        fs::write(&ndjson_path, format!("{}\n\n", serde_json::to_string(&finding).unwrap())).unwrap();
+        fs::write(&ecs_path, concat!(
+            r#"{"file":{"path":"a.txt"},"luhnoxide":{"brand":"Visa","pan":"453201XXXXXX0366","fingerprint":"f1","risk":"high","line":3,"column_start":6}}"#, "\n",
+            r#"{"luhnoxide":{"summary":{"cards_found":1}}}"#, "\n",
+        )).unwrap();
+
+        for path in [&json_path, &ndjson_path, &ecs_path] {
+            let loaded = load(path, None).unwrap();
+            assert_eq!(loaded.findings.len(), 1, "{}", path.display());
+            assert_eq!(loaded.findings[0].fingerprint, "f1");
+            assert_eq!(loaded.findings[0].line_number, 3);
+        }
+        assert_eq!(load(&ecs_path, None).unwrap().risk_of["a.txt"], "high");
+
+        fs::write(&ndjson_path, "{\"brand\": 1}\n").unwrap();
+        let error = load(&ndjson_path, None).err().unwrap();
+        assert!(error.to_string().contains("line 1"), "{}", error);
+    }
+}
@@ -0,0 +1,276 @@
+// Elastic Common Schema output
+//
+// One JSON document per line (NDJSON), ready for Filebeat or the Elasticsearch
+// bulk loader: an alert per finding, then an event for the scan as a whole.
+// Standard ECS fields are used where they exist (file.*, host.*, event.*,
+// rule.*); scanner-specific values go under the `luhnoxide` namespace. A
+// scan comparison from `luhnoxide diff` is written the same way, with the
+// change in event.action and luhnoxide.diff.
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use chrono::{Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde_json::{json, Value};
+
+use crate::diff::{ScanDiff, Status};
+use crate::{CardMatch, ScanSummary};
+
+const ECS_VERSION: &str = "8.11.0";
+
+// Write the documents
+pub fn write_ndjson<W: Write>(writer: &mut W, summary: &ScanSummary, findings: &[CardMatch]) -> io::Result<()> {
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let host = gethostname::gethostname().to_string_lossy().to_string();
+    let risk_of: HashMap<&str, &str> = summary.files_by_risk.iter()
+        .flat_map(|(level, files)| files.iter().map(move |file| (file.as_str(), level.as_str())))
+        .collect();
+    let mut sizes: HashMap<&str, Option<u64>> = HashMap::new();
+
+    for card in findings {
+        let risk = risk_of.get(card.file_path.as_str()).copied().unwrap_or("low");
+        let size = *sizes.entry(&card.file_path)
+            .or_insert_with(|| fs::metadata(&card.file_path).ok().map(|meta| meta.len()));
+        let document = finding_document(card, risk, size, &timestamp, &host);
+        writeln!(writer, "{}", serde_json::to_string(&document)?)?;
+    }
+
+    writeln!(writer, "{}", serde_json::to_string(&summary_document(summary, &timestamp, &host))?)
+}
+
+// Write a scan comparison: one document per finding of either scan, then one
+// for the comparison as a whole
+pub fn write_diff_ndjson<W: Write>(writer: &mut W, diff: &ScanDiff) -> io::Result<()> {
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let host = gethostname::gethostname().to_string_lossy().to_string();
+
+    for change in &diff.changes {
+        let risk = diff.risk(&change.card.file_path);
+        let size = fs::metadata(&change.card.file_path).ok().map(|meta| meta.len());
+        let mut document = finding_document(&change.card, risk, size, &timestamp, &host);
+        document["event"]["action"] = json!(format!("card-{}", change.status.as_str()));
+        document["event"]["dataset"] = json!("luhnoxide.diff");
+        if change.status == Status::Resolved {
+            document["event"]["kind"] = json!("event");
+            document["event"]["type"] = json!(["deletion"]);
+        }
+        document["message"] = json!(format!("{} card number {} in {} at line {}", change.card.brand,
+                                            change.status.as_str(), change.card.file_path, change.card.line_number));
+        document["luhnoxide"]["diff"] = json!({
+            "status": change.status.as_str(),
+            "previous_line": change.previous_line,
+        });
+        writeln!(writer, "{}", serde_json::to_string(&document)?)?;
+    }
+
+    let document = json!({
+        "@timestamp": timestamp,
+        "ecs": { "version": ECS_VERSION },
+        "message": format!("Compared scans: {} added, {} resolved, {} unchanged",
+                           diff.count(Status::Added), diff.count(Status::Resolved), diff.count(Status::Unchanged)),
+        "event": {
+            "kind": "event",
+            "category": ["file"],
+            "type": ["info"],
+            "action": "scan-diff",
+            "module": "luhnoxide",
+            "dataset": "luhnoxide.diff",
+            "outcome": "success",
+        },
+        "host": { "name": host, "hostname": host },
+        "agent": { "type": "luhnoxide", "version": env!("CARGO_PKG_VERSION") },
+        "luhnoxide": {
+            "diff": {
+                "old": diff.old,
+                "new": diff.new,
+                "added": diff.count(Status::Added),
+                "resolved": diff.count(Status::Resolved),
+                "unchanged": diff.count(Status::Unchanged),
+            },
+        },
+    });
+    writeln!(writer, "{}", serde_json::to_string(&document)?)
+}
+
+fn finding_document(card: &CardMatch, risk: &str, size: Option<u64>, timestamp: &str, host: &str) -> Value {
+    let path = Path::new(&card.file_path);
+    let (risk_score, severity) = match risk {
+        "high" => (90, 9),
+        "medium" => (70, 7),
+        _ => (50, 5),
+    };
+
+    let mut file = json!({
+        "path": card.file_path,
+        "name": path.file_name().map(|name| name.to_string_lossy()),
+        "directory": path.parent().map(|dir| dir.to_string_lossy()),
+        "extension": path.extension().map(|ext| ext.to_string_lossy()),
+        "type": "file",
+    });
+    if let Some(size) = size {
+        file["size"] = json!(size);
+    }
+
+    json!({
+        "@timestamp": timestamp,
+        "ecs": { "version": ECS_VERSION },
+        "message": format!("{} card number found in {} at line {}", card.brand, card.file_path, card.line_number),
+        "event": {
+            "kind": "alert",
+            "category": ["file"],
+            "type": ["info"],
+            "action": "card-found",
+            "module": "luhnoxide",
+            "dataset": "luhnoxide.finding",
+            "severity": severity,
+            "risk_score": risk_score,
+        },
+        "rule": {
+            "name": "payment-card-number",
+            "description": "Luhn-valid payment card number",
+            "category": "Data discovery",
+            "ruleset": "luhnoxide",
+        },
+        "file": file,
+        "host": { "name": host, "hostname": host },
+        "agent": { "type": "luhnoxide", "version": env!("CARGO_PKG_VERSION") },
+        "luhnoxide": {
+            "brand": card.brand,
+            "pan": card.full_pan,
+            "bin": card.bin,
+            "last_four": card.last_four,
+            "pan_length": card.length,
+            "fingerprint": card.fingerprint,
+            "risk": risk,
+            "line": card.line_number,
+            "column_start": card.column_start,
+            "column_end": card.column_end,
+            "byte_offset": card.byte_offset,
+            "context": card.context,
+        },
+    })
+}
+
+fn summary_document(summary: &ScanSummary, timestamp: &str, host: &str) -> Value {
+    let tier = |level: &str| summary.files_by_risk.get(level).map_or(0, Vec::len);
+
+    let mut event = json!({
+        "kind": "event",
+        "category": ["file"],
+        "type": ["info"],
+        "action": "scan-summary",
+        "module": "luhnoxide",
+        "dataset": "luhnoxide.summary",
+        "outcome": "success",
+    });
+    // scan_date is local time without a zone
+    if let Some(start) = NaiveDateTime::parse_from_str(&summary.scan_date, "%Y-%m-%d %H:%M:%S").ok()
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+    {
+        event["start"] = json!(start.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+
+    json!({
+        "@timestamp": timestamp,
+        "ecs": { "version": ECS_VERSION },
+        "message": format!("Scanned {} files: {} card numbers ({} unique) in {} files",
+                           summary.total_files_scanned, summary.total_cards_found,
+                           summary.unique_cards_found, summary.total_files_with_cards),
+        "event": event,
+        "host": { "name": host, "hostname": host },
+        "agent": { "type": "luhnoxide", "version": env!("CARGO_PKG_VERSION") },
+        "luhnoxide": {
+            "summary": {
+                "scan_duration": summary.scan_duration,
+                "files_scanned": summary.total_files_scanned,
+                "directories_scanned": summary.total_directories_scanned,
+                "files_with_cards": summary.total_files_with_cards,
+                "clean_files": summary.clean_files,
+                "skipped_files": summary.skipped_files.len(),
+                "cards_found": summary.total_cards_found,
+                "unique_cards": summary.unique_cards_found,
+                "size_scanned_mb": summary.total_size_scanned_mb,
+                "card_types": summary.card_type_counts,
+                "unique_card_types": summary.unique_card_type_counts,
+                "risk": {
+                    "high": tier("high"),
+                    "medium": tier("medium"),
+                    "low": tier("low"),
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_report;
+
+    #[test]
+    fn writes_one_document_per_line_with_masked_findings() {
+        let path = "/srv/a\n{\"luhnoxide\":{}}.txt";
+        let (summary, findings) = test_report(&[(path, "a\"}\r\n4532015112830366"), ("/srv/clean.txt", "none")]);
+        let mut ndjson = Vec::new();
+        write_ndjson(&mut ndjson, &summary, &findings).unwrap();
+        let ndjson = String::from_utf8(ndjson).unwrap();
+
+        assert!(!ndjson.contains("4532015112830366"));
+        let documents: Vec<Value> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(documents.len(), 2);
+
+        let finding = &documents[0];
+        assert_eq!(finding["file"]["path"], path);
+        assert_eq!(finding["event"]["kind"], "alert");
+        assert_eq!(finding["luhnoxide"]["pan"], "453201XXXXXX0366");
+        assert_eq!(finding["luhnoxide"]["bin"], "453201");
+        assert_eq!(finding["luhnoxide"]["last_four"], "0366");
+        assert!(finding["luhnoxide"]["context"].as_str().unwrap().ends_with("453201XXXXXX0366"));
+
+        assert_eq!(documents[1]["event"]["kind"], "event");
+        assert_eq!(documents[1]["ecs"]["version"], ECS_VERSION);
+    }
+
+    #[test]
+    fn findings_map_to_ecs_file_host_and_rule_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.csv");
+        let content = "id,pan\n1,4532015112830366\n";
+        fs::write(&path, content).unwrap();
+        let path = path.to_str().unwrap();
+        let (mut summary, findings) = test_report(&[(path, "1,4532015112830366")]);
+        let tiered = summary.files_by_risk.remove("low").unwrap();
+        summary.files_by_risk.insert("high".to_string(), tiered);
+        let mut ndjson = Vec::new();
+        write_ndjson(&mut ndjson, &summary, &findings).unwrap();
+        let documents: Vec<Value> = ndjson.split(|&byte| byte == b'\n').filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap()).collect();
+
+        let host = gethostname::gethostname().to_string_lossy().to_string();
+        let finding = &documents[0];
+        assert_eq!(finding["file"]["size"], content.len());
+        assert_eq!(finding["file"]["name"], "orders.csv");
+        assert_eq!(finding["file"]["extension"], "csv");
+        assert_eq!(finding["file"]["directory"], dir.path().to_str().unwrap());
+        assert_eq!(finding["host"]["name"], host.as_str());
+        assert_eq!(finding["rule"]["name"], "payment-card-number");
+        assert_eq!(finding["event"]["severity"], 9);
+        assert_eq!(finding["event"]["risk_score"], 90);
+        assert_eq!(finding["luhnoxide"]["risk"], "high");
+
+        // A file that is gone by the time the report is written has no size
+        let (summary, findings) = test_report(&[("/nonexistent/orders.csv", "4532015112830366")]);
+        let mut ndjson = Vec::new();
+        write_ndjson(&mut ndjson, &summary, &findings).unwrap();
+        let finding: Value = serde_json::from_slice(ndjson.split(|&byte| byte == b'\n').next().unwrap()).unwrap();
+        assert!(finding["file"].get("size").is_none());
+        assert_eq!(finding["event"]["severity"], 5);
+
+        let summary_document = &documents[1];
+        assert_eq!(summary_document["host"]["name"], host.as_str());
+        assert_eq!(summary_document["event"]["action"], "scan-summary");
+        assert_eq!(summary_document["luhnoxide"]["summary"]["risk"]["high"], 1);
+        assert_eq!(summary_document["luhnoxide"]["summary"]["cards_found"], 1);
+    }
+}
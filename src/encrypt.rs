@@ -0,0 +1,152 @@
+// Encrypted report output
+//
+// Reports written with --no-mask contain full PANs. Encrypting them to the
+// remediation team's age X25519 public keys means the cleartext never touches
+// disk; only holders of a matching identity can read the report (e.g. with
+// `age --decrypt -i key.txt report.age`).
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use age::stream::StreamWriter;
+use age::x25519;
+
+// Output file for a report, either plain or encrypted
+pub enum ReportWriter {
+    Plain(BufWriter<File>),
+    Encrypted(StreamWriter<BufWriter<File>>),
+}
+
+impl ReportWriter {
+    // Create the output file, encrypting to `recipients` if there are any
+    pub fn create(path: &Path, recipients: &[x25519::Recipient]) -> io::Result<Self> {
+        let writer = BufWriter::new(File::create(path)?);
+        if recipients.is_empty() {
+            return Ok(ReportWriter::Plain(writer));
+        }
+
+        let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(io::Error::other)?;
+        Ok(ReportWriter::Encrypted(encryptor.wrap_output(writer)?))
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, ReportWriter::Encrypted(_))
+    }
+
+    // Flush everything to disk. Encrypted output is truncated without this.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            ReportWriter::Plain(mut writer) => writer.flush(),
+            ReportWriter::Encrypted(writer) => writer.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for ReportWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ReportWriter::Plain(writer) => writer.write(buf),
+            ReportWriter::Encrypted(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ReportWriter::Plain(writer) => writer.flush(),
+            ReportWriter::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
+
+// Collect recipients given on the command line and in a recipients file (one
+// `age1...` key per line, blank lines and `#` comments ignored)
+pub fn parse_recipients<'a>(keys: impl Iterator<Item = &'a str>,
+                            recipients_file: Option<&str>) -> io::Result<Vec<x25519::Recipient>> {
+    let mut all_keys: Vec<String> = keys.map(str::to_string).collect();
+
+    if let Some(path) = recipients_file {
+        let content = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("Cannot read recipients file {}: {}", path, e))
+        })?;
+        all_keys.extend(content.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string));
+    }
+
+    all_keys.iter()
+        .map(|key| x25519::Recipient::from_str(key).map_err(|e| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid age recipient '{}': {}", key, e),
+        )))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    const REPORT: &[u8] = b"pay 4532015112830366 now\n";
+
+    #[test]
+    fn encrypted_reports_decrypt_with_any_recipient_identity() {
+        let (first, second) = (x25519::Identity::generate(), x25519::Identity::generate());
+        let dir = tempfile::tempdir().unwrap();
+        let recipients_file = dir.path().join("recipients.txt");
+        fs::write(&recipients_file, format!("# remediation team\n\n  {}  \n", second.to_public())).unwrap();
+        let recipients = parse_recipients([first.to_public().to_string()].iter().map(String::as_str),
+                                        recipients_file.to_str()).unwrap();
+        assert_eq!(recipients.len(), 2);
+
+        let path = dir.path().join("report.age");
+        let mut writer = ReportWriter::create(&path, &recipients).unwrap();
+        assert!(writer.is_encrypted());
+        writer.write_all(REPORT).unwrap();
+        writer.finish().unwrap();
+
+        let encrypted = fs::read(&path).unwrap();
+        assert!(encrypted.starts_with(b"age-encryption.org/v1"));
+        assert!(!encrypted.windows(16).any(|window| window == b"4532015112830366"));
+        for identity in [&first, &second] {
+            let decryptor = age::Decryptor::new(&encrypted[..]).unwrap();
+            let mut reader = decryptor.decrypt(std::iter::once(identity as &dyn age::Identity)).unwrap();
+            let mut decrypted = Vec::new();
+            reader.read_to_end(&mut decrypted).unwrap();
+            assert_eq!(decrypted, REPORT);
+        }
+
+        let other = x25519::Identity::generate();
+        assert!(age::Decryptor::new(&encrypted[..]).unwrap()
+            .decrypt(std::iter::once(&other as &dyn age::Identity)).is_err());
+
+        // Without recipients the report is written as is
+        let mut writer = ReportWriter::create(&path, &[]).unwrap();
+        assert!(!writer.is_encrypted());
+        writer.write_all(REPORT).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), REPORT);
+    }
+
+    #[test]
+    fn invalid_recipients_are_rejected() {
+        for key in ["age1notakey", "ssh-ed25519 AAAA", ""] {
+            let error = parse_recipients(std::iter::once(key), None).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{}", key);
+            assert!(error.to_string().contains(&format!("'{}'", key)), "{}", error);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let recipients_file = dir.path().join("recipients.txt");
+        fs::write(&recipients_file, "age1notakey\n").unwrap();
+        assert!(parse_recipients(std::iter::empty(), recipients_file.to_str()).is_err());
+
+        let missing = dir.path().join("missing.txt");
+        let error = parse_recipients(std::iter::empty(), missing.to_str()).unwrap_err();
+        assert_eq!(error.to_string().split(':').next().unwrap(),
+                   format!("Cannot read recipients file {}", missing.display()));
+    }
+}
@@ -0,0 +1,336 @@
+// Heuristic false-positive filtering for Luhn-valid candidates
+//
+// Roughly one in ten random digit strings passes the Luhn check, so long
+// identifiers, timestamps and placeholder values regularly show up as
+// "cards". Each check below looks for a pattern that real PANs practically
+// never have and reports why a candidate was rejected.
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::CardMatch;
+use crate::masking::MaskPolicy;
+
+// Runs of identical or sequential digits at least this long are rejected
+const MAX_PATTERN_RUN: usize = 8;
+
+// Epoch seconds between 2001-09-09 and 2038-01-19, the range a 13/16/19 digit
+// millisecond/microsecond/nanosecond timestamp starts with
+const EPOCH_SECONDS_MIN: u64 = 1_000_000_000;
+const EPOCH_SECONDS_MAX: u64 = 2_147_483_647;
+
+// Separators that may continue a grouped number past a candidate
+const NUMBER_SEPARATORS: &[char] = &['-', ' ', '.', ':', '/', ','];
+
+pub const ALL_CHECKS: &str = "repeated,sequence,entropy,embedded,hex,timestamp";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    RepeatedDigits,
+    SequentialDigits,
+    LowEntropy,
+    EmbeddedInLongerNumber,
+    HexContext,
+    EpochTimestamp,
+}
+
+impl RejectReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectReason::RepeatedDigits => "repeated",
+            RejectReason::SequentialDigits => "sequence",
+            RejectReason::LowEntropy => "entropy",
+            RejectReason::EmbeddedInLongerNumber => "embedded",
+            RejectReason::HexContext => "hex",
+            RejectReason::EpochTimestamp => "timestamp",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            RejectReason::RepeatedDigits => "long run of the same digit",
+            RejectReason::SequentialDigits => "long ascending or descending digit sequence",
+            RejectReason::LowEntropy => "digit entropy below threshold",
+            RejectReason::EmbeddedInLongerNumber => "part of a longer number",
+            RejectReason::HexContext => "surrounded by hexadecimal data",
+            RejectReason::EpochTimestamp => "looks like an epoch timestamp",
+        }
+    }
+}
+
+// Which checks are active; a disabled filter accepts every candidate
+#[derive(Clone, Debug)]
+pub struct FilterConfig {
+    enabled: bool,
+    repeated_digits: bool,
+    sequences: bool,
+    low_entropy: bool,
+    min_entropy: f64,
+    embedded: bool,
+    hex_context: bool,
+    timestamps: bool,
+}
+
+impl FilterConfig {
+    pub fn disabled() -> Self {
+        FilterConfig {
+            enabled: false,
+            repeated_digits: false,
+            sequences: false,
+            low_entropy: false,
+            min_entropy: 0.0,
+            embedded: false,
+            hex_context: false,
+            timestamps: false,
+        }
+    }
+
+    // Build a filter from a comma-separated list of check names
+    pub fn from_checks(checks: &str, min_entropy: f64) -> io::Result<Self> {
+        let mut config = FilterConfig::disabled();
+        config.enabled = true;
+        config.min_entropy = min_entropy;
+
+        for check in checks.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            match check.to_lowercase().as_str() {
+                "all" => return FilterConfig::from_checks(ALL_CHECKS, min_entropy),
+                "repeated" => config.repeated_digits = true,
+                "sequence" => config.sequences = true,
+                "entropy" => config.low_entropy = true,
+                "embedded" => config.embedded = true,
+                "hex" => config.hex_context = true,
+                "timestamp" => config.timestamps = true,
+                other => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown false-positive check '{}' (expected one of: {})", other, ALL_CHECKS),
+                    ));
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Run every enabled check against a candidate. `digits` is the candidate
+    // without separators and `start..end` is its byte span within `line`.
+    pub fn check(&self, digits: &str, line: &str, start: usize, end: usize) -> Vec<RejectReason> {
+        let mut reasons = Vec::new();
+        if !self.enabled {
+            return reasons;
+        }
+
+        // Checks read the candidate in place, so no copy of it is left behind
+        let values = digits.as_bytes();
+
+        if self.repeated_digits && longest_run(values, |a, b| a == b) >= MAX_PATTERN_RUN {
+            reasons.push(RejectReason::RepeatedDigits);
+        }
+        if self.sequences
+            && (longest_run(values, |a, b| digit(b) == (digit(a) + 1) % 10) >= MAX_PATTERN_RUN
+                || longest_run(values, |a, b| digit(a) == (digit(b) + 1) % 10) >= MAX_PATTERN_RUN)
+        {
+            reasons.push(RejectReason::SequentialDigits);
+        }
+        if self.low_entropy && digit_entropy(values) < self.min_entropy {
+            reasons.push(RejectReason::LowEntropy);
+        }
+        if self.embedded && is_embedded(line, start, end) {
+            reasons.push(RejectReason::EmbeddedInLongerNumber);
+        }
+        if self.hex_context && in_hex_context(line, start, end) {
+            reasons.push(RejectReason::HexContext);
+        }
+        if self.timestamps && looks_like_timestamp(digits) {
+            reasons.push(RejectReason::EpochTimestamp);
+        }
+
+        reasons
+    }
+}
+
+// A candidate that passed Luhn and brand checks but was dropped by the filter
+pub struct FilterRejection {
+    pub card: CardMatch,
+    pub reasons: Vec<RejectReason>,
+}
+
+impl FilterRejection {
+    pub fn explain(&self, policy: &MaskPolicy) -> String {
+        let reasons: Vec<String> = self.reasons.iter()
+            .map(|reason| format!("{} ({})", reason.as_str(), reason.description()))
+            .collect();
+
+        format!(
+            "Rejected {} at {}:{} - {}",
+            self.card.masked_pan(policy),
+            self.card.file_path,
+            self.card.line_number,
+            reasons.join(", ")
+        )
+    }
+}
+
+// Value of an ASCII digit
+fn digit(byte: u8) -> u8 {
+    byte - b'0'
+}
+
+// Length of the longest run where each neighbouring pair satisfies `linked`
+fn longest_run(values: &[u8], linked: impl Fn(u8, u8) -> bool) -> usize {
+    let mut longest = if values.is_empty() { 0 } else { 1 };
+    let mut current = longest;
+
+    for pair in values.windows(2) {
+        if linked(pair[0], pair[1]) {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 1;
+        }
+    }
+
+    longest
+}
+
+// Shannon entropy of the digit distribution, in bits (max ~3.32)
+fn digit_entropy(values: &[u8]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0usize; 10];
+    for &value in values {
+        counts[digit(value) as usize] += 1;
+    }
+
+    let total = values.len() as f64;
+    counts.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// True if another digit directly follows or precedes the candidate, or the
+// candidate is itself grouped with a separator and another digit group
+// continues it across that same separator (the tail of a longer grouped
+// number). Digits across other separators are neighbouring fields, as in CSV
+// columns or a timestamp in front of a PAN, and don't count.
+fn is_embedded(line: &str, start: usize, end: usize) -> bool {
+    let candidate = &line[start..end];
+    let continues = |mut neighbours: std::str::Chars| match neighbours.next() {
+        Some(c) if c.is_ascii_digit() => true,
+        Some(c) if NUMBER_SEPARATORS.contains(&c) && candidate.contains(c) => {
+            neighbours.next().is_some_and(|c| c.is_ascii_digit())
+        }
+        _ => false,
+    };
+
+    continues(line[end..].chars()) || {
+        let before: String = line[..start].chars().rev().take(2).collect();
+        continues(before.chars())
+    }
+}
+
+// True if the candidate is glued to hex letters, follows a `0x` prefix, or is
+// a dash-separated neighbour of a hex group (UUID-style identifiers)
+fn in_hex_context(line: &str, start: usize, end: usize) -> bool {
+    let prefix = &line[..start];
+    let suffix = &line[end..];
+
+    if prefix.ends_with("0x") || prefix.ends_with("0X") {
+        return true;
+    }
+    if prefix.chars().next_back().is_some_and(is_hex_letter)
+        || suffix.chars().next().is_some_and(is_hex_letter)
+    {
+        return true;
+    }
+
+    let group_before = prefix.strip_suffix('-')
+        .map(|p| p.rsplit(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or(""));
+    let group_after = suffix.strip_prefix('-')
+        .map(|s| s.split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or(""));
+
+    [group_before, group_after].into_iter().flatten().any(is_hex_group)
+}
+
+fn is_hex_letter(c: char) -> bool {
+    matches!(c, 'a'..='f' | 'A'..='F')
+}
+
+fn is_hex_group(group: &str) -> bool {
+    !group.is_empty()
+        && group.chars().all(|c| c.is_ascii_hexdigit())
+        && group.chars().any(is_hex_letter)
+}
+
+// 13, 16 and 19 digit numbers whose leading ten digits are plausible epoch
+// seconds are millisecond, microsecond or nanosecond timestamps
+fn looks_like_timestamp(digits: &str) -> bool {
+    if ![13, 16, 19].contains(&digits.len()) {
+        return false;
+    }
+
+    digits[..10].parse::<u64>()
+        .map(|seconds| (EPOCH_SECONDS_MIN..=EPOCH_SECONDS_MAX).contains(&seconds))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reasons the given checks reject `candidate`, the first occurrence of
+    // which is taken as its span in `line`
+    fn rejected(checks: &str, line: &str, candidate: &str) -> Vec<RejectReason> {
+        let filter = FilterConfig::from_checks(checks, 2.0).unwrap();
+        let start = line.find(candidate).unwrap();
+        let digits: String = candidate.chars().filter(char::is_ascii_digit).collect();
+        filter.check(&digits, line, start, start + candidate.len())
+    }
+
+    #[test]
+    fn each_check_rejects_its_pattern_and_accepts_real_pans() {
+        use RejectReason::*;
+
+        let cases: &[(&str, &str, &str, &[RejectReason])] = &[
+            ("repeated", "id 4444444441111111", "4444444441111111", &[RepeatedDigits]),
+            ("repeated", "pay 4532015112830366", "4532015112830366", &[]),
+            ("sequence", "ref 4123456789012341", "4123456789012341", &[SequentialDigits]),
+            ("sequence", "pay 4532015112830366", "4532015112830366", &[]),
+            ("entropy", "n 4000000000000002", "4000000000000002", &[LowEntropy]),
+            ("entropy", "pay 4532015112830366", "4532015112830366", &[]),
+            ("embedded", "ref 4532 0151 1283 0366 7781", "4532 0151 1283 0366", &[EmbeddedInLongerNumber]),
+            ("embedded", "ref 9-4532-0151-1283-0366", "4532-0151-1283-0366", &[EmbeddedInLongerNumber]),
+            ("embedded", "john,4532015112830366,123,12/27", "4532015112830366", &[]),
+            ("embedded", "ts=2024-01-01 10:00 4532015112830366", "4532015112830366", &[]),
+            ("embedded", "4532015112830366 12/27 123", "4532015112830366", &[]),
+            ("embedded", "card 4532-0151-1283-0366, cvv 123", "4532-0151-1283-0366", &[]),
+            ("hex", "key 0x4532015112830366", "4532015112830366", &[HexContext]),
+            ("hex", "id deadbeef-4532015112830366", "4532015112830366", &[HexContext]),
+            ("hex", "pay 4532015112830366 now", "4532015112830366", &[]),
+            ("timestamp", "at 1700000000000 ms", "1700000000000", &[EpochTimestamp]),
+            ("timestamp", "pay 4532015112830366", "4532015112830366", &[]),
+            ("all", "john,4532015112830366,123,12/27", "4532015112830366", &[]),
+            ("all", "ts=2024-01-01 10:00 4532015112830366", "4532015112830366", &[]),
+        ];
+
+        for (checks, line, candidate, expected) in cases {
+            assert_eq!(rejected(checks, line, candidate), *expected, "{} check on {:?}", checks, line);
+        }
+    }
+
+    #[test]
+    fn disabled_filter_and_unknown_checks() {
+        assert!(FilterConfig::disabled().check("4444444444444448", "4444444444444448", 0, 16).is_empty());
+        assert!(FilterConfig::from_checks("repeated,bogus", 2.0).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+// Keyed fingerprints of PANs
+//
+// A fingerprint is a truncated HMAC-SHA256 of the PAN under a secret key. It
+// is stable for as long as the key is, so the same card can be counted once
+// and followed across files and scans without the PAN itself ever being
+// written out. Without the key, fingerprints cannot be brute-forced back to
+// PANs the way a plain hash of a 16-digit number could.
+use std::io;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::keys;
+
+pub const KEY_ENV_VAR: &str = "LUHNOXIDE_FINGERPRINT_KEY";
+
+// Hex characters kept from the digest (128 bits)
+const FINGERPRINT_LEN: usize = 32;
+
+pub struct Fingerprinter {
+    key: Vec<u8>,
+    ephemeral: bool,
+}
+
+impl Fingerprinter {
+    // Use the configured key, or a random one-off key if none was supplied
+    pub fn from_config(key_file: Option<&str>) -> io::Result<Self> {
+        match keys::load_key(KEY_ENV_VAR, key_file)? {
+            Some(key) => Ok(Fingerprinter::from_key(key)),
+            None => {
+                let mut key = vec![0u8; 32];
+                getrandom::fill(&mut key).map_err(io::Error::other)?;
+                Ok(Fingerprinter { key, ephemeral: true })
+            }
+        }
+    }
+
+    pub fn from_key(key: Vec<u8>) -> Self {
+        Fingerprinter { key, ephemeral: false }
+    }
+
+    // True if fingerprints are only comparable within this run
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral
+    }
+
+    // Fail unless fingerprints are stable across runs, for `flag`s that
+    // compare them with earlier scans
+    pub fn require_stable(&self, flag: &str) -> io::Result<()> {
+        if self.ephemeral {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} needs a fingerprint key: set {} or pass --fingerprint-key-file", flag, KEY_ENV_VAR),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn fingerprint(&self, pan: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(pan.as_bytes());
+        let digest = mac.finalize().into_bytes();
+
+        let mut hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        hex.truncate(FINGERPRINT_LEN);
+        hex
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAN: &str = "4532015112830366";
+
+    #[test]
+    fn keyed_fingerprints_are_stable_across_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("fingerprint.key");
+        std::fs::write(&key_file, "0123456789abcdef0123456789abcdef\n").unwrap();
+
+        let first = Fingerprinter::from_config(key_file.to_str()).unwrap();
+        let second = Fingerprinter::from_key(b"0123456789abcdef0123456789abcdef".to_vec());
+        assert!(!first.is_ephemeral());
+        assert!(first.require_stable("--db").is_ok());
+        assert_eq!(first.fingerprint(PAN), second.fingerprint(PAN));
+        assert_eq!(first.fingerprint(PAN).len(), FINGERPRINT_LEN);
+        assert_ne!(first.fingerprint(PAN), first.fingerprint("4111111111111111"));
+
+        let other = Fingerprinter::from_key(b"fedcba9876543210fedcba9876543210".to_vec());
+        assert_ne!(first.fingerprint(PAN), other.fingerprint(PAN));
+    }
+
+    #[test]
+    fn ephemeral_fingerprints_cannot_be_compared_with_other_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.key");
+        let error = Fingerprinter::from_config(missing.to_str()).err().unwrap();
+        assert!(error.to_string().contains(missing.to_str().unwrap()), "{}", error);
+
+        let ephemeral = Fingerprinter { key: vec![7; 32], ephemeral: true };
+        let error = ephemeral.require_stable("--cache").unwrap_err();
+        assert!(error.to_string().contains("--cache") && error.to_string().contains(KEY_ENV_VAR));
+    }
+}
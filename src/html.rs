@@ -0,0 +1,607 @@
+// HTML report rendering
+//
+// The report is rendered from a template with HTML auto-escaping, so every
+// file path, snippet and brand taken from a scan is escaped wherever it lands.
+// The built-in template lives in templates/report.html; users can supply
+// their own, usually extending the built-in one and overriding its blocks.
+//
+// The report must work as a single file opened offline, so the charts are
+// inline SVG and the findings table is driven by a small inline script. The
+// findings are embedded as JSON and the script only ever inserts them with
+// textContent, so nothing from a scanned file is interpreted as markup.
+//
+// Scan comparisons from `luhnoxide diff` and trends from `report trend` use
+// templates/diff.html and templates/trend.html, which extend the report
+// template and replace its header and content. Charts are drawn by the
+// macros in templates/charts.html.
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+use minijinja::value::Serde;
+use minijinja::{AutoEscape, Environment, Value};
+use serde::Serialize;
+
+use crate::diff::{ScanDiff, Status};
+use crate::trend::{self, TrendPoint};
+use crate::{CardMatch, ScanSummary, SharedCard};
+
+const REPORT_TEMPLATE: &str = include_str!("../templates/report.html");
+const CHARTS_TEMPLATE: &str = include_str!("../templates/charts.html");
+const DIFF_TEMPLATE: &str = include_str!("../templates/diff.html");
+const TREND_TEMPLATE: &str = include_str!("../templates/trend.html");
+
+// Name a user-supplied template is registered under
+const CUSTOM_TEMPLATE: &str = "custom.html";
+
+// Bar colours for the brand chart, cycled in order
+const BRAND_COLORS: &[&str] = &["#3498db", "#9b59b6", "#1abc9c", "#e67e22", "#34495e", "#16a085", "#c0392b"];
+
+// Risk tiers as (level, label, colour, description)
+const RISK_LEVELS: &[(&str, &str, &str, &str)] = &[
+    ("high", "High", "#e74c3c", "Files containing many credit card numbers or highly sensitive data:"),
+    ("medium", "Medium", "#f39c12", "Files containing some credit card numbers:"),
+    ("low", "Low", "#2ecc71", "Files containing few credit card numbers:"),
+];
+
+// Everything a report template can use. Numbers that need formatting are
+// passed preformatted; the raw figures are available under `summary`.
+#[derive(Serialize)]
+struct ReportContext<'a> {
+    title: &'a str,
+    scan_date: &'a str,
+    scan_duration: &'a str,
+    summary: &'a ScanSummary,
+    stats: Vec<Stat>,
+    brands: Vec<BrandRow<'a>>,
+    charts: Vec<Chart>,
+    unique_cards_per_file: Vec<FileCount<'a>>,
+    shared_cards: &'a [SharedCard],
+    risk_tiers: Vec<RiskTier<'a>>,
+    clean_percentage: String,
+    scanned_files: &'a [String],
+    skipped_files: &'a [String],
+    finding_brands: BTreeSet<&'a str>,
+    findings: Vec<FindingRow<'a>>,
+    // The findings as JSON, already safe to place inside a <script> element
+    findings_json: Value,
+    // Earlier scans of the same inputs, when the scan is stored with --db
+    trend: Option<TrendView>,
+}
+
+#[derive(Serialize)]
+struct Stat {
+    label: &'static str,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct BrandRow<'a> {
+    brand: &'a str,
+    count: usize,
+    unique: usize,
+    percentage: String,
+}
+
+#[derive(Serialize)]
+struct FileCount<'a> {
+    file: &'a str,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct RiskTier<'a> {
+    level: &'static str,
+    label: &'static str,
+    title: String,
+    description: &'static str,
+    files: &'a [String],
+}
+
+// Horizontal bar chart, laid out here and drawn as SVG by the template
+#[derive(Serialize)]
+struct Chart {
+    title: String,
+    width: usize,
+    height: usize,
+    label_width: usize,
+    bars: Vec<Bar>,
+}
+
+#[derive(Serialize)]
+struct Bar {
+    label: String,
+    value: usize,
+    color: &'static str,
+    y: usize,
+    width: usize,
+}
+
+// One row of the findings table as handed to the script
+#[derive(Serialize)]
+struct FindingRow<'a> {
+    file: &'a str,
+    line: usize,
+    column_start: usize,
+    column_end: usize,
+    brand: &'a str,
+    pan: &'a str,
+    fingerprint: &'a str,
+    risk: &'a str,
+    // Context snippet split around the match so it can be highlighted
+    before: &'a str,
+    matched: &'a str,
+    after: &'a str,
+}
+
+// Line charts and table of successive scans
+#[derive(Serialize)]
+struct TrendView {
+    charts: Vec<LineChart>,
+    rows: Vec<TrendRow>,
+}
+
+#[derive(Serialize)]
+struct TrendRow {
+    scan: String,
+    scan_date: String,
+    files_scanned: usize,
+    files_with_cards: usize,
+    cards_found: usize,
+    unique_cards: usize,
+    clean_percentage: String,
+}
+
+// Line chart, laid out here and drawn as SVG by the template
+#[derive(Serialize)]
+struct LineChart {
+    title: &'static str,
+    color: &'static str,
+    width: usize,
+    height: usize,
+    // Plot area
+    left: usize,
+    right: usize,
+    top: usize,
+    bottom: usize,
+    max_label: String,
+    polyline: String,
+    points: Vec<LinePoint>,
+    first_label: String,
+    last_label: String,
+}
+
+#[derive(Serialize)]
+struct LinePoint {
+    x: String,
+    y: String,
+    label: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct TrendContext<'a> {
+    title: &'a str,
+    database: &'a str,
+    stats: Vec<Stat>,
+    trend: TrendView,
+}
+
+// Everything the diff template uses
+#[derive(Serialize)]
+struct DiffContext<'a> {
+    title: &'a str,
+    old: &'a str,
+    new: &'a str,
+    stats: Vec<Stat>,
+    charts: Vec<Chart>,
+    files: Vec<DiffFile<'a>>,
+    sections: Vec<DiffSection<'a>>,
+}
+
+#[derive(Serialize)]
+struct DiffFile<'a> {
+    file: &'a str,
+    risk: &'a str,
+    added: usize,
+    resolved: usize,
+    unchanged: usize,
+}
+
+#[derive(Serialize)]
+struct DiffSection<'a> {
+    label: &'static str,
+    description: &'static str,
+    rows: Vec<DiffRow<'a>>,
+}
+
+#[derive(Serialize)]
+struct DiffRow<'a> {
+    file: &'a str,
+    line: usize,
+    previous_line: Option<usize>,
+    brand: &'a str,
+    pan: &'a str,
+    fingerprint: &'a str,
+    context: &'a str,
+}
+
+// Lay out a bar chart. Each bar is (label, value, colour).
+fn bar_chart(title: &str, bars: Vec<(String, usize, &'static str)>) -> Chart {
+    const ROW: usize = 36;
+    const LABEL_WIDTH: usize = 140;
+    const BAR_WIDTH: usize = 260;
+
+    let max = bars.iter().map(|(_, value, _)| *value).max().unwrap_or(0).max(1);
+    Chart {
+        title: title.to_string(),
+        width: LABEL_WIDTH + BAR_WIDTH + 60,
+        height: (bars.len() * ROW).max(ROW),
+        label_width: LABEL_WIDTH,
+        bars: bars.into_iter()
+            .enumerate()
+            .map(|(index, (label, value, color))| Bar {
+                label,
+                value,
+                color,
+                y: index * ROW,
+                width: (value * BAR_WIDTH).div_ceil(max).max(if value > 0 { 2 } else { 0 }),
+            })
+            .collect(),
+    }
+}
+
+// Lay out a line chart of `values`, one point per scan, left to right
+fn line_chart(title: &'static str, color: &'static str, points: &[TrendPoint], values: &[f64], percent: bool) -> LineChart {
+    const WIDTH: usize = 460;
+    const HEIGHT: usize = 220;
+    const LEFT: usize = 50;
+    const RIGHT: usize = WIDTH - 20;
+    const TOP: usize = 10;
+    const BOTTOM: usize = HEIGHT - 30;
+
+    let max = if percent { 100.0 } else { values.iter().copied().fold(0.0, f64::max).max(1.0) };
+    let step = if values.len() > 1 { (RIGHT - LEFT) as f64 / (values.len() - 1) as f64 } else { 0.0 };
+    let coordinates: Vec<(f64, f64)> = values.iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let x = if values.len() > 1 { LEFT as f64 + index as f64 * step } else { (LEFT + RIGHT) as f64 / 2.0 };
+            (x, BOTTOM as f64 - value / max * (BOTTOM - TOP) as f64)
+        })
+        .collect();
+    let display = |value: f64| if percent { format!("{:.1}%", value) } else { format!("{}", value) };
+
+    LineChart {
+        title,
+        color,
+        width: WIDTH,
+        height: HEIGHT,
+        left: LEFT,
+        right: RIGHT,
+        top: TOP,
+        bottom: BOTTOM,
+        max_label: display(max),
+        polyline: coordinates.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect::<Vec<_>>().join(" "),
+        points: coordinates.iter()
+            .zip(points.iter().zip(values))
+            .map(|((x, y), (point, value))| LinePoint {
+                x: format!("{:.1}", x),
+                y: format!("{:.1}", y),
+                label: point.label(),
+                value: display(*value),
+            })
+            .collect(),
+        first_label: points.first().map(|point| point.scan_date.clone()).unwrap_or_default(),
+        last_label: if points.len() > 1 { points.last().map(|point| point.scan_date.clone()).unwrap_or_default() } else { String::new() },
+    }
+}
+
+fn trend_view(points: &[TrendPoint]) -> TrendView {
+    TrendView {
+        charts: trend::SERIES.iter()
+            .zip(BRAND_COLORS)
+            .map(|((title, value), color)| {
+                let values: Vec<f64> = points.iter().map(value).collect();
+                line_chart(title, color, points, &values, title.ends_with("(%)"))
+            })
+            .collect(),
+        rows: points.iter()
+            .map(|point| TrendRow {
+                scan: point.scan_id.map_or_else(|| "current".to_string(), |id| id.to_string()),
+                scan_date: point.scan_date.clone(),
+                files_scanned: point.files_scanned,
+                files_with_cards: point.files_with_cards,
+                cards_found: point.cards_found,
+                unique_cards: point.unique_cards,
+                clean_percentage: format!("{:.1}%", point.clean_percentage),
+            })
+            .collect(),
+    }
+}
+
+// Brand distribution chart, most common brand first
+fn brand_chart(card_type_counts: &HashMap<String, usize>) -> Chart {
+    let mut brands: Vec<(&String, &usize)> = card_type_counts.iter().collect();
+    brands.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    let bars = brands.iter()
+        .enumerate()
+        .map(|(index, (brand, count))| (brand.to_string(), **count, BRAND_COLORS[index % BRAND_COLORS.len()]))
+        .collect();
+    bar_chart("Brand Distribution", bars)
+}
+
+// Number of files in each risk tier
+fn risk_chart(files_by_risk: &HashMap<String, Vec<String>>) -> Chart {
+    let bars = RISK_LEVELS.iter()
+        .map(|(level, label, color, _)| {
+            (format!("{} Risk", label), files_by_risk.get(*level).map_or(0, Vec::len), *color)
+        })
+        .collect();
+    bar_chart("Files by Risk Tier", bars)
+}
+
+// Findings as handed to the script, each tagged with its file's risk tier
+fn finding_rows<'a>(findings: &'a [CardMatch], files_by_risk: &HashMap<String, Vec<String>>) -> Vec<FindingRow<'a>> {
+    let risk_of: HashMap<&str, &'static str> = RISK_LEVELS.iter()
+        .flat_map(|(level, ..)| {
+            files_by_risk.get(*level).into_iter().flatten().map(move |file| (file.as_str(), *level))
+        })
+        .collect();
+
+    findings.iter()
+        .map(|card| {
+            let (before, matched, after) = split_snippet(card);
+            FindingRow {
+                file: &card.file_path,
+                line: card.line_number,
+                column_start: card.column_start,
+                column_end: card.column_end,
+                brand: &card.brand,
+                pan: &card.full_pan,
+                fingerprint: &card.fingerprint,
+                risk: risk_of.get(card.file_path.as_str()).copied().unwrap_or("low"),
+                before,
+                matched,
+                after,
+            }
+        })
+        .collect()
+}
+
+// Render the report with the built-in template, or with the given template
+// source, which can extend the built-in one as "report.html".
+// `trend` holds earlier scans followed by this one, or nothing to leave the
+// trend section out.
+pub fn render_report(summary: &ScanSummary, findings: &[CardMatch], template: Option<&str>,
+                     trend: &[TrendPoint]) -> io::Result<String> {
+    let (env, name) = environment(template)?;
+
+    let rows = finding_rows(findings, &summary.files_by_risk);
+    let context = ReportContext {
+        title: "Luhnoxide Credit Card Scan Report",
+        scan_date: &summary.scan_date,
+        scan_duration: &summary.scan_duration,
+        summary,
+        stats: summary.key_metrics().into_iter()
+            .map(|(label, value)| Stat { label, value })
+            .collect(),
+        brands: summary.brand_rows().into_iter()
+            .map(|(brand, count, unique, percentage)| BrandRow {
+                brand,
+                count,
+                unique,
+                percentage: format!("{:.1}%", percentage),
+            })
+            .collect(),
+        charts: vec![brand_chart(&summary.card_type_counts), risk_chart(&summary.files_by_risk)],
+        unique_cards_per_file: summary.files_by_unique_cards().into_iter()
+            .map(|(file, count)| FileCount { file, count: *count })
+            .collect(),
+        shared_cards: &summary.cards_in_multiple_files,
+        risk_tiers: RISK_LEVELS.iter()
+            .map(|(level, label, _, description)| RiskTier {
+                level,
+                label,
+                title: format!("{} Risk Files", label),
+                description,
+                files: summary.files_by_risk.get(*level).map_or(&[], Vec::as_slice),
+            })
+            .collect(),
+        clean_percentage: format!("{:.1}%", summary.clean_percentage()),
+        scanned_files: &summary.all_scanned_files,
+        skipped_files: &summary.skipped_files,
+        finding_brands: findings.iter().map(|card| card.brand.as_str()).collect(),
+        findings_json: Value::from_safe_string(embed_json(&rows)),
+        findings: rows,
+        trend: (!trend.is_empty()).then(|| trend_view(trend)),
+    };
+
+    env.get_template(name)
+        .and_then(|template| template.render(Serde(context)))
+        .map_err(template_error)
+}
+
+// Render a scan comparison with the built-in diff template
+pub fn render_diff(diff: &ScanDiff) -> io::Result<String> {
+    let (mut env, _) = environment(None)?;
+    env.add_template("diff.html", DIFF_TEMPLATE).map_err(template_error)?;
+
+    let colors = ["#e74c3c", "#2ecc71", "#f39c12"];
+    let context = DiffContext {
+        title: "Luhnoxide Scan Comparison",
+        old: &diff.old,
+        new: &diff.new,
+        stats: diff.key_metrics().into_iter()
+            .map(|(label, value)| Stat { label, value })
+            .collect(),
+        charts: vec![bar_chart("Findings by Change", Status::ALL.into_iter()
+            .zip(colors)
+            .map(|(status, color)| (status.label().to_string(), diff.count(status), color))
+            .collect())],
+        files: diff.files().into_iter()
+            .map(|(file, counts)| DiffFile {
+                file,
+                risk: diff.risk(file),
+                added: counts.added,
+                resolved: counts.resolved,
+                unchanged: counts.unchanged,
+            })
+            .collect(),
+        sections: Status::ALL.into_iter()
+            .map(|status| DiffSection {
+                label: status.label(),
+                description: status.description(),
+                rows: diff.with_status(status)
+                    .map(|change| DiffRow {
+                        file: &change.card.file_path,
+                        line: change.card.line_number,
+                        previous_line: change.previous_line,
+                        brand: &change.card.brand,
+                        pan: &change.card.full_pan,
+                        fingerprint: &change.card.fingerprint,
+                        context: &change.card.context,
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    env.get_template("diff.html")
+        .and_then(|template| template.render(Serde(context)))
+        .map_err(template_error)
+}
+
+// Render the trend of stored scans with the built-in trend template
+pub fn render_trend(database: &str, points: &[TrendPoint]) -> io::Result<String> {
+    let (mut env, _) = environment(None)?;
+    env.add_template("trend.html", TREND_TEMPLATE).map_err(template_error)?;
+
+    let context = TrendContext {
+        title: "Luhnoxide Scan Trend",
+        database,
+        stats: trend::changes(points).into_iter()
+            .map(|(label, value)| Stat { label, value })
+            .collect(),
+        trend: trend_view(points),
+    };
+
+    env.get_template("trend.html")
+        .and_then(|template| template.render(Serde(context)))
+        .map_err(template_error)
+}
+
+// Check that a user-supplied template parses, so mistakes show up before a
+// long scan rather than after it
+pub fn check_template(source: &str) -> io::Result<()> {
+    environment(Some(source)).map(|_| ())
+}
+
+// Environment holding the built-in template and, if given, the user's one,
+// along with the name of the template to render
+fn environment(template: Option<&str>) -> io::Result<(Environment<'_>, &'static str)> {
+    let mut env = Environment::new();
+    // Escape everything whatever the template's file name
+    env.set_auto_escape_callback(|_| AutoEscape::Html);
+    env.add_template("charts.html", CHARTS_TEMPLATE).map_err(template_error)?;
+    env.add_template("report.html", REPORT_TEMPLATE).map_err(template_error)?;
+
+    match template {
+        Some(source) => {
+            env.add_template(CUSTOM_TEMPLATE, source).map_err(template_error)?;
+            Ok((env, CUSTOM_TEMPLATE))
+        }
+        None => Ok((env, "report.html")),
+    }
+}
+
+fn template_error(error: minijinja::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Template error: {}", error))
+}
+
+// Context snippet split into the text before, of and after the match
+fn split_snippet(card: &CardMatch) -> (&str, &str, &str) {
+    let (context_start, context_end) = card.context_span;
+    let line = &card.line_content;
+    let in_order = context_start <= card.match_start
+        && card.match_start <= card.match_end
+        && card.match_end <= context_end
+        && context_end <= line.len();
+
+    if !in_order || [context_start, card.match_start, card.match_end, context_end].iter().any(|&i| !line.is_char_boundary(i)) {
+        // No usable spans (e.g. results loaded from an older version)
+        return (&card.context, "", "");
+    }
+    (
+        &line[context_start..card.match_start],
+        &line[card.match_start..card.match_end],
+        &line[card.match_end..context_end],
+    )
+}
+
+// JSON safe to place inside a <script> element: '<' is escaped so no string
+// can close the element
+fn embed_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| "[]".to_string())
+        .replace('<', "\\u003c")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_report;
+
+    #[test]
+    fn reports_show_masked_pans_and_escape_scanned_text() {
+        let (summary, findings) = test_report(&[
+            ("/srv/<script>alert(1)</script>.txt", "a<i>x</i> 4532015112830366"),
+            ("/srv/clean.txt", "nothing here"),
+        ]);
+        let html = render_report(&summary, &findings, None, &[]).unwrap();
+
+        assert!(html.contains("453201XXXXXX0366"));
+        assert!(!html.contains("4532015112830366"));
+        assert!(html.contains("&lt;script&gt;alert(1)"));
+        assert!(!html.contains("<script>alert(1)"));
+        assert!(!html.contains("<i>x</i>"));
+        // Embedded findings cannot close the script element they live in
+        assert!(html.contains("\\u003ci>x\\u003c/i> "));
+    }
+
+    #[test]
+    fn custom_templates_are_escaped_too() {
+        let (summary, findings) = test_report(&[("/srv/<b>x</b>.txt", "pay 4532015112830366")]);
+        let template = "{% extends \"report.html\" %}{% block content %}\
+                        {% for file in summary.all_scanned_files %}[{{ file }}]{% endfor %}{% endblock %}";
+        let html = render_report(&summary, &findings, Some(template), &[]).unwrap();
+
+        assert!(html.contains("[&#x2f;srv&#x2f;&lt;b&gt;x&lt;&#x2f;b&gt;.txt]"), "{}", html);
+        check_template("{% if %}").unwrap_err();
+    }
+
+    #[test]
+    fn branded_templates_override_blocks_and_keep_the_rest() {
+        let (summary, findings) = test_report(&[("/srv/a.txt", "pay 4532015112830366")]);
+        let template = "{% extends \"report.html\" %}\
+                        {% block header %}<h1>Acme QSA</h1>{{ super() }}{% endblock %}\
+                        {% block footer %}<p>Confidential</p>{% endblock %}";
+        let html = render_report(&summary, &findings, Some(template), &[]).unwrap();
+
+        assert!(html.contains("<h1>Acme QSA</h1>\n        <h1>Luhnoxide Credit Card Scan Report</h1>"));
+        assert!(html.contains("<p>Confidential</p>"));
+        assert!(!html.contains("Generated by Luhnoxide"));
+        assert!(html.contains("<h2>Compliance Status</h2>"));
+        assert!(html.contains("453201XXXXXX0366"));
+
+        // A template can also stand alone and use the report's values
+        let html = render_report(&summary, &findings, Some("{{ summary.total_cards_found }} {{ clean_percentage }}"), &[])
+            .unwrap();
+        assert_eq!(html, "1 0.0%");
+
+        let error = check_template("{% extends \"missing.html\" %}").and_then(|_| {
+            render_report(&summary, &findings, Some("{% extends \"missing.html\" %}"), &[]).map(|_| ())
+        }).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("Template error: "), "{}", error);
+    }
+}
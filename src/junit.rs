@@ -0,0 +1,203 @@
+// JUnit XML report for CI systems
+//
+// Each scanned file is a testcase: files with findings fail, with the masked
+// findings in the failure message, files that could not be read are skipped
+// and the rest pass. Jenkins, GitLab and most other CI servers render this
+// natively, so a scan step in a pipeline shows up like a failing test.
+//
+// A scan comparison from `luhnoxide diff` has a testcase per file with
+// findings in either scan: files that still hold cards fail and files whose
+// findings were all resolved pass, which suits remediation verification.
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::diff::ScanDiff;
+use crate::{CardMatch, ScanSummary};
+
+const SUITE_NAME: &str = "luhnoxide";
+
+// Render the report
+pub fn render(summary: &ScanSummary, findings: &[CardMatch]) -> String {
+    let mut xml = String::new();
+
+    // Writing to a String cannot fail
+    let _ = write_report(&mut xml, summary, findings);
+    xml
+}
+
+fn write_report(xml: &mut String, summary: &ScanSummary, findings: &[CardMatch]) -> std::fmt::Result {
+    let mut by_file: HashMap<&str, Vec<&CardMatch>> = HashMap::new();
+    for card in findings {
+        by_file.entry(&card.file_path).or_default().push(card);
+    }
+    let skipped: HashSet<&str> = summary.skipped_files.iter().map(String::as_str).collect();
+
+    let tests = summary.all_scanned_files.len();
+    let failures = summary.all_scanned_files.iter().filter(|file| by_file.contains_key(file.as_str())).count();
+    let skips = summary.all_scanned_files.iter()
+        .filter(|file| skipped.contains(file.as_str()) && !by_file.contains_key(file.as_str()))
+        .count();
+    let timestamp = chrono::NaiveDateTime::parse_from_str(&summary.scan_date, "%Y-%m-%d %H:%M:%S")
+        .map(|date| date.format("%Y-%m-%dT%H:%M:%S").to_string())
+        .unwrap_or_else(|_| summary.scan_date.clone());
+
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(xml, r#"<testsuites name="{0}" tests="{1}" failures="{2}" errors="0" skipped="{3}">"#,
+             SUITE_NAME, tests, failures, skips)?;
+    writeln!(xml, r#"  <testsuite name="{0}" tests="{1}" failures="{2}" errors="0" skipped="{3}" timestamp="{4}">"#,
+             SUITE_NAME, tests, failures, skips, escape(&timestamp))?;
+
+    for file in &summary.all_scanned_files {
+        write!(xml, r#"    <testcase classname="{0}" name="{1}" file="{1}""#, SUITE_NAME, escape(file))?;
+
+        if let Some(cards) = by_file.get_mut(file.as_str()) {
+            cards.sort_by_key(|card| (card.line_number, card.column_start));
+            let unique: HashSet<&str> = cards.iter().map(|card| card.fingerprint.as_str()).collect();
+            writeln!(xml, ">")?;
+            writeln!(xml, r#"      <failure type="CardDataFound" message="{} card number{} found ({} unique)">{}</failure>"#,
+                     cards.len(), plural(cards.len()), unique.len(), escape(&failure_details(cards)))?;
+            writeln!(xml, "    </testcase>")?;
+        } else if skipped.contains(file.as_str()) {
+            writeln!(xml, ">")?;
+            writeln!(xml, r#"      <skipped message="File could not be processed (binary, permission issues, etc.)"/>"#)?;
+            writeln!(xml, "    </testcase>")?;
+        } else {
+            writeln!(xml, "/>")?;
+        }
+    }
+
+    writeln!(xml, "  </testsuite>")?;
+    writeln!(xml, "</testsuites>")
+}
+
+// Render a scan comparison
+pub fn render_diff(diff: &ScanDiff) -> String {
+    let mut xml = String::new();
+    let _ = write_diff(&mut xml, diff);
+    xml
+}
+
+fn write_diff(xml: &mut String, diff: &ScanDiff) -> std::fmt::Result {
+    let files = diff.files();
+    let tests = files.len();
+    let failures = files.iter().filter(|(_, counts)| counts.added + counts.unchanged > 0).count();
+
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(xml, r#"<testsuites name="{0}-diff" tests="{1}" failures="{2}" errors="0" skipped="0">"#,
+             SUITE_NAME, tests, failures)?;
+    writeln!(xml, r#"  <testsuite name="{0}-diff" tests="{1}" failures="{2}" errors="0" skipped="0">"#,
+             SUITE_NAME, tests, failures)?;
+    writeln!(xml, "    <properties>")?;
+    writeln!(xml, r#"      <property name="old" value="{}"/>"#, escape(&diff.old))?;
+    writeln!(xml, r#"      <property name="new" value="{}"/>"#, escape(&diff.new))?;
+    writeln!(xml, "    </properties>")?;
+
+    for (file, counts) in &files {
+        write!(xml, r#"    <testcase classname="{0}" name="{1}" file="{1}""#, SUITE_NAME, escape(file))?;
+        if counts.added + counts.unchanged == 0 {
+            writeln!(xml, "/>")?;
+            continue;
+        }
+
+        let mut details = String::new();
+        for change in diff.changes.iter().filter(|change| change.card.file_path == *file) {
+            let card = &change.card;
+            let _ = writeln!(details, "{}: line {}, columns {}-{}: {} {} (fingerprint {})",
+                             change.status.label(), card.line_number, card.column_start, card.column_end,
+                             card.brand, card.full_pan, card.fingerprint);
+        }
+        writeln!(xml, ">")?;
+        writeln!(xml, r#"      <failure type="{}" message="{} added, {} unchanged, {} resolved">{}</failure>"#,
+                 if counts.added > 0 { "CardDataAdded" } else { "CardDataRemains" },
+                 counts.added, counts.unchanged, counts.resolved, escape(&details))?;
+        writeln!(xml, "    </testcase>")?;
+    }
+
+    writeln!(xml, "  </testsuite>")?;
+    writeln!(xml, "</testsuites>")
+}
+
+// One line per finding, plus its context snippet
+fn failure_details(cards: &[&CardMatch]) -> String {
+    let mut details = String::new();
+    for card in cards {
+        let _ = writeln!(details, "Line {}, columns {}-{}: {} {} (fingerprint {})",
+                         card.line_number, card.column_start, card.column_end,
+                         card.brand, card.full_pan, card.fingerprint);
+        let _ = writeln!(details, "  Context: {}", card.context);
+    }
+    details
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+// Text safe in an XML attribute or element. Control characters XML 1.0 does
+// not allow are replaced, since a file's content can contain anything.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => escaped.push('\u{FFFD}'),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_report;
+
+    #[test]
+    fn reports_fail_files_with_masked_findings_and_escape_scanned_text() {
+        let (mut summary, findings) = test_report(&[
+            ("/srv/\"a\" & <b>.txt", "x]]><y/>\u{1} 4532015112830366"),
+            ("/srv/clean.txt", "nothing here"),
+            ("/srv/blob.bin", ""),
+        ]);
+        summary.skipped_files.push("/srv/blob.bin".to_string());
+        let xml = render(&summary, &findings);
+
+        assert!(xml.contains(r#"<testsuite name="luhnoxide" tests="3" failures="1" errors="0" skipped="1""#));
+        assert!(xml.contains(r#"name="/srv/&quot;a&quot; &amp; &lt;b&gt;.txt""#));
+        assert!(xml.contains("x]]&gt;&lt;y/&gt;\u{FFFD} 453201XXXXXX0366"));
+        assert!(!xml.contains("4532015112830366"));
+        assert!(!xml.contains("<y/>"));
+        assert!(xml.contains(r#"<testcase classname="luhnoxide" name="/srv/clean.txt" file="/srv/clean.txt"/>"#));
+        assert!(xml.contains("<skipped message="));
+    }
+
+    #[test]
+    fn failures_count_and_list_findings_in_line_order() {
+        let (mut summary, mut findings) = test_report(&[
+            ("/srv/a.txt", "4532015112830366, 5425233430109903, 4532015112830366"),
+            ("/srv/partial.txt", "4532015112830366"),
+        ]);
+        // A file skipped part way through with findings still fails
+        summary.skipped_files.push("/srv/partial.txt".to_string());
+        summary.scan_date = "2026-10-18 09:30:00".to_string();
+        findings.reverse();
+        let xml = render(&summary, &findings);
+
+        assert!(xml.contains(r#"<testsuites name="luhnoxide" tests="2" failures="2" errors="0" skipped="0">"#));
+        assert!(xml.contains(r#"skipped="0" timestamp="2026-10-18T09:30:00">"#));
+        assert!(xml.contains(r#"<failure type="CardDataFound" message="3 card numbers found (2 unique)">Line 1, columns 1-17: Visa 453201XXXXXX0366"#));
+        assert!(xml.contains(r#"<failure type="CardDataFound" message="1 card number found (1 unique)">"#));
+        let details = &xml[xml.find("(2 unique)").unwrap()..xml.find("</failure>").unwrap()];
+        let columns: Vec<&str> = details.lines().filter_map(|line| line.split(", columns ").nth(1))
+            .map(|rest| rest.split(':').next().unwrap())
+            .collect();
+        assert_eq!(columns, ["1-17", "19-35", "37-53"]);
+        assert_eq!(details.matches("  Context: ").count(), 3);
+        assert!(!xml.contains("<skipped"));
+    }
+}
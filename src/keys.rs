@@ -0,0 +1,40 @@
+// Loading of secret keys for keyed operations (tokenization, fingerprints)
+use std::env;
+use std::fs;
+use std::io;
+
+// Keys shorter than this are rejected as too easy to brute force
+const MIN_KEY_LEN: usize = 16;
+
+// Read a key from `key_file` if given, otherwise from the `env_var` variable.
+// Surrounding whitespace (e.g. a trailing newline in a key file) is ignored.
+pub fn load_key(env_var: &str, key_file: Option<&str>) -> io::Result<Option<Vec<u8>>> {
+    let key = match key_file {
+        Some(path) => Some(fs::read(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("Cannot read key file {}: {}", path, e))
+        })?),
+        None => env::var(env_var).ok().map(String::into_bytes),
+    };
+
+    match key {
+        Some(key) => {
+            let key = key.trim_ascii().to_vec();
+            if key.len() < MIN_KEY_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Key must be at least {} bytes long", MIN_KEY_LEN),
+                ));
+            }
+            Ok(Some(key))
+        }
+        None => Ok(None),
+    }
+}
+
+// Like `load_key`, but fail with a hint when no key was supplied
+pub fn require_key(env_var: &str, key_file: Option<&str>, flag: &str) -> io::Result<Vec<u8>> {
+    load_key(env_var, key_file)?.ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("No key supplied: set {} or pass {}", env_var, flag),
+    ))
+}
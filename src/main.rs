@@ -1,14 +1,44 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 use regex::Regex;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufWriter, Write};
+use std::io::{self, BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Instant, Duration};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use serde::{Serialize, Deserialize};
-use chrono;
+use zeroize::{Zeroize, Zeroizing};
+
+mod audit;
+mod cache;
+mod db;
+mod diff;
+mod ecs;
+mod encrypt;
+mod filters;
+mod fingerprint;
+mod html;
+mod junit;
+mod keys;
+mod markdown;
+mod masking;
+mod pdf;
+mod quarantine;
+mod remediate;
+mod siem;
+mod tokenize;
+mod trend;
+mod webhook;
+mod xlsx;
+
+use encrypt::ReportWriter;
+use filters::{FilterConfig, FilterRejection};
+use fingerprint::Fingerprinter;
+use masking::MaskPolicy;
+use pdf::PdfReport;
+use remediate::RewriteOptions;
+use tokenize::Tokenizer;
 
 // Define credit card brand information
 struct CardBrand {
@@ -72,92 +102,176 @@ struct CardMatch {
     file_path: String,
     line_number: usize,
     line_content: String,
+    // Keyed hash of the PAN, safe to store and compare across scans
+    #[serde(default)]
+    fingerprint: String,
+    // Byte span of the match within line_content
+    #[serde(default)]
+    match_start: usize,
+    #[serde(default)]
+    match_end: usize,
+    // Spans of every card found on the same line
+    #[serde(default, skip_serializing)]
+    line_spans: Vec<(usize, usize)>,
+    // Position of the match in the file: byte offset and 1-based character
+    // columns within the line (end exclusive)
+    #[serde(default)]
+    byte_offset: u64,
+    #[serde(default)]
+    column_start: usize,
+    #[serde(default)]
+    column_end: usize,
+    // A few characters either side of the match
+    #[serde(default)]
+    context: String,
+    // Byte span of the context within line_content
+    #[serde(default, skip_serializing)]
+    context_span: (usize, usize),
 }
 
 impl CardMatch {
-    fn to_string(&self, show_full: bool) -> String {
-        let pan_display = if show_full {
-            format!("Full PAN: {}", self.full_pan)
-        } else {
-            format!("Masked PAN: {}", self.masked_pan())
-        };
+    fn to_string(&self, show_full: bool, policy: &MaskPolicy) -> String {
+        // Work on a sanitized copy unless full PANs were requested
+        let card = if show_full { self.clone() } else { self.masked_copy(policy) };
         
-        // Create a sanitized version of the line content
-        let sanitized_line = if !show_full {
-            self.mask_line_content()
+        let pan_display = if show_full {
+            format!("Full PAN: {}", card.full_pan)
         } else {
-            self.line_content.clone()
+            format!("Masked PAN: {}", card.full_pan)
         };
         
         format!(
-            "File: {}\nLine: {}\nBrand: {}\nPAN Length: {}\nBIN: {}\nLast Four: {}\n{}\nLine Content: {}\n",
-            self.file_path,
-            self.line_number,
-            self.brand,
-            self.length,
-            self.bin,
-            self.last_four,
+            "File: {}\nLine: {}\nColumns: {}-{}\nByte Offset: {}\nBrand: {}\nPAN Length: {}\nBIN: {}\nLast Four: {}\n{}\nFingerprint: {}\nContext: {}\nLine Content: {}\n",
+            card.file_path,
+            card.line_number,
+            card.column_start,
+            card.column_end,
+            card.byte_offset,
+            card.brand,
+            card.length,
+            card.bin,
+            card.last_four,
             pan_display,
-            sanitized_line.trim()
+            card.fingerprint,
+            card.context,
+            card.line_content.trim()
         )
     }
     
-    fn masked_pan(&self) -> String {
-        // Keep BIN (first 6) and last 4 digits, mask the middle with 'X'
-        let masked_middle = "X".repeat(self.length - 10);
-        format!("{}{}{}", &self.bin, masked_middle, &self.last_four)
+    fn masked_pan(&self, policy: &MaskPolicy) -> String {
+        policy.mask(&self.full_pan)
     }
     
-    fn mask_line_content(&self) -> String {
-        // Create a regex to find the card number in various formats
-        let card_digits_only = self.full_pan.clone();
-        let mut masked_line = self.line_content.clone();
+    // Copy of this match with every PAN-derived field masked per the policy,
+    // as used by all masked output formats. The cleartext is never copied, and
+    // masking the copy again with the same policy changes nothing.
+    fn masked_copy(&self, policy: &MaskPolicy) -> CardMatch {
+        let line_content = self.mask_line_content(policy);
+        let context = self.masked_context(&line_content);
+        
+        // Spans move if the mask character is wider than a digit
+        let remap = |offset| remap_offset(&self.line_content, &line_content, offset);
+        let remap_span = |(start, end)| (remap(start), remap(end));
         
-        // Handle cards with no separators
-        if masked_line.contains(&card_digits_only) {
-            masked_line = masked_line.replace(&card_digits_only, &self.masked_pan());
-            return masked_line;
+        CardMatch {
+            brand: self.brand.clone(),
+            full_pan: self.masked_pan(policy),
+            bin: policy.visible_bin(&self.full_pan),
+            last_four: policy.visible_last_four(&self.full_pan),
+            length: self.length,
+            file_path: self.file_path.clone(),
+            line_number: self.line_number,
+            fingerprint: self.fingerprint.clone(),
+            match_start: remap(self.match_start),
+            match_end: remap(self.match_end),
+            line_spans: self.line_spans.iter().copied().map(remap_span).collect(),
+            byte_offset: self.byte_offset,
+            column_start: self.column_start,
+            column_end: self.column_end,
+            context_span: remap_span(self.context_span),
+            line_content,
+            context,
+        }
+    }
+    
+    // Cut the context window out of the masked line. Masking keeps every
+    // character in place, so the window sits at the same character positions.
+    fn masked_context(&self, masked_line: &str) -> String {
+        let (start, end) = self.context_span;
+        if end <= start || end > self.line_content.len() {
+            // No recorded span (e.g. loaded from older results)
+            return String::new();
         }
         
-        // Handle cards with spaces or dashes
-        // Try common formats: groups of 4, groups of 4 with last group of 3-7
-        let patterns = [
-            // 4-4-4-4 format (16 digits with spaces)
-            format!(
-                "{} {} {} {}", 
-                &card_digits_only[0..4], 
-                &card_digits_only[4..8], 
-                &card_digits_only[8..12], 
-                &card_digits_only[12..16]
-            ),
-            // 4-4-4-4 format (16 digits with dashes)
-            format!(
-                "{}-{}-{}-{}", 
-                &card_digits_only[0..4], 
-                &card_digits_only[4..8], 
-                &card_digits_only[8..12], 
-                &card_digits_only[12..16]
-            ),
-        ];
+        let skip = self.line_content[..start].chars().count();
+        let take = self.line_content[start..end].chars().count();
+        masked_line.chars().skip(skip).take(take).collect()
+    }
+    
+    fn mask_line_content(&self, policy: &MaskPolicy) -> String {
+        // Mask every card found on the line by its recorded position
+        let own_span = [(self.match_start, self.match_end)];
+        let spans: &[(usize, usize)] = if !self.line_spans.is_empty() {
+            &self.line_spans
+        } else if self.match_end > self.match_start {
+            &own_span
+        } else {
+            // Matches loaded from older results carry no spans
+            return self.mask_pan_in(&self.line_content, policy);
+        };
         
-        for pattern in patterns {
-            if masked_line.contains(&pattern) {
-                // For simplicity, replace with masked version without separators
-                // A more sophisticated approach would preserve the original format
-                masked_line = masked_line.replace(&pattern, &self.masked_pan());
-                break;
-            }
+        policy.mask_spans(&self.line_content, spans)
+    }
+    
+    // Mask this card's PAN wherever it appears in the given text
+    fn mask_pan_in(&self, text: &str, policy: &MaskPolicy) -> String {
+        if text == self.line_content && !self.line_spans.is_empty() {
+            return self.mask_line_content(policy);
         }
         
-        masked_line
+        // Otherwise look for the PAN's digits in any separator layout
+        policy.mask_spans(text, &masking::find_pan(text, &self.full_pan))
+    }
+}
+
+// Wipe PAN material from memory as soon as a match is dropped
+impl Drop for CardMatch {
+    fn drop(&mut self) {
+        self.full_pan.zeroize();
+        self.bin.zeroize();
+        self.last_four.zeroize();
+        self.line_content.zeroize();
+        self.context.zeroize();
     }
 }
 
+// Byte offset in `masked` of the character at byte `offset` in `original`.
+// Masking keeps every character in place, so only the byte width can differ.
+fn remap_offset(original: &str, masked: &str, offset: usize) -> usize {
+    if original.len() == masked.len() || !original.is_char_boundary(offset) {
+        return offset;
+    }
+    let index = original[..offset].chars().count();
+    masked.char_indices().nth(index).map(|(i, _)| i).unwrap_or(masked.len())
+}
+
+// A card, identified by its fingerprint, that was found in more than one file
+#[derive(Serialize, Deserialize)]
+struct SharedCard {
+    fingerprint: String,
+    brand: String,
+    masked_pan: String,
+    files: Vec<String>,
+}
+
 // Structure to hold scan statistics and summary
 #[derive(Serialize, Deserialize)]
 struct ScanSummary {
     scan_date: String,
     scan_duration: String,
+    // The same duration in seconds, for consumers that need a number
+    #[serde(default)]
+    scan_duration_seconds: f64,
     total_files_scanned: usize,
     total_directories_scanned: usize,
     total_files_with_cards: usize,
@@ -168,6 +282,17 @@ struct ScanSummary {
     skipped_files: Vec<String>,
     total_size_scanned_mb: f64,
     all_scanned_files: Vec<String>, // New field to store all scanned file paths
+    // Distinct cards by fingerprint, as opposed to occurrences
+    unique_cards_found: usize,
+    unique_card_type_counts: HashMap<String, usize>,
+    unique_cards_per_file: HashMap<String, usize>,
+    cards_in_multiple_files: Vec<SharedCard>,
+    // Files answered from --cache without being read, and files scanned and
+    // stored in it
+    #[serde(default)]
+    cache_hits: usize,
+    #[serde(default)]
+    cache_misses: usize,
 }
 
 impl ScanSummary {
@@ -175,6 +300,7 @@ impl ScanSummary {
         ScanSummary {
             scan_date: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
             scan_duration: "0s".to_string(),
+            scan_duration_seconds: 0.0,
             total_files_scanned: 0,
             total_directories_scanned: 0,
             total_files_with_cards: 0,
@@ -189,10 +315,17 @@ impl ScanSummary {
             skipped_files: Vec::new(),
             total_size_scanned_mb: 0.0,
             all_scanned_files: Vec::new(),
+            unique_cards_found: 0,
+            unique_card_type_counts: HashMap::new(),
+            unique_cards_per_file: HashMap::new(),
+            cards_in_multiple_files: Vec::new(),
+            cache_hits: 0,
+            cache_misses: 0,
         }
     }
     
     fn update_duration(&mut self, duration: Duration) {
+        self.scan_duration_seconds = duration.as_secs_f64();
         let seconds = duration.as_secs();
         if seconds < 60 {
             self.scan_duration = format!("{}s", seconds);
@@ -221,300 +354,196 @@ impl ScanSummary {
         self.all_scanned_files.push(file_path.to_string());
     }
     
-    // Generate HTML report
-    fn to_html(&self) -> String {
-        let mut html = String::from(r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Luhnoxide Card Scan Report</title>
-    <style>
-        body {
-            font-family: Arial, sans-serif;
-            line-height: 1.6;
-            margin: 0;
-            padding: 20px;
-            color: #333;
-        }
-        .container {
-            max-width: 1000px;
-            margin: 0 auto;
-        }
-        h1 {
-            color: #2c3e50;
-            border-bottom: 2px solid #3498db;
-            padding-bottom: 10px;
-        }
-        h2 {
-            color: #2c3e50;
-            margin-top: 30px;
-        }
-        .summary-box {
-            background-color: #f8f9fa;
-            border-radius: 5px;
-            padding: 15px;
-            margin-bottom: 20px;
-            box-shadow: 0 1px 3px rgba(0,0,0,0.1);
-        }
-        .stat-grid {
-            display: grid;
-            grid-template-columns: repeat(auto-fill, minmax(200px, 1fr));
-            gap: 15px;
-            margin-bottom: 20px;
-        }
-        .stat-item {
-            background-color: #ffffff;
-            border-left: 4px solid #3498db;
-            padding: 10px 15px;
-            box-shadow: 0 1px 2px rgba(0,0,0,0.1);
-        }
-        .stat-value {
-            font-size: 24px;
-            font-weight: bold;
-            color: #2c3e50;
-        }
-        .stat-label {
-            font-size: 14px;
-            color: #7f8c8d;
-        }
-        table {
-            width: 100%;
-            border-collapse: collapse;
-            margin-bottom: 20px;
-        }
-        th, td {
-            padding: 10px;
-            text-align: left;
-            border-bottom: 1px solid #ddd;
-        }
-        th {
-            background-color: #f2f2f2;
-            font-weight: bold;
-        }
-        tr:hover {
-            background-color: #f5f5f5;
-        }
-        .file-list {
-            max-height: 200px;
-            overflow-y: auto;
-            border: 1px solid #ddd;
-            padding: 10px;
-            margin-bottom: 20px;
-        }
-        .risk-high {
-            color: #e74c3c;
-        }
-        .risk-medium {
-            color: #f39c12;
-        }
-        .risk-low {
-            color: #2ecc71;
-        }
-        .footer {
-            margin-top: 30px;
-            border-top: 1px solid #ddd;
-            padding-top: 10px;
-            font-size: 12px;
-            color: #7f8c8d;
-        }
-        .chart {
-            height: 300px;
-            margin-bottom: 20px;
-        }
-    </style>
-</head>
-<body>
-    <div class="container">
-        <h1>Luhnoxide Credit Card Scan Report</h1>
-        
-        <div class="summary-box">
-            <p><strong>Scan Date:</strong> "#);
-        
-        html.push_str(&self.scan_date);
-        html.push_str(r#"</p>
-            <p><strong>Scan Duration:</strong> "#);
-        
-        html.push_str(&self.scan_duration);
-        html.push_str(r#"</p>
-        </div>
-        
-        <h2>Key Metrics</h2>
-        <div class="stat-grid">
-            <div class="stat-item">
-                <div class="stat-value">"#);
-        
-        html.push_str(&self.total_files_scanned.to_string());
-        html.push_str(r#"</div>
-                <div class="stat-label">Files Scanned</div>
-            </div>
-            <div class="stat-item">
-                <div class="stat-value">"#);
-        
-        html.push_str(&self.total_directories_scanned.to_string());
-        html.push_str(r#"</div>
-                <div class="stat-label">Directories Scanned</div>
-            </div>
-            <div class="stat-item">
-                <div class="stat-value">"#);
-        
-        html.push_str(&self.total_cards_found.to_string());
-        html.push_str(r#"</div>
-                <div class="stat-label">Card Numbers Found</div>
-            </div>
-            <div class="stat-item">
-                <div class="stat-value">"#);
+    // Count distinct cards overall, per brand and per file, and find cards
+    // that occur in several files. Cards are told apart by fingerprint only.
+    fn record_unique_cards(&mut self, results: &[CardMatch], policy: &MaskPolicy) {
+        let mut cards: HashMap<&str, (&CardMatch, BTreeSet<&str>)> = HashMap::new();
+        let mut cards_per_file: HashMap<&str, HashSet<&str>> = HashMap::new();
         
-        html.push_str(&self.total_files_with_cards.to_string());
-        html.push_str(r#"</div>
-                <div class="stat-label">Files Containing Cards</div>
-            </div>
-            <div class="stat-item">
-                <div class="stat-value">"#);
-        
-        html.push_str(&self.clean_files.to_string());
-        html.push_str(r#"</div>
-                <div class="stat-label">Clean Files</div>
-            </div>
-            <div class="stat-item">
-                <div class="stat-value">"#);
-        
-        html.push_str(&format!("{:.2}", self.total_size_scanned_mb));
-        html.push_str(r#"</div>
-                <div class="stat-label">Total Size (MB)</div>
-            </div>
-        </div>
-        
-        <h2>Card Type Distribution</h2>
-        <table>
-            <tr>
-                <th>Card Brand</th>
-                <th>Count</th>
-                <th>Percentage</th>
-            </tr>"#);
+        for card in results {
+            cards.entry(&card.fingerprint)
+                .or_insert_with(|| (card, BTreeSet::new()))
+                .1.insert(&card.file_path);
+            cards_per_file.entry(&card.file_path).or_default().insert(&card.fingerprint);
+        }
         
-        let total_cards = self.total_cards_found as f64;
-        for (brand, count) in &self.card_type_counts {
-            let percentage = if total_cards > 0.0 {
-                (*count as f64 / total_cards) * 100.0
-            } else {
-                0.0
-            };
-            
-            html.push_str(&format!(r#"
-            <tr>
-                <td>{}</td>
-                <td>{}</td>
-                <td>{:.1}%</td>
-            </tr>"#, brand, count, percentage));
+        self.unique_cards_found = cards.len();
+        self.unique_card_type_counts.clear();
+        for (card, _) in cards.values() {
+            *self.unique_card_type_counts.entry(card.brand.clone()).or_insert(0) += 1;
         }
         
-        html.push_str(r#"
-        </table>
+        self.unique_cards_per_file = cards_per_file.into_iter()
+            .map(|(file, fingerprints)| (file.to_string(), fingerprints.len()))
+            .collect();
         
-        <h2>Risk Assessment</h2>"#);
+        self.cards_in_multiple_files = cards.into_iter()
+            .filter(|(_, (_, files))| files.len() > 1)
+            .map(|(fingerprint, (card, files))| SharedCard {
+                fingerprint: fingerprint.to_string(),
+                brand: card.brand.clone(),
+                masked_pan: card.masked_pan(policy),
+                files: files.into_iter().map(str::to_string).collect(),
+            })
+            .collect();
+        self.cards_in_multiple_files.sort_by(|a, b| {
+            b.files.len().cmp(&a.files.len()).then_with(|| a.fingerprint.cmp(&b.fingerprint))
+        });
+    }
+    
+    // Files with cards, most distinct cards first
+    fn files_by_unique_cards(&self) -> Vec<(&String, &usize)> {
+        let mut files: Vec<_> = self.unique_cards_per_file.iter().collect();
+        files.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        files
+    }
+    
+    // Text section for unique card counts, optionally limiting list lengths
+    fn write_unique_cards<W: Write>(&self, writer: &mut W, limit: Option<usize>) -> io::Result<()> {
+        let limit = limit.unwrap_or(usize::MAX);
         
-        // High Risk Files
-        if !self.files_by_risk["high"].is_empty() {
-            html.push_str(r#"
-        <h3 class="risk-high">High Risk Files</h3>
-        <p>Files containing many credit card numbers or highly sensitive data:</p>
-        <div class="file-list">"#);
-            
-            for file in &self.files_by_risk["high"] {
-                html.push_str(&format!("<p>{}</p>", file));
-            }
-            
-            html.push_str(r#"
-        </div>"#);
+        writeln!(writer, "\nUnique Cards per File:")?;
+        let files = self.files_by_unique_cards();
+        for (file, count) in files.iter().take(limit) {
+            writeln!(writer, "  {}: {}", file, count)?;
         }
-        
-        // Medium Risk Files
-        if !self.files_by_risk["medium"].is_empty() {
-            html.push_str(r#"
-        <h3 class="risk-medium">Medium Risk Files</h3>
-        <p>Files containing some credit card numbers:</p>
-        <div class="file-list">"#);
-            
-            for file in &self.files_by_risk["medium"] {
-                html.push_str(&format!("<p>{}</p>", file));
-            }
-            
-            html.push_str(r#"
-        </div>"#);
+        if files.len() > limit {
+            writeln!(writer, "  ... and {} more files", files.len() - limit)?;
         }
         
-        // Low Risk Files
-        if !self.files_by_risk["low"].is_empty() {
-            html.push_str(r#"
-        <h3 class="risk-low">Low Risk Files</h3>
-        <p>Files containing few credit card numbers:</p>
-        <div class="file-list">"#);
-            
-            for file in &self.files_by_risk["low"] {
-                html.push_str(&format!("<p>{}</p>", file));
+        if !self.cards_in_multiple_files.is_empty() {
+            writeln!(writer, "\nCards Found in Multiple Files:")?;
+            for card in self.cards_in_multiple_files.iter().take(limit) {
+                writeln!(writer, "  {} ({}, fingerprint {}) appears in {} files:",
+                         card.masked_pan, card.brand, card.fingerprint, card.files.len())?;
+                for file in &card.files {
+                    writeln!(writer, "    {}", file)?;
+                }
+            }
+            if self.cards_in_multiple_files.len() > limit {
+                writeln!(writer, "  ... and {} more cards", self.cards_in_multiple_files.len() - limit)?;
             }
-            
-            html.push_str(r#"
-        </div>"#);
         }
         
-        // Clean Status
-        let clean_percentage = if self.total_files_scanned > 0 {
+        Ok(())
+    }
+    
+    // Headline figures shared by the HTML and PDF reports
+    fn key_metrics(&self) -> Vec<(&'static str, String)> {
+        let mut metrics = vec![
+            ("Files Scanned", self.total_files_scanned.to_string()),
+            ("Directories Scanned", self.total_directories_scanned.to_string()),
+            ("Card Numbers Found", self.total_cards_found.to_string()),
+            ("Unique Cards", self.unique_cards_found.to_string()),
+            ("Files Containing Cards", self.total_files_with_cards.to_string()),
+            ("Clean Files", self.clean_files.to_string()),
+            ("Total Size (MB)", format!("{:.2}", self.total_size_scanned_mb)),
+        ];
+        if self.used_cache() {
+            metrics.push(("Cache Hits", self.cache_hits.to_string()));
+            metrics.push(("Cache Misses", self.cache_misses.to_string()));
+        }
+        metrics
+    }
+    
+    fn used_cache(&self) -> bool {
+        self.cache_hits + self.cache_misses > 0
+    }
+    
+    // (brand, count, unique, percentage of all findings) per brand
+    fn brand_rows(&self) -> Vec<(&str, usize, usize, f64)> {
+        let total_cards = self.total_cards_found as f64;
+        self.card_type_counts.iter()
+            .map(|(brand, count)| {
+                let percentage = if total_cards > 0.0 {
+                    (*count as f64 / total_cards) * 100.0
+                } else {
+                    0.0
+                };
+                let unique = self.unique_card_type_counts.get(brand).copied().unwrap_or(0);
+                (brand.as_str(), *count, unique, percentage)
+            })
+            .collect()
+    }
+    
+    fn clean_percentage(&self) -> f64 {
+        if self.total_files_scanned > 0 {
             (self.clean_files as f64 / self.total_files_scanned as f64) * 100.0
         } else {
             0.0
-        };
+        }
+    }
+    
+    // Self-contained HTML report from the built-in or a user-supplied template
+    fn to_html(&self, findings: &[CardMatch], template: Option<&str>, trend: &[trend::TrendPoint]) -> io::Result<String> {
+        html::render_report(self, findings, template, trend)
+    }
+    
+    // Native PDF with the same sections as the HTML report
+    fn to_pdf(&self) -> Vec<u8> {
+        let mut pdf = PdfReport::new("Luhnoxide Credit Card Scan Report", &[
+            ("Scan Date", self.scan_date.clone()),
+            ("Scan Duration", self.scan_duration.clone()),
+        ]);
         
-        html.push_str(&format!(r#"
-        <h2>Compliance Status</h2>
-        <div class="summary-box">
-            <p><strong>{:.1}%</strong> of scanned files are free of credit card data.</p>
-        </div>
-        "#, clean_percentage));
+        pdf.heading("Key Metrics");
+        pdf.stats(&self.key_metrics());
         
-        // Add the complete list of scanned files
-        html.push_str(r#"
-        <h2>Scanned Files</h2>
-        <p>Complete list of all scanned files:</p>
-        <div class="file-list">"#);
+        pdf.heading("Card Type Distribution");
+        let brand_rows: Vec<Vec<String>> = self.brand_rows().into_iter()
+            .map(|(brand, count, unique, percentage)| {
+                vec![brand.to_string(), count.to_string(), unique.to_string(), format!("{:.1}%", percentage)]
+            })
+            .collect();
+        pdf.table(&["Card Brand", "Count", "Unique", "Percentage"], &[0.4, 0.2, 0.2, 0.2], &brand_rows);
         
-        for file in &self.all_scanned_files {
-            html.push_str(&format!("<p>{}</p>", file));
-        }
+        pdf.heading("Unique Cards per File");
+        let file_rows: Vec<Vec<String>> = self.files_by_unique_cards().into_iter()
+            .map(|(file, count)| vec![file.clone(), count.to_string()])
+            .collect();
+        pdf.table(&["File", "Unique Cards"], &[0.8, 0.2], &file_rows);
         
-        html.push_str(r#"
-        </div>"#);
+        if !self.cards_in_multiple_files.is_empty() {
+            pdf.heading("Cards Found in Multiple Files");
+            let shared_rows: Vec<Vec<String>> = self.cards_in_multiple_files.iter()
+                .map(|card| vec![
+                    card.masked_pan.clone(),
+                    card.brand.clone(),
+                    card.fingerprint.clone(),
+                    format!("Appears in {} files:\n{}", card.files.len(), card.files.join("\n")),
+                ])
+                .collect();
+            pdf.table(&["Masked PAN", "Brand", "Fingerprint", "Files"], &[0.2, 0.14, 0.3, 0.36], &shared_rows);
+        }
         
-        if !self.skipped_files.is_empty() {
-            html.push_str(r#"
-        <h2>Skipped Files</h2>
-        <p>Files that could not be processed (binary, permission issues, etc.):</p>
-        <div class="file-list">"#);
-            
-            for file in &self.skipped_files {
-                html.push_str(&format!("<p>{}</p>", file));
+        pdf.heading("Risk Assessment");
+        let tiers = [
+            ("high", "High Risk Files", "Files containing many credit card numbers or highly sensitive data:", pdf::RISK_HIGH),
+            ("medium", "Medium Risk Files", "Files containing some credit card numbers:", pdf::RISK_MEDIUM),
+            ("low", "Low Risk Files", "Files containing few credit card numbers:", pdf::RISK_LOW),
+        ];
+        for (level, title, description, color) in tiers {
+            if !self.files_by_risk[level].is_empty() {
+                pdf.subheading(title, color);
+                pdf.paragraph(description);
+                pdf.list(&self.files_by_risk[level]);
             }
-            
-            html.push_str(r#"
-        </div>"#);
         }
         
-        html.push_str(r#"
-        <div class="footer">
-            <p>Generated by Luhnoxide - Credit Card Scanner</p>
-        </div>
-    </div>
-</body>
-</html>"#);
+        pdf.heading("Compliance Status");
+        pdf.callout(&format!("{:.1}% of scanned files are free of credit card data.", self.clean_percentage()));
         
-        html
-    }
-    
-    // Generate PDF-friendly HTML
-    fn to_pdf_html(&self) -> String {
-        // Simplified version for PDF conversion
-        self.to_html()
+        pdf.heading("Scanned Files");
+        pdf.paragraph("Complete list of all scanned files:");
+        pdf.list(&self.all_scanned_files);
+        
+        if !self.skipped_files.is_empty() {
+            pdf.heading("Skipped Files");
+            pdf.paragraph("Files that could not be processed (binary, permission issues, etc.):");
+            pdf.list(&self.skipped_files);
+        }
+        
+        pdf.finish()
     }
 }
 
@@ -526,6 +555,12 @@ enum OutputFormat {
     Csv,
     Html,
     Pdf,
+    Markdown,
+    Xlsx,
+    Junit,
+    Cef,
+    Leef,
+    Ecs,
 }
 
 impl OutputFormat {
@@ -535,6 +570,12 @@ impl OutputFormat {
             "csv" => OutputFormat::Csv,
             "html" => OutputFormat::Html,
             "pdf" => OutputFormat::Pdf,
+            "markdown" => OutputFormat::Markdown,
+            "xlsx" => OutputFormat::Xlsx,
+            "junit" => OutputFormat::Junit,
+            "cef" => OutputFormat::Cef,
+            "leef" => OutputFormat::Leef,
+            "ecs" => OutputFormat::Ecs,
             _ => OutputFormat::Text,
         }
     }
@@ -565,15 +606,13 @@ fn is_valid_luhn(number: &str) -> bool {
     sum % 10 == 0 && sum > 0
 }
 
-// Determine the card brand based on pattern and length
+// Determine the card brand based on pattern and length. The number is read in
+// place, digits only, so no copy of the PAN is made
 fn identify_card_brand(number: &str) -> Option<&'static str> {
-    let cleaned_number = number.replace(['-', ' '], "");
-    
     for brand in CARD_BRANDS {
-        if let Ok(re) = Regex::new(brand.pattern) {
-            if re.is_match(&cleaned_number) && brand.lengths.contains(&cleaned_number.len()) {
-                return Some(brand.name);
-            }
+        if let Ok(re) = Regex::new(brand.pattern)
+            && re.is_match(number) && brand.lengths.contains(&number.len()) {
+            return Some(brand.name);
         }
     }
     None
@@ -600,50 +639,231 @@ fn collect_files(path: &Path, files: &mut Vec<PathBuf>, dir_count: &mut usize) -
     Ok(())
 }
 
+// Pattern to find potential credit card numbers with optional separators
+// (spaces, dashes or dots, in any grouping)
+const CARD_PATTERN: &str = r"(?:^|\D)([0-9](?:[0-9.\s-]){11,18}[0-9])(?:\D|$)";
+
+// Settings shared by every file scan
+struct ScanConfig {
+    card_pattern: Regex,
+    filter: FilterConfig,
+    fingerprinter: Fingerprinter,
+    // Characters of context kept on each side of a match
+    context_chars: usize,
+    // When set, matches are masked with this policy as soon as they are found
+    // and the cleartext PAN is not kept
+    discard_pans: Option<MaskPolicy>,
+}
+
+// Scan settings shared by the module tests: no false-positive filter, a fixed
+// fingerprint key and 10 characters of context
+#[cfg(test)]
+fn test_scan_config(discard_pans: Option<MaskPolicy>) -> ScanConfig {
+    ScanConfig {
+        card_pattern: Regex::new(CARD_PATTERN).unwrap(),
+        filter: FilterConfig::disabled(),
+        fingerprinter: Fingerprinter::from_key(b"0123456789abcdef".to_vec()),
+        context_chars: 10,
+        discard_pans,
+    }
+}
+
+// A summarised scan for the report tests, one line per file, with findings
+// masked as the reports receive them by default
+#[cfg(test)]
+fn test_report(files: &[(&str, &str)]) -> (ScanSummary, Vec<CardMatch>) {
+    let policy = MaskPolicy::from_config("first6last4", "X").unwrap();
+    let config = test_scan_config(Some(policy.clone()));
+    let mut summary = ScanSummary::new();
+    let mut findings = Vec::new();
+
+    for (file, line) in files {
+        let cards = scan_line(&config, line, 1, 0, file).0;
+        summary.add_scanned_file(file);
+        if !cards.is_empty() {
+            summary.total_files_with_cards += 1;
+            summary.add_file_by_risk("low", file);
+        }
+        findings.extend(cards);
+    }
+
+    summary.total_files_scanned = files.len();
+    summary.clean_files = summary.total_files_scanned - summary.total_files_with_cards;
+    summary.total_cards_found = findings.len();
+    for card in &findings {
+        summary.increment_card_type(&card.brand);
+    }
+    summary.record_unique_cards(&findings, &policy);
+    (summary, findings)
+}
+
+// Find the card numbers on a single line starting at `line_offset` bytes into
+// the file. Candidates dropped by the false-positive filter are returned
+// separately.
+fn scan_line(config: &ScanConfig, line: &str, line_number: usize, line_offset: u64,
+             file_path: &str) -> (Vec<CardMatch>, Vec<FilterRejection>) {
+    let mut line_matches = Vec::new();
+    let mut line_rejections = Vec::new();
+    
+    for cap in config.card_pattern.captures_iter(line) {
+        if let Some(matched) = cap.get(1) {
+            let potential_card: Zeroizing<String> = Zeroizing::new(
+                matched.as_str().chars().filter(|c| c.is_ascii_digit()).collect()
+            );
+            
+            // Check if the number is a valid length and passes Luhn
+            if (13..=19).contains(&potential_card.len()) && is_valid_luhn(&potential_card)
+                && let Some(brand) = identify_card_brand(&potential_card) {
+                // Columns are 1-based character positions, the end is exclusive
+                let column_start = line[..matched.start()].chars().count() + 1;
+                let column_end = column_start + matched.as_str().chars().count();
+                let (context_start, context_end) = context_bounds(line, matched.start(), matched.end(), config.context_chars);
+                
+                let match_details = CardMatch {
+                    brand: brand.to_string(),
+                    full_pan: potential_card.to_string(),
+                    bin: potential_card.chars().take(6).collect(),
+                    last_four: potential_card.chars().rev().take(4).collect::<String>().chars().rev().collect(),
+                    length: potential_card.len(),
+                    file_path: file_path.to_string(),
+                    line_number,
+                    line_content: line.to_string(),
+                    fingerprint: config.fingerprinter.fingerprint(&potential_card),
+                    match_start: matched.start(),
+                    match_end: matched.end(),
+                    line_spans: Vec::new(),
+                    byte_offset: line_offset + matched.start() as u64,
+                    column_start,
+                    column_end,
+                    context: line[context_start..context_end].to_string(),
+                    context_span: (context_start, context_end),
+                };
+                
+                // Drop obvious non-PAN patterns before recording the match
+                let reasons = config.filter.check(&potential_card, line, matched.start(), matched.end());
+                if !reasons.is_empty() {
+                    line_rejections.push(FilterRejection { card: match_details, reasons });
+                    continue;
+                }
+                
+                line_matches.push(match_details);
+            }
+        }
+    }
+    
+    // Every match remembers where all cards on its line are, so masking the
+    // line for one of them hides the others too
+    let spans: Vec<(usize, usize)> = line_matches.iter()
+        .map(|card| (card.match_start, card.match_end))
+        .collect();
+    for card in &mut line_matches {
+        card.line_spans = spans.clone();
+    }
+    
+    // Keep nothing but the masked form once the fingerprint has been taken
+    if let Some(policy) = &config.discard_pans {
+        (line_matches, line_rejections) = mask_line_results(policy, &line_matches, &line_rejections);
+    }
+    
+    (line_matches, line_rejections)
+}
+
+// Masked copies of a line's matches and rejected candidates. Rejected
+// candidates hide every candidate on the line, not only the cards.
+fn mask_line_results(policy: &MaskPolicy, line_matches: &[CardMatch],
+                     line_rejections: &[FilterRejection]) -> (Vec<CardMatch>, Vec<FilterRejection>) {
+    let candidate_spans: Vec<(usize, usize)> = line_matches.iter()
+        .chain(line_rejections.iter().map(|rejection| &rejection.card))
+        .map(|card| (card.match_start, card.match_end))
+        .collect();
+    let masked_rejections = line_rejections.iter()
+        .map(|rejection| {
+            let mut card = rejection.card.clone();
+            card.line_spans = candidate_spans.clone();
+            FilterRejection { card: card.masked_copy(policy), reasons: rejection.reasons.clone() }
+        })
+        .collect();
+    
+    (line_matches.iter().map(|card| card.masked_copy(policy)).collect(), masked_rejections)
+}
+
+// Byte range of up to `context_chars` characters either side of a match
+fn context_bounds(line: &str, start: usize, end: usize, context_chars: usize) -> (usize, usize) {
+    let context_start = if context_chars == 0 {
+        start
+    } else {
+        line[..start].char_indices()
+            .rev()
+            .nth(context_chars - 1)
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    };
+    let context_end = line[end..].char_indices()
+        .nth(context_chars)
+        .map(|(index, _)| end + index)
+        .unwrap_or(line.len());
+    
+    (context_start, context_end)
+}
+
+// Initial capacity of the line buffer used while scanning a file
+const LINE_BUFFER_CAPACITY: usize = 64 * 1024;
+
 // Scan a single file for credit card numbers
 fn scan_file(file_path: &Path, results: &Arc<Mutex<Vec<CardMatch>>>, 
              files_with_cards: &Arc<Mutex<HashSet<String>>>, 
-             skipped_files: &Arc<Mutex<Vec<String>>>) -> io::Result<()> {
+             skipped_files: &Arc<Mutex<Vec<String>>>,
+             rejections: &Arc<Mutex<Vec<FilterRejection>>>,
+             config: &ScanConfig, cache: Option<&cache::ScanCache>) -> io::Result<()> {
     // Skip binary files or files that can't be opened as text
     match File::open(file_path) {
         Ok(file) => {
+            // What the file held before reading it, and its findings, are
+            // stored in the cache along with a hash of its content
+            let mut cache_entry = cache.and_then(|cache| cache.start_entry(&file));
+            
             // Try to treat as a text file
-            let reader = io::BufReader::new(file);
+            let mut reader = io::BufReader::new(cache::HashingReader::new(file, cache_entry.is_some()));
             
-            // Pattern to find potential credit card numbers with optional separators
-            let card_pattern = Regex::new(r"(?:^|\D)([0-9](?:[0-9-\s]){11,18}[0-9])(?:\D|$)").unwrap();
             let file_path_str = file_path.to_string_lossy().to_string();
             let mut found_card = false;
+            // Lines are read into a single buffer that is wiped when the scan
+            // ends. Reserving room up front keeps lines shorter than that from
+            // leaving copies behind when the buffer grows; longer lines and
+            // BufReader's own buffer are not wiped
+            let mut buffer = Zeroizing::new(String::with_capacity(LINE_BUFFER_CAPACITY));
+            let mut line_number = 0;
+            let mut line_offset: u64 = 0;
 
-            for (line_number, line_result) in reader.lines().enumerate() {
-                match line_result {
-                    Ok(line) => {
-                        for cap in card_pattern.captures_iter(&line) {
-                            if let Some(matched) = cap.get(1) {
-                                let potential_card = matched.as_str().replace(['-', ' '], "");
-                                
-                                // Check if the number is a valid length and passes Luhn
-                                if (13..=19).contains(&potential_card.len()) && is_valid_luhn(&potential_card) {
-                                    if let Some(brand) = identify_card_brand(&potential_card) {
-                                        let match_details = CardMatch {
-                                            brand: brand.to_string(),
-                                            full_pan: potential_card.clone(),
-                                            bin: potential_card.chars().take(6).collect(),
-                                            last_four: potential_card.chars().rev().take(4).collect::<String>().chars().rev().collect(),
-                                            length: potential_card.len(),
-                                            file_path: file_path_str.clone(),
-                                            line_number: line_number + 1,
-                                            line_content: line.clone(),
-                                        };
-                                        
-                                        if let Ok(mut results_vec) = results.lock() {
-                                            results_vec.push(match_details);
-                                        }
-                                        
-                                        found_card = true;
-                                    }
-                                }
+            // Read line by line, keeping track of each line's byte offset
+            loop {
+                buffer.clear();
+                match reader.read_line(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(bytes_read) => {
+                        line_number += 1;
+                        let line = buffer.strip_suffix('\n').unwrap_or(&buffer[..]);
+                        let line = line.strip_suffix('\r').unwrap_or(line);
+                        
+                        let (line_matches, line_rejections) = scan_line(
+                            config, line, line_number, line_offset, &file_path_str
+                        );
+                        line_offset += bytes_read as u64;
+                        
+                        if let Some(entry) = &mut cache_entry {
+                            entry.add_line(&line_matches, &line_rejections);
+                        }
+                        
+                        if !line_rejections.is_empty() && let Ok(mut rejections_vec) = rejections.lock() {
+                            rejections_vec.extend(line_rejections);
+                        }
+                        
+                        if !line_matches.is_empty() {
+                            if let Ok(mut results_vec) = results.lock() {
+                                results_vec.extend(line_matches);
                             }
+                            
+                            found_card = true;
                         }
                     },
                     Err(_) => {
@@ -651,15 +871,20 @@ fn scan_file(file_path: &Path, results: &Arc<Mutex<Vec<CardMatch>>>,
                         if let Ok(mut skipped) = skipped_files.lock() {
                             skipped.push(file_path_str.clone());
                         }
+                        if let Some(entry) = cache_entry {
+                            entry.finish(file_path_str, None);
+                        }
                         return Ok(());
                     }
                 }
             }
             
-            if found_card {
-                if let Ok(mut files_with_cards_set) = files_with_cards.lock() {
-                    files_with_cards_set.insert(file_path_str);
-                }
+            if found_card && let Ok(mut files_with_cards_set) = files_with_cards.lock() {
+                files_with_cards_set.insert(file_path_str.clone());
+            }
+            
+            if let Some(entry) = cache_entry {
+                entry.finish(file_path_str, reader.into_inner().finish());
             }
             
             Ok(())
@@ -678,6 +903,7 @@ fn main() -> io::Result<()> {
         .version("1.0")
         .author("Your Name <your.email@example.com>")
         .about("Scans files for valid credit card numbers using the Luhn algorithm")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("input")
                 .short("i")
@@ -685,7 +911,7 @@ fn main() -> io::Result<()> {
                 .value_name("INPUT")
                 .help("Input file or directory paths (comma-separated)")
                 .takes_value(true)
-                .required(true),
+                .required_unless("verify-audit-log"),
         )
         .arg(
             Arg::with_name("output")
@@ -700,17 +926,147 @@ fn main() -> io::Result<()> {
                 .short("f")
                 .long("format")
                 .value_name("FORMAT")
-                .help("Output format: text (default), json, csv, html, pdf")
+                .help("Output format: text (default), json, csv, html, pdf, markdown, xlsx, junit, cef, leef, ecs")
                 .takes_value(true)
-                .possible_values(&["text", "json", "csv", "html", "pdf"])
+                .possible_values(&["text", "json", "csv", "html", "pdf", "markdown", "xlsx", "junit", "cef", "leef", "ecs"])
                 .default_value("text"),
         )
+        .arg(
+            Arg::with_name("template")
+                .long("template")
+                .value_name("FILE")
+                .help("Template for the HTML report, in place of the built-in one")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("syslog")
+                .long("syslog")
+                .value_name("TARGET")
+                .help("Also send one event per finding plus a summary event to a syslog collector: udp://host:port, tcp://host:port or unix:///path")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("syslog-format")
+                .long("syslog-format")
+                .value_name("FORMAT")
+                .help("Event format for --syslog messages: cef (default) or leef")
+                .takes_value(true)
+                .possible_values(&["cef", "leef"])
+                .default_value("cef"),
+        )
+        .arg(
+            Arg::with_name("db")
+                .long("db")
+                .value_name("FILE")
+                .help("Also record the scan, its files and masked findings in this SQLite database")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .value_name("FILE")
+                .help("Reuse the findings of files unchanged since the last scan from this SQLite cache, and update it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rebuild-cache")
+                .long("rebuild-cache")
+                .help("Discard the cache's contents and scan every file")
+                .requires("cache")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("webhook")
+                .long("webhook")
+                .value_name("URL")
+                .help("POST a JSON summary of the scan with the top masked findings to this URL when it completes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("webhook-format")
+                .long("webhook-format")
+                .value_name("FORMAT")
+                .help("Payload for --webhook: json (default), slack or teams")
+                .takes_value(true)
+                .possible_values(&["json", "slack", "teams"])
+                .default_value("json"),
+        )
+        .arg(
+            Arg::with_name("webhook-secret-file")
+                .long("webhook-secret-file")
+                .value_name("FILE")
+                .help("Secret used to sign webhook requests with HMAC-SHA256 (default: LUHNOXIDE_WEBHOOK_SECRET)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("webhook-retries")
+                .long("webhook-retries")
+                .value_name("N")
+                .help("Times to retry a failed webhook delivery, with exponential backoff (default: 3)")
+                .takes_value(true)
+                .default_value("3"),
+        )
         .arg(
             Arg::with_name("no-mask")
                 .long("no-mask")
                 .help("Disable masking of middle digits in credit card numbers")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("audit-log")
+                .long("audit-log")
+                .value_name("FILE")
+                .help("Audit log recording every run that writes full PANs (default: luhnoxide-audit.jsonl)")
+                .takes_value(true)
+                .default_value(audit::DEFAULT_LOG),
+        )
+        .arg(
+            Arg::with_name("verify-audit-log")
+                .long("verify-audit-log")
+                .value_name("FILE")
+                .help("Check the hash chain of an audit log and exit")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("discard-pans")
+                .long("discard-pans")
+                .help("Never retain full PANs: mask each match as soon as it is found")
+                .takes_value(false)
+                .conflicts_with_all(&["no-mask", "redact", "tokenize"]),
+        )
+        .arg(
+            Arg::with_name("encrypt-to")
+                .long("encrypt-to")
+                .value_name("RECIPIENT")
+                .help("Encrypt the output file to an age X25519 public key (age1...); may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("recipients-file")
+                .long("recipients-file")
+                .value_name("FILE")
+                .help("File of age recipients (one per line) to encrypt the output file to")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mask-policy")
+                .long("mask-policy")
+                .value_name("POLICY")
+                .help("Digits left visible when masking: first6last4 (default), first8last4, last4, full")
+                .takes_value(true)
+                .possible_values(masking::POLICIES)
+                .default_value("first6last4"),
+        )
+        .arg(
+            Arg::with_name("mask-char")
+                .long("mask-char")
+                .value_name("CHAR")
+                .help("Character used to mask digits")
+                .takes_value(true)
+                .default_value("X"),
+        )
         .arg(
             Arg::with_name("summary")
                 .short("s")
@@ -718,19 +1074,359 @@ fn main() -> io::Result<()> {
                 .help("Generate a summary report")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("fp-filter")
+                .long("fp-filter")
+                .help("Reject Luhn-valid matches that look like non-PAN data (repeated digits, sequences, IDs, timestamps)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("fp-checks")
+                .long("fp-checks")
+                .value_name("CHECKS")
+                .help("False-positive checks to run (comma-separated): repeated, sequence, entropy, embedded, hex, timestamp, all")
+                .takes_value(true)
+                .default_value(filters::ALL_CHECKS),
+        )
+        .arg(
+            Arg::with_name("min-entropy")
+                .long("min-entropy")
+                .value_name("BITS")
+                .help("Minimum Shannon entropy of a match's digits for the entropy check")
+                .takes_value(true)
+                .default_value("2.0"),
+        )
+        .arg(
+            Arg::with_name("explain")
+                .long("explain")
+                .help("Print every candidate rejected by the false-positive filter and why to stderr")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("fingerprint-key-file")
+                .long("fingerprint-key-file")
+                .value_name("FILE")
+                .help("File holding the PAN fingerprint key (default: LUHNOXIDE_FINGERPRINT_KEY, else a one-off key per run)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context")
+                .long("context")
+                .value_name("CHARS")
+                .help("Characters of surrounding text to include on each side of a match")
+                .takes_value(true)
+                .default_value("20"),
+        )
+        .arg(
+            Arg::with_name("redact")
+                .long("redact")
+                .help("Rewrite files containing card numbers in place with the PANs masked")
+                .takes_value(false)
+                .conflicts_with("tokenize"),
+        )
+        .arg(
+            Arg::with_name("tokenize")
+                .long("tokenize")
+                .help("Rewrite files in place, replacing each PAN with a Luhn-valid token that keeps its BIN and last four")
+                .takes_value(false)
+                .requires("vault"),
+        )
+        .arg(
+            Arg::with_name("vault")
+                .long("vault")
+                .value_name("FILE")
+                .help("Token vault file mapping tokens back to PANs. It holds the cleartext card numbers, unencrypted, \
+                       protected only by owner-only file permissions (appended to)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("token-key-file")
+                .long("token-key-file")
+                .value_name("FILE")
+                .help("File holding the tokenization key (default: LUHNOXIDE_TOKEN_KEY environment variable)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Preview remediation changes as a diff without modifying any file")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("backup-dir")
+                .long("backup-dir")
+                .value_name("DIR")
+                .help("Copy each file into DIR (mirroring its path) before remediating it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("quarantine")
+                .long("quarantine")
+                .value_name("DIR")
+                .help("Move flagged files into DIR, leaving a tombstone note and recording them in a manifest")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("quarantine-level")
+                .long("quarantine-level")
+                .value_name("LEVEL")
+                .help("Lowest risk level to quarantine: low, medium or high (default)")
+                .takes_value(true)
+                .possible_values(&["low", "medium", "high"])
+                .default_value("high"),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare two scan results and report added, resolved and unchanged findings")
+                .arg(
+                    Arg::with_name("old")
+                        .value_name("OLD")
+                        .help("Earlier results: JSON or NDJSON output, or a --db database")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("new")
+                        .value_name("NEW")
+                        .help("Later results, in any of the same forms; the same database compares its last two scans")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("old-scan")
+                        .long("old-scan")
+                        .value_name("ID")
+                        .help("Scan id to read when OLD is a database (default: the latest)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("new-scan")
+                        .long("new-scan")
+                        .value_name("ID")
+                        .help("Scan id to read when NEW is a database (default: the latest)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("OUTPUT")
+                        .help("Output file path (default: console)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format: text (default), json, csv, html, pdf, markdown, xlsx, junit, cef, leef, ecs")
+                        .takes_value(true)
+                        .possible_values(&["text", "json", "csv", "html", "pdf", "markdown", "xlsx", "junit", "cef", "leef", "ecs"])
+                        .default_value("text"),
+                )
+                .arg(
+                    Arg::with_name("mask-policy")
+                        .long("mask-policy")
+                        .value_name("POLICY")
+                        .help("Digits left visible when masking: first6last4 (default), first8last4, last4, full")
+                        .takes_value(true)
+                        .possible_values(masking::POLICIES)
+                        .default_value("first6last4"),
+                )
+                .arg(
+                    Arg::with_name("mask-char")
+                        .long("mask-char")
+                        .value_name("CHAR")
+                        .help("Character used to mask digits")
+                        .takes_value(true)
+                        .default_value("X"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Reports built from the scans stored with --db")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("trend")
+                        .about("Show findings, unique cards, files with cards and compliance over time")
+                        .arg(
+                            Arg::with_name("db")
+                                .long("db")
+                                .value_name("FILE")
+                                .help("SQLite database written by --db")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("last")
+                                .long("last")
+                                .value_name("N")
+                                .help("Only the latest N scans")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("inputs")
+                                .long("inputs")
+                                .value_name("PATHS")
+                                .help("Only scans of these input paths, as given to -i (default: all scans)")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .short("o")
+                                .long("output")
+                                .value_name("OUTPUT")
+                                .help("Output file path (default: console)")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("format")
+                                .short("f")
+                                .long("format")
+                                .value_name("FORMAT")
+                                .help("Output format: text (default), json, csv, markdown, html")
+                                .takes_value(true)
+                                .possible_values(&["text", "json", "csv", "markdown", "html"])
+                                .default_value("text"),
+                        ),
+                ),
+        )
         .get_matches();
 
+    if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        return diff::run(diff_matches);
+    }
+
+    if let Some(trend_matches) = matches.subcommand_matches("report").and_then(|report| report.subcommand_matches("trend")) {
+        return trend::run(trend_matches);
+    }
+    
+    if let Some(log_path) = matches.value_of("verify-audit-log") {
+        let (entries, last_hash) = audit::verify_log(Path::new(log_path)).map_err(|e| io::Error::new(
+            e.kind(),
+            format!("Audit log {} failed verification: {}", log_path, e),
+        ))?;
+        println!("Audit log {} verified: {} entries, hash chain intact, last hash {}", log_path, entries, last_hash);
+        return Ok(());
+    }
+    
     // Check if we should show full PANs (default is to mask)
     let show_full = matches.is_present("no-mask");
     
+    // Configure the false-positive filter stage
+    let filter = if matches.is_present("fp-filter") {
+        let min_entropy = matches.value_of("min-entropy").unwrap_or("2.0").parse::<f64>()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "--min-entropy must be a number"))?;
+        FilterConfig::from_checks(matches.value_of("fp-checks").unwrap_or(filters::ALL_CHECKS), min_entropy)?
+    } else {
+        FilterConfig::disabled()
+    };
+    
+    // Keyed fingerprints let the same card be recognised without its PAN
+    let fingerprinter = Fingerprinter::from_config(matches.value_of("fingerprint-key-file"))?;
+    
+    let context_chars = matches.value_of("context").unwrap_or("20").parse::<usize>()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "--context must be a non-negative number"))?;
+    
+    // Masking policy applied to every masked output
+    let mask_policy = MaskPolicy::from_config(
+        matches.value_of("mask-policy").unwrap_or("first6last4"),
+        matches.value_of("mask-char").unwrap_or("X"),
+    )?;
+    
+    // Full PANs are only kept when something downstream needs them: unmasked
+    // output or in-place remediation. Otherwise matches are masked during the
+    // scan, and --discard-pans makes that a guarantee.
+    let needs_full_pans = show_full || matches.is_present("redact") || matches.is_present("tokenize");
+    let discard_pans = if needs_full_pans { None } else { Some(mask_policy.clone()) };
+    
+    let scan_config = Arc::new(ScanConfig {
+        card_pattern: Regex::new(CARD_PATTERN).unwrap(),
+        filter,
+        fingerprinter,
+        context_chars,
+        discard_pans,
+    });
+    
+    // Public keys to encrypt the output file to
+    let recipients = encrypt::parse_recipients(
+        matches.values_of("encrypt-to").into_iter().flatten(),
+        matches.value_of("recipients-file"),
+    )?;
+    if !recipients.is_empty() && matches.value_of("output").is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Encrypted output requires -o/--output"));
+    }
+    
+    // Connect to the syslog collector before scanning so a bad target fails early
+    let syslog_format = siem::EventFormat::from_str(matches.value_of("syslog-format").unwrap_or("cef"))?;
+    let mut syslog = match matches.value_of("syslog") {
+        Some(target) => Some(siem::SyslogSink::connect(target).map_err(|e| io::Error::new(
+            e.kind(),
+            format!("Cannot connect to syslog target {}: {}", target, e),
+        ))?),
+        None => None,
+    };
+    
+    // Open the history database before scanning so a bad path fails early
+    let mut database = match matches.value_of("db") {
+        Some(path) => {
+            scan_config.fingerprinter.require_stable("--db")?;
+            Some(db::Database::open(Path::new(path))?)
+        }
+        None => None,
+    };
+    
+    // Open the cache before scanning so a bad path fails early
+    let cache = match matches.value_of("cache") {
+        Some(path) => Some(Arc::new(cache::ScanCache::open(
+            Path::new(path), &scan_config, &mask_policy, matches.is_present("rebuild-cache"),
+        )?)),
+        None => None,
+    };
+    
+    // Set up the webhook before scanning so a bad URL or secret fails early
+    let webhook = match matches.value_of("webhook") {
+        Some(url) => {
+            let format = webhook::PayloadFormat::from_str(matches.value_of("webhook-format").unwrap_or("json"))?;
+            let secret = keys::load_key(webhook::SECRET_ENV_VAR, matches.value_of("webhook-secret-file"))?;
+            let retries = matches.value_of("webhook-retries").unwrap_or("3").parse::<u32>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "--webhook-retries must be a non-negative number"))?;
+            Some(webhook::Notifier::new(url, format, secret, retries)?)
+        }
+        None => None,
+    };
+    
     // Determine output format
     let format_str = matches.value_of("format").unwrap_or("text");
     let output_format = OutputFormat::from_str(format_str);
     
+    // Load and parse the report template before scanning so mistakes fail early
+    let template = match matches.value_of("template") {
+        Some(path) => {
+            let source = fs::read_to_string(path).map_err(|e| io::Error::new(
+                e.kind(),
+                format!("Cannot read template {}: {}", path, e),
+            ))?;
+            html::check_template(&source)?;
+            Some(source)
+        }
+        None => None,
+    };
+    
     // Create summary object if summary report is requested
     let generate_summary = matches.is_present("summary") || 
+                          matches.is_present("quarantine") ||
                           format_str == "html" || 
-                          format_str == "pdf";
+                          format_str == "pdf" ||
+                          format_str == "markdown" ||
+                          format_str == "xlsx" ||
+                          format_str == "junit" ||
+                          format_str == "cef" ||
+                          format_str == "leef" ||
+                          format_str == "ecs" ||
+                          syslog.is_some() ||
+                          database.is_some() ||
+                          webhook.is_some();
     
     let start_time = Instant::now();
     let summary = if generate_summary {
@@ -747,7 +1443,7 @@ fn main() -> io::Result<()> {
     let mut files_to_scan: Vec<PathBuf> = Vec::new();
     let mut total_directories: usize = 0;
     
-    for input_path in input_paths {
+    for input_path in &input_paths {
         let path = Path::new(input_path);
         if path.is_dir() {
             total_directories += 1;
@@ -755,15 +1451,13 @@ fn main() -> io::Result<()> {
         collect_files(path, &mut files_to_scan, &mut total_directories)?;
     }
     
-    if let Some(ref summary_arc) = summary {
-        if let Ok(mut summary) = summary_arc.lock() {
-            summary.total_files_scanned = files_to_scan.len();
-            summary.total_directories_scanned = total_directories;
-            
-            // Add each file path to the summary
-            for file_path in &files_to_scan {
-                summary.add_scanned_file(&file_path.to_string_lossy());
-            }
+    if let Some(ref summary_arc) = summary && let Ok(mut summary) = summary_arc.lock() {
+        summary.total_files_scanned = files_to_scan.len();
+        summary.total_directories_scanned = total_directories;
+        
+        // Add each file path to the summary
+        for file_path in &files_to_scan {
+            summary.add_scanned_file(&file_path.to_string_lossy());
         }
     }
     
@@ -773,10 +1467,8 @@ fn main() -> io::Result<()> {
         .map(|meta| meta.len())
         .sum();
     
-    if let Some(ref summary_arc) = summary {
-        if let Ok(mut summary) = summary_arc.lock() {
-            summary.total_size_scanned_mb = total_size as f64 / (1024.0 * 1024.0);
-        }
+    if let Some(ref summary_arc) = summary && let Ok(mut summary) = summary_arc.lock() {
+        summary.total_size_scanned_mb = total_size as f64 / (1024.0 * 1024.0);
     }
     
     // Thread-safe storage for results
@@ -788,14 +1480,27 @@ fn main() -> io::Result<()> {
     // Set to track skipped files
     let skipped_files = Arc::new(Mutex::new(Vec::new()));
     
+    // Candidates dropped by the false-positive filter
+    let rejections = Arc::new(Mutex::new(Vec::new()));
+    
     // Process files in parallel
     let mut handles = vec![];
     for file_path in files_to_scan {
         let results_clone = Arc::clone(&results);
         let files_with_cards_clone = Arc::clone(&files_with_cards);
         let skipped_files_clone = Arc::clone(&skipped_files);
+        let rejections_clone = Arc::clone(&rejections);
+        let scan_config_clone = Arc::clone(&scan_config);
+        let cache_clone = cache.clone();
         let handle = thread::spawn(move || {
-            if let Err(e) = scan_file(&file_path, &results_clone, &files_with_cards_clone, &skipped_files_clone) {
+            // Files unchanged since the last run are answered from the cache
+            if let Some(cache) = &cache_clone
+                && cache.reuse(&file_path, &results_clone, &files_with_cards_clone, &skipped_files_clone,
+                               &rejections_clone) {
+                return;
+            }
+            if let Err(e) = scan_file(&file_path, &results_clone, &files_with_cards_clone, &skipped_files_clone,
+                                      &rejections_clone, &scan_config_clone, cache_clone.as_deref()) {
                 eprintln!("Error scanning file {:?}: {}", file_path, e);
                 if let Ok(mut skipped) = skipped_files_clone.lock() {
                     skipped.push(file_path.to_string_lossy().to_string());
@@ -810,107 +1515,159 @@ fn main() -> io::Result<()> {
         handle.join().unwrap();
     }
     
+    // Store what this run found for the next one
+    if let Some(ref cache) = cache {
+        cache.save(&input_paths)?;
+        eprintln!("Cache {}: {} file(s) reused, {} scanned", cache.path().display(), cache.hits(), cache.misses());
+    }
+    
+    if scan_config.fingerprinter.is_ephemeral() && let Ok(results_vec) = results.lock() && !results_vec.is_empty() {
+        eprintln!("Note: no fingerprint key configured ({} or --fingerprint-key-file); \
+                   fingerprints are only comparable within this run", fingerprint::KEY_ENV_VAR);
+    }
+    
+    // Report what the false-positive filter dropped
+    if matches.is_present("explain") && let Ok(rejections_vec) = rejections.lock() {
+        if !scan_config.filter.is_enabled() {
+            eprintln!("False-positive filter is disabled (use --fp-filter); nothing was rejected");
+        }
+        for rejection in rejections_vec.iter() {
+            eprintln!("{}", rejection.explain(&mask_policy));
+        }
+        if scan_config.filter.is_enabled() {
+            eprintln!("{} candidate(s) rejected by the false-positive filter", rejections_vec.len());
+        }
+    }
+    
     // Update summary information if needed
-    if let Some(ref summary_arc) = summary {
-        if let Ok(mut summary) = summary_arc.lock() {
-            if let Ok(results_vec) = results.lock() {
-                summary.total_cards_found = results_vec.len();
-                
-                // Count card types
-                for card in &*results_vec {
-                    summary.increment_card_type(&card.brand);
-                }
-            }
+    if let Some(ref summary_arc) = summary && let Ok(mut summary) = summary_arc.lock() {
+        if let Ok(results_vec) = results.lock() {
+            summary.total_cards_found = results_vec.len();
             
-            if let Ok(files_with_cards_set) = files_with_cards.lock() {
-                summary.total_files_with_cards = files_with_cards_set.len();
-                summary.clean_files = summary.total_files_scanned - summary.total_files_with_cards;
-                    // Risk assessment - categorize files
-                for file_path in &*files_with_cards_set {
-                    // Count card occurrences per file
-                    if let Ok(results_vec) = results.lock() {
-                        let cards_in_file = results_vec.iter()
-                            .filter(|card| card.file_path == *file_path)
-                            .count();
-                        
-                        // Simple risk assessment
-                        let risk_level = if cards_in_file > 10 {
-                            "high"
-                        } else if cards_in_file > 3 {
-                            "medium"
-                        } else {
-                            "low"
-                        };
-                        
-                        summary.add_file_by_risk(risk_level, file_path);
-                    }
-                }
+            // Count card types
+            for card in &*results_vec {
+                summary.increment_card_type(&card.brand);
             }
             
-            if let Ok(skipped) = skipped_files.lock() {
-                summary.skipped_files = skipped.clone();
+            summary.record_unique_cards(&results_vec, &mask_policy);
+        }
+        
+        if let Ok(files_with_cards_set) = files_with_cards.lock() {
+            summary.total_files_with_cards = files_with_cards_set.len();
+            summary.clean_files = summary.total_files_scanned - summary.total_files_with_cards;
+                // Risk assessment - categorize files
+            for file_path in &*files_with_cards_set {
+                // Count card occurrences per file
+                if let Ok(results_vec) = results.lock() {
+                    let cards_in_file = results_vec.iter()
+                        .filter(|card| card.file_path == *file_path)
+                        .count();
+                    
+                    // Simple risk assessment
+                    let risk_level = if cards_in_file > 10 {
+                        "high"
+                    } else if cards_in_file > 3 {
+                        "medium"
+                    } else {
+                        "low"
+                    };
+                    
+                    summary.add_file_by_risk(risk_level, file_path);
+                }
             }
-            
-            // Record scan duration
-            let duration = start_time.elapsed();
-            summary.update_duration(duration);
         }
+        
+        if let Ok(skipped) = skipped_files.lock() {
+            summary.skipped_files = skipped.clone();
+        }
+        
+        if let Some(ref cache) = cache {
+            summary.cache_hits = cache.hits();
+            summary.cache_misses = cache.misses();
+        }
+        
+        // Record scan duration
+        let duration = start_time.elapsed();
+        summary.update_duration(duration);
     }
     
+    // Every write of full PANs is recorded in the audit log before any of it
+    // happens: unmasked output and syslog events, the token vault, and
+    // backups of files about to be remediated
+    let dry_run = matches.is_present("dry-run");
+    let remediating = matches.is_present("redact") || matches.is_present("tokenize");
+    let mut full_pan_destinations = Vec::new();
+    if show_full {
+        full_pan_destinations.push(match matches.value_of("output") {
+            Some(path) => format!("output file {}", path),
+            None => "stdout".to_string(),
+        });
+        if let Some(target) = matches.value_of("syslog") {
+            full_pan_destinations.push(format!("syslog {}", target));
+        }
+        if remediating && dry_run {
+            full_pan_destinations.push("stdout (dry-run preview)".to_string());
+        }
+    }
+    if matches.is_present("tokenize") && !dry_run {
+        full_pan_destinations.push(format!("token vault {}", matches.value_of("vault").unwrap_or_default()));
+    }
+    if remediating && !dry_run && let Some(backup_dir) = matches.value_of("backup-dir") {
+        full_pan_destinations.push(format!("backups in {}", backup_dir));
+    }
+    if !full_pan_destinations.is_empty() && let Ok(results_vec) = results.lock() {
+        let log_path = matches.value_of("audit-log").unwrap_or(audit::DEFAULT_LOG);
+        audit::record_run(Path::new(log_path), &results_vec, matches.value_of("output"),
+                          &full_pan_destinations, format_str)?;
+    }
+    
+    // Findings as every output shows them: masked unless --no-mask. The report
+    // modules render whatever they are given, so this is the only place PANs
+    // are masked for output and every format must be fed from here.
+    let findings: Vec<CardMatch> = results.lock()
+        .map(|results_vec| results_vec.iter()
+            .map(|card| if show_full { card.clone() } else { card.masked_copy(&mask_policy) })
+            .collect())
+        .unwrap_or_default();
+    
     // Output results
     if let Some(output_path) = matches.value_of("output") {
-        let output_file = File::create(output_path)?;
-        let mut writer = BufWriter::new(output_file);
+        let mut writer = ReportWriter::create(Path::new(output_path), &recipients)?;
         
         if let Ok(results_vec) = results.lock() {
             match output_format {
                 OutputFormat::Json => {
                     // Output as JSON
-                    // Create a vector of sanitized results for output
-                    let output_data: Vec<_> = results_vec.iter().map(|card| {
-                        let mut card_output = card.clone();
-                        if !show_full {
-                            // Replace full PAN with masked version if masking is enabled
-                            card_output.full_pan = card.masked_pan();
-                            // Also mask the line content
-                            card_output.line_content = card.mask_line_content();
-                        }
-                        card_output
-                    }).collect();
-                    
-                    serde_json::to_writer_pretty(&mut writer, &output_data)?;
+                    serde_json::to_writer_pretty(&mut writer, &findings)?;
                 }
                 OutputFormat::Csv => {
                     // Output as CSV
-                    let mut csv_writer = csv::Writer::from_writer(writer);
+                    let mut csv_writer = csv::Writer::from_writer(&mut writer);
                     
                     // Write header
-                    csv_writer.write_record(&[
+                    csv_writer.write_record([
                         "Brand", "PAN Length", "BIN", "Last Four", 
                         if show_full { "Full PAN" } else { "Masked PAN" },
-                        "File Path", "Line Number", "Line Content"
+                        "File Path", "Line Number", "Column Start", "Column End", "Byte Offset",
+                        "Context", "Line Content", "Fingerprint"
                     ])?;
                     
                     // Write data rows
-                    for card in results_vec.iter() {
-                        let pan_field = if show_full { &card.full_pan } else { &card.masked_pan() };
-                        
-                        // Use sanitized line content if masking is enabled
-                        let sanitized_line = if !show_full {
-                            card.mask_line_content()
-                        } else {
-                            card.line_content.clone()
-                        };
-                        
-                        csv_writer.write_record(&[
+                    for card in &findings {
+                        csv_writer.write_record([
                             &card.brand,
                             &card.length.to_string(),
                             &card.bin,
                             &card.last_four,
-                            pan_field,
+                            &card.full_pan,
                             &card.file_path,
                             &card.line_number.to_string(),
-                            &sanitized_line
+                            &card.column_start.to_string(),
+                            &card.column_end.to_string(),
+                            &card.byte_offset.to_string(),
+                            &card.context,
+                            &card.line_content,
+                            &card.fingerprint
                         ])?;
                     }
                     
@@ -921,15 +1678,17 @@ fn main() -> io::Result<()> {
                     if let Some(ref summary_arc) = summary {
                         if let Ok(summary) = summary_arc.lock() {
                             if output_format == OutputFormat::Html {
-                                write!(writer, "{}", summary.to_html())?;
+                                // Chart this scan against the earlier stored scans of the same inputs
+                                let mut trend = match database {
+                                    Some(ref database) => database.scan_history(Some(input_paths_str), None)?,
+                                    None => Vec::new(),
+                                };
+                                if !trend.is_empty() {
+                                    trend.push(trend::TrendPoint::from_summary(&summary));
+                                }
+                                write!(writer, "{}", summary.to_html(&findings, template.as_deref(), &trend)?)?;
                             } else {
-                                // For PDF, we use the same HTML but it's converted externally
-                                write!(writer, "{}", summary.to_pdf_html())?;
-                                
-                                // Display instructions for converting HTML to PDF
-                                println!("HTML file for PDF generation has been created at {}", output_path);
-                                println!("To convert to PDF, use a browser or a tool like wkhtmltopdf:");
-                                println!("wkhtmltopdf {} {}.pdf", output_path, output_path);
+                                writer.write_all(&summary.to_pdf())?;
                             }
                         }
                     } else {
@@ -937,42 +1696,88 @@ fn main() -> io::Result<()> {
                         writeln!(writer, "Summary generation was not enabled")?;
                     }
                 }
+                OutputFormat::Markdown => {
+                    // Generate Markdown report
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        write!(writer, "{}", markdown::render(&summary, &findings))?;
+                    }
+                }
+                OutputFormat::Xlsx => {
+                    // Generate Excel workbook
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        writer.write_all(&xlsx::render(&summary, &findings, show_full)?)?;
+                    }
+                }
+                OutputFormat::Junit => {
+                    // Generate JUnit XML report
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        write!(writer, "{}", junit::render(&summary, &findings))?;
+                    }
+                }
+                OutputFormat::Cef | OutputFormat::Leef => {
+                    // One SIEM event per line
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        let event_format = if output_format == OutputFormat::Cef { siem::EventFormat::Cef } else { siem::EventFormat::Leef };
+                        for event in siem::events(event_format, &summary, &findings) {
+                            writeln!(writer, "{}", event.message)?;
+                        }
+                    }
+                }
+                OutputFormat::Ecs => {
+                    // Elastic Common Schema documents as NDJSON
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        ecs::write_ndjson(&mut writer, &summary, &findings)?;
+                    }
+                }
                 OutputFormat::Text => {
                     // Output as text (default)
                     for card_match in results_vec.iter() {
-                        writeln!(writer, "{}\n", card_match.to_string(show_full))?;
+                        writeln!(writer, "{}\n", card_match.to_string(show_full, &mask_policy))?;
                     }
                     
                     // If summary was requested, add it at the end
-                    if let Some(ref summary_arc) = summary {
-                        if let Ok(summary) = summary_arc.lock() {
-                            writeln!(writer, "\n\n=== SUMMARY ===\n")?;
-                            writeln!(writer, "Scan Date: {}", summary.scan_date)?;
-                            writeln!(writer, "Scan Duration: {}", summary.scan_duration)?;
-                            writeln!(writer, "Total Files Scanned: {}", summary.total_files_scanned)?;
-                            writeln!(writer, "Total Directories Scanned: {}", summary.total_directories_scanned)?;
-                            writeln!(writer, "Total Size Scanned: {:.2} MB", summary.total_size_scanned_mb)?;
-                            writeln!(writer, "Files with Card Numbers: {}", summary.total_files_with_cards)?;
-                            writeln!(writer, "Clean Files: {}", summary.clean_files)?;
-                            writeln!(writer, "Total Card Numbers Found: {}", summary.total_cards_found)?;
-                            
-                            writeln!(writer, "\nCard Type Distribution:")?;
-                            for (brand, count) in &summary.card_type_counts {
-                                writeln!(writer, "  {}: {}", brand, count)?;
-                            }
-                            
-                            // Add file list section for text output
-                            writeln!(writer, "\nScanned Files:")?;
-                            for file in &summary.all_scanned_files {
-                                writeln!(writer, "  {}", file)?;
-                            }
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        writeln!(writer, "\n\n=== SUMMARY ===\n")?;
+                        writeln!(writer, "Scan Date: {}", summary.scan_date)?;
+                        writeln!(writer, "Scan Duration: {}", summary.scan_duration)?;
+                        writeln!(writer, "Total Files Scanned: {}", summary.total_files_scanned)?;
+                        writeln!(writer, "Total Directories Scanned: {}", summary.total_directories_scanned)?;
+                        writeln!(writer, "Total Size Scanned: {:.2} MB", summary.total_size_scanned_mb)?;
+                        if summary.used_cache() {
+                            writeln!(writer, "Cache Hits: {} (Misses: {})", summary.cache_hits, summary.cache_misses)?;
+                        }
+                        writeln!(writer, "Files with Card Numbers: {}", summary.total_files_with_cards)?;
+                        writeln!(writer, "Clean Files: {}", summary.clean_files)?;
+                        writeln!(writer, "Total Card Numbers Found: {}", summary.total_cards_found)?;
+                        writeln!(writer, "Unique Card Numbers Found: {}", summary.unique_cards_found)?;
+                        
+                        writeln!(writer, "\nCard Type Distribution:")?;
+                        for (brand, count) in &summary.card_type_counts {
+                            let unique = summary.unique_card_type_counts.get(brand).copied().unwrap_or(0);
+                            writeln!(writer, "  {}: {} ({} unique)", brand, count, unique)?;
+                        }
+                        
+                        summary.write_unique_cards(&mut writer, None)?;
+                        
+                        // Add file list section for text output
+                        writeln!(writer, "\nScanned Files:")?;
+                        for file in &summary.all_scanned_files {
+                            writeln!(writer, "  {}", file)?;
                         }
                     }
                 }
             }
         }
         
-        println!("Results written to {} in {} format", output_path, format_str);
+        let encrypted = writer.is_encrypted();
+        writer.finish()?;
+        
+        if encrypted {
+            println!("Results written to {} in {} format, encrypted to {} recipient(s)",
+                     output_path, format_str, recipients.len());
+        } else {
+            println!("Results written to {} in {} format", output_path, format_str);
+        }
     } else {
         // Output to console
         if let Ok(results_vec) = results.lock() {
@@ -981,114 +1786,132 @@ fn main() -> io::Result<()> {
             match output_format {
                 OutputFormat::Json => {
                     // Output as JSON to console
-                    // Create a vector of sanitized results for output
-                    let output_data: Vec<_> = results_vec.iter().map(|card| {
-                        let mut card_output = card.clone();
-                        if !show_full {
-                            // Replace full PAN with masked version if masking is enabled
-                            card_output.full_pan = card.masked_pan();
-                            // Also mask the line content
-                            card_output.line_content = card.mask_line_content();
-                        }
-                        card_output
-                    }).collect();
-                    
-                    println!("{}", serde_json::to_string_pretty(&output_data)?);
+                    println!("{}", serde_json::to_string_pretty(&findings)?);
                 }
                 OutputFormat::Csv => {
                     // Output as CSV to console
                     let mut csv_writer = csv::Writer::from_writer(io::stdout());
                     
                     // Write header
-                    csv_writer.write_record(&[
+                    csv_writer.write_record([
                         "Brand", "PAN Length", "BIN", "Last Four", 
                         if show_full { "Full PAN" } else { "Masked PAN" },
-                        "File Path", "Line Number", "Line Content"
+                        "File Path", "Line Number", "Column Start", "Column End", "Byte Offset",
+                        "Context", "Line Content", "Fingerprint"
                     ])?;
                     
                     // Write data rows
-                    for card in results_vec.iter() {
-                        let pan_field = if show_full { &card.full_pan } else { &card.masked_pan() };
-                        
-                        // Use sanitized line content if masking is enabled
-                        let sanitized_line = if !show_full {
-                            card.mask_line_content()
-                        } else {
-                            card.line_content.clone()
-                        };
-                        
-                        csv_writer.write_record(&[
+                    for card in &findings {
+                        csv_writer.write_record([
                             &card.brand,
                             &card.length.to_string(),
                             &card.bin,
                             &card.last_four,
-                            pan_field,
+                            &card.full_pan,
                             &card.file_path,
                             &card.line_number.to_string(),
-                            &sanitized_line
+                            &card.column_start.to_string(),
+                            &card.column_end.to_string(),
+                            &card.byte_offset.to_string(),
+                            &card.context,
+                            &card.line_content,
+                            &card.fingerprint
                         ])?;
                     }
                     
                     csv_writer.flush()?;
                 }
-                OutputFormat::Html | OutputFormat::Pdf => {
+                OutputFormat::Html | OutputFormat::Pdf | OutputFormat::Xlsx => {
                     // Cannot output HTML directly to console in a useful way
-                    println!("HTML/PDF format requires an output file to be specified with -o/--output");
+                    println!("HTML/PDF/XLSX format requires an output file to be specified with -o/--output");
                     println!("Please run again with an output file path");
                 }
+                OutputFormat::Markdown => {
+                    // Output Markdown report to console
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        print!("{}", markdown::render(&summary, &findings));
+                    }
+                }
+                OutputFormat::Junit => {
+                    // Output JUnit XML report to console
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        print!("{}", junit::render(&summary, &findings));
+                    }
+                }
+                OutputFormat::Cef | OutputFormat::Leef => {
+                    // Output SIEM events to console
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        let event_format = if output_format == OutputFormat::Cef { siem::EventFormat::Cef } else { siem::EventFormat::Leef };
+                        for event in siem::events(event_format, &summary, &findings) {
+                            println!("{}", event.message);
+                        }
+                    }
+                }
+                OutputFormat::Ecs => {
+                    // Output ECS documents to console
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        ecs::write_ndjson(&mut io::stdout(), &summary, &findings)?;
+                    }
+                }
                 OutputFormat::Text => {
                     // Output as text (default)
                     for card_match in results_vec.iter() {
-                        println!("{}\n", card_match.to_string(show_full));
+                        println!("{}\n", card_match.to_string(show_full, &mask_policy));
                     }
                     
                     // If summary was requested, add it at the end
-                    if let Some(ref summary_arc) = summary {
-                        if let Ok(summary) = summary_arc.lock() {
-                            println!("\n\n=== SUMMARY ===\n");
-                            println!("Scan Date: {}", summary.scan_date);
-                            println!("Scan Duration: {}", summary.scan_duration);
-                            println!("Total Files Scanned: {}", summary.total_files_scanned);
-                            println!("Total Directories Scanned: {}", summary.total_directories_scanned);
-                            println!("Total Size Scanned: {:.2} MB", summary.total_size_scanned_mb);
-                            println!("Files with Card Numbers: {}", summary.total_files_with_cards);
-                            println!("Clean Files: {}", summary.clean_files);
-                            println!("Total Card Numbers Found: {}", summary.total_cards_found);
-                            
-                            println!("\nCard Type Distribution:");
-                            for (brand, count) in &summary.card_type_counts {
-                                println!("  {}: {}", brand, count);
-                            }
-                            
-                            // Display risk assessment
-                            if !summary.files_by_risk["high"].is_empty() {
-                                println!("\nHigh Risk Files: {}", summary.files_by_risk["high"].len());
-                            }
-                            if !summary.files_by_risk["medium"].is_empty() {
-                                println!("Medium Risk Files: {}", summary.files_by_risk["medium"].len());
-                            }
-                            if !summary.files_by_risk["low"].is_empty() {
-                                println!("Low Risk Files: {}", summary.files_by_risk["low"].len());
-                            }
-                            
-                            // Calculate compliance percentage
-                            let compliance_percentage = if summary.total_files_scanned > 0 {
-                                (summary.clean_files as f64 / summary.total_files_scanned as f64) * 100.0
-                            } else {
-                                0.0
-                            };
-                            println!("\nCompliance Status: {:.1}% of files are free of card data", compliance_percentage);
-                            
-                            // Add file list section for console output
-                            println!("\nScanned Files:");
-                            // Limit to first 10 files to avoid flooding the console
-                            let display_limit = std::cmp::min(10, summary.all_scanned_files.len());
-                            for file in summary.all_scanned_files.iter().take(display_limit) {
-                                println!("  {}", file);
-                            }
-                            if summary.all_scanned_files.len() > display_limit {
-                                println!("  ... and {} more files", summary.all_scanned_files.len() - display_limit);
-                            }
+                    if let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+                        println!("\n\n=== SUMMARY ===\n");
+                        println!("Scan Date: {}", summary.scan_date);
+                        println!("Scan Duration: {}", summary.scan_duration);
+                        println!("Total Files Scanned: {}", summary.total_files_scanned);
+                        println!("Total Directories Scanned: {}", summary.total_directories_scanned);
+                        println!("Total Size Scanned: {:.2} MB", summary.total_size_scanned_mb);
+                        if summary.used_cache() {
+                            println!("Cache Hits: {} (Misses: {})", summary.cache_hits, summary.cache_misses);
+                        }
+                        println!("Files with Card Numbers: {}", summary.total_files_with_cards);
+                        println!("Clean Files: {}", summary.clean_files);
+                        println!("Total Card Numbers Found: {}", summary.total_cards_found);
+                        println!("Unique Card Numbers Found: {}", summary.unique_cards_found);
+                        
+                        println!("\nCard Type Distribution:");
+                        for (brand, count) in &summary.card_type_counts {
+                            let unique = summary.unique_card_type_counts.get(brand).copied().unwrap_or(0);
+                            println!("  {}: {} ({} unique)", brand, count, unique);
+                        }
+                        
+                        // Limit lists to avoid flooding the console
+                        summary.write_unique_cards(&mut io::stdout(), Some(10))?;
+                        
+                        // Display risk assessment
+                        if !summary.files_by_risk["high"].is_empty() {
+                            println!("\nHigh Risk Files: {}", summary.files_by_risk["high"].len());
+                        }
+                        if !summary.files_by_risk["medium"].is_empty() {
+                            println!("Medium Risk Files: {}", summary.files_by_risk["medium"].len());
+                        }
+                        if !summary.files_by_risk["low"].is_empty() {
+                            println!("Low Risk Files: {}", summary.files_by_risk["low"].len());
+                        }
+                        
+                        // Calculate compliance percentage
+                        let compliance_percentage = if summary.total_files_scanned > 0 {
+                            (summary.clean_files as f64 / summary.total_files_scanned as f64) * 100.0
+                        } else {
+                            0.0
+                        };
+                        println!("\nCompliance Status: {:.1}% of files are free of card data", compliance_percentage);
+                        
+                        // Add file list section for console output
+                        println!("\nScanned Files:");
+                        // Limit to first 10 files to avoid flooding the console
+                        let display_limit = std::cmp::min(10, summary.all_scanned_files.len());
+                        for file in summary.all_scanned_files.iter().take(display_limit) {
+                            println!("  {}", file);
+                        }
+                        if summary.all_scanned_files.len() > display_limit {
+                            println!("  ... and {} more files", summary.all_scanned_files.len() - display_limit);
                         }
                     }
                 }
@@ -1096,5 +1919,88 @@ fn main() -> io::Result<()> {
         }
     }
     
+    // Forward findings to the SIEM
+    if let Some(mut sink) = syslog.take() && let Some(ref summary_arc) = summary && let Ok(summary) = summary_arc.lock() {
+        let events = siem::events(syslog_format, &summary, &findings);
+        for event in &events {
+            sink.send(event)?;
+        }
+        sink.finish()?;
+        println!("Sent {} event(s) to {}", events.len(), matches.value_of("syslog").unwrap_or_default());
+    }
+    
+    // Record the scan in the history database
+    if let Some(ref mut database) = database && let Some(ref summary_arc) = summary
+        && let (Ok(summary), Ok(results_vec)) = (summary_arc.lock(), results.lock()) {
+        let scan_id = database.record_scan(&summary, &results_vec, &mask_policy, input_paths_str)?;
+        println!("Recorded scan {} in {}", scan_id, matches.value_of("db").unwrap_or_default());
+    }
+    
+    // Remediate affected files in place
+    if matches.is_present("redact") {
+        let options = RewriteOptions {
+            dry_run,
+            backup_dir: matches.value_of("backup-dir").map(Path::new),
+            show_full,
+        };
+        
+        if let Ok(results_vec) = results.lock() {
+            let stats = remediate::redact_files(&results_vec, &options, &mask_policy)?;
+            if options.dry_run {
+                println!("Dry run: {} PAN(s) on {} line(s) in {} file(s) would be redacted",
+                         stats.pans_replaced, stats.lines_changed, stats.files_rewritten);
+            } else {
+                println!("Redacted {} PAN(s) on {} line(s) in {} file(s)",
+                         stats.pans_replaced, stats.lines_changed, stats.files_rewritten);
+            }
+        }
+    }
+    
+    // Replace PANs with format-preserving tokens
+    if matches.is_present("tokenize") {
+        let key = keys::require_key(tokenize::KEY_ENV_VAR, matches.value_of("token-key-file"), "--token-key-file")?;
+        let tokenizer = Tokenizer::new(key);
+        let vault_path = Path::new(matches.value_of("vault").unwrap());
+        let options = RewriteOptions {
+            dry_run,
+            backup_dir: matches.value_of("backup-dir").map(Path::new),
+            show_full,
+        };
+        
+        if let Ok(results_vec) = results.lock() {
+            let stats = tokenize::tokenize_files(&results_vec, &tokenizer, vault_path, &options)?;
+            if options.dry_run {
+                println!("Dry run: {} PAN(s) on {} line(s) in {} file(s) would be tokenized",
+                         stats.pans_replaced, stats.lines_changed, stats.files_rewritten);
+            } else {
+                println!("Tokenized {} PAN(s) on {} line(s) in {} file(s); vault (cleartext PANs): {}",
+                         stats.pans_replaced, stats.lines_changed, stats.files_rewritten, vault_path.display());
+            }
+        }
+    }
+    
+    // Move high-risk files out of shared locations
+    if let Some(quarantine_dir) = matches.value_of("quarantine") {
+        let level = matches.value_of("quarantine-level").unwrap_or("high");
+        
+        if let Some(ref summary_arc) = summary
+            && let (Ok(summary), Ok(results_vec)) = (summary_arc.lock(), results.lock()) {
+            let entries = quarantine::quarantine_files(&summary, &results_vec, Path::new(quarantine_dir), level, dry_run)?;
+            if !dry_run {
+                println!("Quarantined {} file(s) into {} (manifest: {})",
+                         entries.len(), quarantine_dir,
+                         Path::new(quarantine_dir).join(quarantine::MANIFEST_FILE).display());
+            }
+        }
+    }
+    
+    // Notify the webhook that the scan completed
+    if let Some(notifier) = webhook && let Some(ref summary_arc) = summary
+        && let (Ok(summary), Ok(results_vec)) = (summary_arc.lock(), results.lock()) {
+        let attempts = notifier.notify(&summary, &results_vec, &mask_policy)?;
+        println!("Sent scan notification to {} ({} attempt(s))",
+                 matches.value_of("webhook").unwrap_or_default(), attempts);
+    }
+    
     Ok(())
 }
@@ -0,0 +1,311 @@
+// GitHub-flavored Markdown report
+//
+// Meant to be pasted into tickets and wikis: the summary as tables, then one
+// collapsible <details> section per file listing its findings. Text from
+// scanned files is escaped so paths or snippets containing '|', '*', '<' and
+// the like cannot break a table or turn into markup. Scan comparisons from
+// `luhnoxide diff` and trends from `report trend` are rendered the same way.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use crate::diff::{ScanDiff, Status};
+use crate::trend::{self, TrendPoint};
+use crate::{CardMatch, ScanSummary};
+
+const RISK_TIERS: &[(&str, &str)] = &[
+    ("high", "High Risk Files"),
+    ("medium", "Medium Risk Files"),
+    ("low", "Low Risk Files"),
+];
+
+// Render the report
+pub fn render(summary: &ScanSummary, findings: &[CardMatch]) -> String {
+    let mut md = String::new();
+
+    // Writing to a String cannot fail
+    let _ = write_report(&mut md, summary, findings);
+    md
+}
+
+fn write_report(md: &mut String, summary: &ScanSummary, findings: &[CardMatch]) -> std::fmt::Result {
+    writeln!(md, "# Luhnoxide Credit Card Scan Report\n")?;
+    writeln!(md, "**Scan Date:** {}  ", text(&summary.scan_date))?;
+    writeln!(md, "**Scan Duration:** {}\n", text(&summary.scan_duration))?;
+
+    writeln!(md, "## Key Metrics\n")?;
+    writeln!(md, "| Metric | Value |")?;
+    writeln!(md, "|--------|------:|")?;
+    for (label, value) in summary.key_metrics() {
+        writeln!(md, "| {} | {} |", label, value)?;
+    }
+
+    writeln!(md, "\n## Card Type Distribution\n")?;
+    writeln!(md, "| Card Brand | Count | Unique | Percentage |")?;
+    writeln!(md, "|------------|------:|-------:|-----------:|")?;
+    let mut brands = summary.brand_rows();
+    brands.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    for (brand, count, unique, percentage) in brands {
+        writeln!(md, "| {} | {} | {} | {:.1}% |", text(brand), count, unique, percentage)?;
+    }
+
+    if !summary.cards_in_multiple_files.is_empty() {
+        writeln!(md, "\n## Cards Found in Multiple Files\n")?;
+        writeln!(md, "| Masked PAN | Brand | Fingerprint | Files |")?;
+        writeln!(md, "|------------|-------|-------------|-------|")?;
+        for card in &summary.cards_in_multiple_files {
+            let files: Vec<String> = card.files.iter().map(|file| text(file)).collect();
+            writeln!(md, "| {} | {} | {} | Appears in {} files:<br>{} |",
+                     text(&card.masked_pan), text(&card.brand), text(&card.fingerprint),
+                     card.files.len(), files.join("<br>"))?;
+        }
+    }
+
+    writeln!(md, "\n## Risk Assessment\n")?;
+    writeln!(md, "| Risk | Files |")?;
+    writeln!(md, "|------|------:|")?;
+    for (level, title) in RISK_TIERS {
+        writeln!(md, "| {} | {} |", title, summary.files_by_risk.get(*level).map_or(0, Vec::len))?;
+    }
+
+    write_findings_by_file(md, summary, findings)?;
+
+    writeln!(md, "\n## Compliance Status\n")?;
+    writeln!(md, "**{:.1}%** of scanned files are free of credit card data.", summary.clean_percentage())?;
+
+    write_file_list(md, "Scanned Files", &summary.all_scanned_files)?;
+    if !summary.skipped_files.is_empty() {
+        write_file_list(md, "Skipped Files", &summary.skipped_files)?;
+    }
+
+    writeln!(md, "\n---\n\n_Generated by Luhnoxide - Credit Card Scanner_")
+}
+
+// One collapsible section per file, most findings first, with a table of the
+// file's findings in line order
+fn write_findings_by_file(md: &mut String, summary: &ScanSummary, findings: &[CardMatch]) -> std::fmt::Result {
+    let risk_of: HashMap<&str, &str> = summary.files_by_risk.iter()
+        .flat_map(|(level, files)| files.iter().map(move |file| (file.as_str(), level.as_str())))
+        .collect();
+
+    let mut by_file: HashMap<&str, Vec<&CardMatch>> = HashMap::new();
+    for card in findings {
+        by_file.entry(&card.file_path).or_default().push(card);
+    }
+    let mut files: Vec<(&str, Vec<&CardMatch>)> = by_file.into_iter().collect();
+    files.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(b.0)));
+
+    writeln!(md, "\n## Findings by File")?;
+    if files.is_empty() {
+        return writeln!(md, "\nNo credit card numbers found.");
+    }
+
+    for (file, mut cards) in files {
+        cards.sort_by_key(|card| (card.line_number, card.column_start));
+        let unique: BTreeSet<&str> = cards.iter().map(|card| card.fingerprint.as_str()).collect();
+        let risk = risk_of.get(file).copied().unwrap_or("low");
+
+        writeln!(md, "\n<details>")?;
+        writeln!(md, "<summary><code>{}</code> &mdash; {} finding{}, {} unique card{}, {} risk</summary>\n",
+                 html(file), cards.len(), plural(cards.len()), unique.len(), plural(unique.len()), risk)?;
+        writeln!(md, "| Line | Columns | Brand | PAN | Fingerprint | Context |")?;
+        writeln!(md, "|-----:|---------|-------|-----|-------------|---------|")?;
+        for card in cards {
+            writeln!(md, "| {} | {}-{} | {} | {} | {} | {} |",
+                     card.line_number, card.column_start, card.column_end, text(&card.brand),
+                     text(&card.full_pan), text(&card.fingerprint), text(&card.context))?;
+        }
+        writeln!(md, "\n</details>")?;
+    }
+    Ok(())
+}
+
+// Render a scan comparison: the counts, a table of files, then a collapsible
+// section of findings per status
+pub fn render_diff(diff: &ScanDiff) -> String {
+    let mut md = String::new();
+    let _ = write_diff(&mut md, diff);
+    md
+}
+
+fn write_diff(md: &mut String, diff: &ScanDiff) -> std::fmt::Result {
+    writeln!(md, "# Luhnoxide Scan Comparison\n")?;
+    writeln!(md, "**Old Scan:** {}  ", text(&diff.old))?;
+    writeln!(md, "**New Scan:** {}\n", text(&diff.new))?;
+
+    writeln!(md, "## Key Metrics\n")?;
+    writeln!(md, "| Metric | Value |")?;
+    writeln!(md, "|--------|------:|")?;
+    for (label, value) in diff.key_metrics() {
+        writeln!(md, "| {} | {} |", label, value)?;
+    }
+
+    writeln!(md, "\n## Files\n")?;
+    writeln!(md, "| File | Risk | Added | Resolved | Unchanged |")?;
+    writeln!(md, "|------|------|------:|---------:|----------:|")?;
+    for (file, counts) in diff.files() {
+        writeln!(md, "| {} | {} | {} | {} | {} |",
+                 text(file), diff.risk(file), counts.added, counts.resolved, counts.unchanged)?;
+    }
+
+    for status in Status::ALL {
+        let count = diff.count(status);
+        writeln!(md, "\n## {} Findings\n", status.label())?;
+        if count == 0 {
+            writeln!(md, "None.")?;
+            continue;
+        }
+        writeln!(md, "<details>")?;
+        writeln!(md, "<summary>{} finding{}</summary>\n", count, plural(count))?;
+        writeln!(md, "| File | Line | Brand | Masked PAN | Fingerprint | Context |")?;
+        writeln!(md, "|------|-----:|-------|------------|-------------|---------|")?;
+        for change in diff.with_status(status) {
+            let card = &change.card;
+            let line = match change.previous_line {
+                Some(previous) => format!("{} (was {})", card.line_number, previous),
+                None => card.line_number.to_string(),
+            };
+            writeln!(md, "| {} | {} | {} | {} | {} | {} |",
+                     text(&card.file_path), line, text(&card.brand), text(&card.full_pan),
+                     text(&card.fingerprint), text(&card.context))?;
+        }
+        writeln!(md, "\n</details>")?;
+    }
+
+    writeln!(md, "\n---\n\n_Generated by Luhnoxide - Credit Card Scanner_")
+}
+
+// Render the trend of stored scans: the change since the first scan, then a
+// table with one row per scan
+pub fn render_trend(database: &str, points: &[TrendPoint]) -> String {
+    let mut md = String::new();
+    let _ = write_trend(&mut md, database, points);
+    md
+}
+
+fn write_trend(md: &mut String, database: &str, points: &[TrendPoint]) -> std::fmt::Result {
+    writeln!(md, "# Luhnoxide Scan Trend\n")?;
+    writeln!(md, "**Database:** {}  ", text(database))?;
+    writeln!(md, "**Scans:** {}\n", points.len())?;
+
+    writeln!(md, "## Change Since the First Scan\n")?;
+    writeln!(md, "| Metric | Latest (change) |")?;
+    writeln!(md, "|--------|----------------:|")?;
+    for (label, change) in trend::changes(points) {
+        writeln!(md, "| {} | {} |", label, change)?;
+    }
+
+    writeln!(md, "\n## Scans\n")?;
+    writeln!(md, "| Scan | Date | Files Scanned | Files With Cards | Cards Found | Unique Cards | Clean Files |")?;
+    writeln!(md, "|-----:|------|--------------:|-----------------:|------------:|-------------:|------------:|")?;
+    for point in points {
+        writeln!(md, "| {} | {} | {} | {} | {} | {} | {:.1}% |",
+                 point.scan_id.map(|id| id.to_string()).unwrap_or_default(), text(&point.scan_date),
+                 point.files_scanned, point.files_with_cards, point.cards_found, point.unique_cards,
+                 point.clean_percentage)?;
+    }
+
+    writeln!(md, "\n---\n\n_Generated by Luhnoxide - Credit Card Scanner_")
+}
+
+// Long file lists are collapsed so they don't bury the rest of the report
+fn write_file_list(md: &mut String, title: &str, files: &[String]) -> std::fmt::Result {
+    writeln!(md, "\n## {}\n", title)?;
+    writeln!(md, "<details>")?;
+    writeln!(md, "<summary>{} file{}</summary>\n", files.len(), plural(files.len()))?;
+    for file in files {
+        writeln!(md, "- {}", text(file))?;
+    }
+    writeln!(md, "\n</details>")
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+// Text safe inside a table cell or list item: inline markup characters are
+// backslash-escaped, as is a leading character that would start a block,
+// '&' is written as an entity and line breaks are flattened
+fn text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\r' | '\n' | '\t' => escaped.push(' '),
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '#' | '-' | '+' | '=' if index == 0 => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Text inside raw HTML such as a <summary> element, where Markdown escapes
+// don't apply
+fn html(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_report;
+
+    #[test]
+    fn reports_show_masked_pans_and_escape_scanned_text() {
+        let (summary, findings) = test_report(&[("/srv/a|b<i>.txt", "x | *y* 4532015112830366")]);
+        let md = render(&summary, &findings);
+
+        assert!(md.contains("453201XXXXXX0366"));
+        assert!(!md.contains("4532015112830366"));
+        assert!(md.contains("<summary><code>/srv/a|b&lt;i&gt;.txt</code> &mdash; 1 finding, 1 unique card, low risk</summary>"));
+        assert!(md.contains("\n- /srv/a\\|b\\<i\\>.txt\n"));
+        assert!(md.contains("| x \\| \\*y\\* 453201XXXXXX0366 |\n"));
+        assert!(!md.contains("<i>"));
+    }
+
+    #[test]
+    fn table_cells_keep_pipes_from_splitting_rows() {
+        let (summary, findings) = test_report(&[
+            ("/srv/a|b.txt", "x|4532015112830366|y 5555555555554444"),
+            ("/srv/c|d.txt", "|4532015112830366|"),
+        ]);
+        let md = render(&summary, &findings);
+
+        // Every row of a table has as many cell separators as its header
+        let mut separators = None;
+        for line in md.lines() {
+            if !line.starts_with('|') {
+                separators = None;
+                continue;
+            }
+            let count = line.char_indices()
+                .filter(|&(index, c)| c == '|' && !line[..index].ends_with('\\'))
+                .count();
+            assert_eq!(count, *separators.get_or_insert(count), "{}", line);
+        }
+
+        assert!(md.contains("| 453201XXXXXX0366 | Visa | "));
+        assert!(md.contains(" | Appears in 2 files:<br>/srv/a\\|b.txt<br>/srv/c\\|d.txt |\n"));
+        assert!(md.contains(" | x\\|453201XXXXXX0366\\|y 555555X |\n"));
+        assert!(md.contains(" | \\|453201XXXXXX0366\\| |\n"));
+        // The file with the most findings comes first
+        assert!(md.find("<code>/srv/a|b.txt</code> &mdash; 2 findings, 2 unique cards")
+                < md.find("<code>/srv/c|d.txt</code> &mdash; 1 finding, 1 unique card"));
+    }
+
+    #[test]
+    fn text_escapes_markup_and_block_starts() {
+        assert_eq!(text("# a_b & [c](d)\nnext"), "\\# a\\_b &amp; \\[c\\](d) next");
+        assert_eq!(text("-1 - 2"), "\\-1 - 2");
+        assert_eq!(html("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+}
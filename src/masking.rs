@@ -0,0 +1,285 @@
+// PAN masking policies
+//
+// PCI DSS allows at most the first six (or, for PANs of 16+ digits with an
+// 8-digit BIN, the first eight) and the last four digits to be displayed.
+// Some organisations only allow the last four, or nothing at all.
+use std::io;
+
+use regex::Regex;
+
+pub const POLICIES: &[&str] = &["first6last4", "first8last4", "last4", "full"];
+
+// Separators a PAN may be written with, besides whitespace
+const SEPARATORS: &[char] = &['-', '.'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskStyle {
+    First6Last4,
+    First8Last4,
+    Last4,
+    Full,
+}
+
+#[derive(Clone, Debug)]
+pub struct MaskPolicy {
+    style: MaskStyle,
+    mask_char: char,
+}
+
+impl MaskPolicy {
+    pub fn from_config(policy: &str, mask_char: &str) -> io::Result<Self> {
+        let style = match policy.to_lowercase().as_str() {
+            "first6last4" => MaskStyle::First6Last4,
+            "first8last4" => MaskStyle::First8Last4,
+            "last4" => MaskStyle::Last4,
+            "full" => MaskStyle::Full,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown masking policy '{}' (expected one of: {})", other, POLICIES.join(", ")),
+                ));
+            }
+        };
+
+        let mut chars = mask_char.chars();
+        let mask_char = match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_ascii_digit() && !c.is_whitespace() && !SEPARATORS.contains(&c) => c,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Mask character must be a single character other than a digit, space, '-' or '.'",
+                ));
+            }
+        };
+
+        Ok(MaskPolicy { style, mask_char })
+    }
+
+    // Number of leading and trailing digits left visible for a PAN of `length`
+    fn visible(&self, length: usize) -> (usize, usize) {
+        match self.style {
+            MaskStyle::First6Last4 => (6, 4),
+            // 8-digit BINs may only be shown for PANs of 16 digits or more
+            MaskStyle::First8Last4 if length >= 16 => (8, 4),
+            MaskStyle::First8Last4 => (6, 4),
+            MaskStyle::Last4 => (0, 4),
+            MaskStyle::Full => (0, 0),
+        }
+    }
+
+    // Masked form of a digits-only PAN
+    pub fn mask(&self, pan: &str) -> String {
+        self.mask_formatted(pan)
+    }
+
+    // Mask a PAN written with any separators, keeping the separators in place.
+    // Already-masked positions still count towards the PAN's length, so
+    // masking a masked PAN again leaves it unchanged.
+    pub fn mask_formatted(&self, formatted: &str) -> String {
+        let is_position = |c: char| c.is_ascii_digit() || c == self.mask_char;
+        let length = formatted.chars().filter(|&c| is_position(c)).count();
+        let (prefix, suffix) = self.visible(length);
+        let mut index = 0;
+
+        formatted.chars()
+            .map(|c| {
+                if !is_position(c) {
+                    return c;
+                }
+                let visible = index < prefix || index + suffix >= length;
+                index += 1;
+                if visible { c } else { self.mask_char }
+            })
+            .collect()
+    }
+
+    // Mask the PANs at the given byte spans of `text`; everything outside the
+    // spans is left untouched
+    pub fn mask_spans(&self, text: &str, spans: &[(usize, usize)]) -> String {
+        let mut spans: Vec<(usize, usize)> = spans.iter()
+            .copied()
+            .filter(|&(start, end)| {
+                start < end && end <= text.len() && text.is_char_boundary(start) && text.is_char_boundary(end)
+            })
+            .collect();
+        spans.sort_unstable();
+
+        let mut masked = String::with_capacity(text.len());
+        let mut position = 0;
+        for (start, end) in spans {
+            // Overlapping spans were already covered by the previous one
+            if start < position {
+                continue;
+            }
+            masked.push_str(&text[position..start]);
+            masked.push_str(&self.mask_formatted(&text[start..end]));
+            position = end;
+        }
+        masked.push_str(&text[position..]);
+        masked
+    }
+
+    // BIN as it may be displayed under this policy
+    pub fn visible_bin(&self, pan: &str) -> String {
+        let (prefix, _) = self.visible(pan.chars().count());
+        if prefix == 0 {
+            self.mask_char.to_string().repeat(6)
+        } else {
+            pan.chars().take(prefix).collect()
+        }
+    }
+
+    // Last four digits as they may be displayed under this policy
+    pub fn visible_last_four(&self, pan: &str) -> String {
+        let length = pan.chars().count();
+        let (_, suffix) = self.visible(length);
+        if suffix == 0 {
+            self.mask_char.to_string().repeat(4)
+        } else {
+            pan.chars().skip(length - suffix).collect()
+        }
+    }
+}
+
+// Byte spans where the digits of `pan` appear in `text`, with any separators
+// between them, and not as part of a longer digit run
+pub fn find_pan(text: &str, pan: &str) -> Vec<(usize, usize)> {
+    let digits: Vec<String> = pan.chars().map(|c| regex::escape(&c.to_string())).collect();
+    let re = Regex::new(&digits.join(r"[.\s-]*")).expect("PAN digits form a valid pattern");
+
+    re.find_iter(text)
+        .filter(|found| {
+            let before = text[..found.start()].chars().next_back();
+            let after = text[found.end()..].chars().next();
+            !before.is_some_and(|c| c.is_ascii_digit()) && !after.is_some_and(|c| c.is_ascii_digit())
+        })
+        .map(|found| (found.start(), found.end()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::MaskPolicy;
+    use crate::{is_valid_luhn, scan_line, test_scan_config, CardMatch};
+
+    fn scan(line: &str) -> Vec<CardMatch> {
+        let config = test_scan_config(None);
+        scan_line(&config, line, 1, 0, "test.txt").0
+    }
+
+    fn policies() -> Vec<MaskPolicy> {
+        let mut policies: Vec<MaskPolicy> = ["first6last4", "first8last4", "last4", "full"].iter()
+            .map(|policy| MaskPolicy::from_config(policy, "X").unwrap())
+            .collect();
+        policies.push(MaskPolicy::from_config("first8last4", "\u{2022}").unwrap());
+        policies
+    }
+
+    // True if the PAN's digits appear in `text`, whatever separates them
+    fn leaks(text: &str, pan: &str) -> bool {
+        text.split(|c: char| !(c.is_ascii_digit() || c.is_whitespace() || c == '-' || c == '.'))
+            .map(|run| run.chars().filter(|c| c.is_ascii_digit()).collect::<String>())
+            .any(|digits| digits.contains(pan))
+    }
+
+    // Append the Luhn check digit to a digit string
+    fn with_check_digit(body: &[u8]) -> String {
+        let body: String = body.iter().map(|d| (b'0' + d) as char).collect();
+        (0..10)
+            .map(|check| format!("{}{}", body, check))
+            .find(|pan| is_valid_luhn(pan))
+            .unwrap()
+    }
+
+    // Write a PAN with the given separator after each digit
+    fn layout(pan: &str, separators: &[&str]) -> String {
+        let mut formatted = String::new();
+        for (i, c) in pan.chars().enumerate() {
+            formatted.push(c);
+            if i + 1 < pan.len() {
+                formatted.push_str(separators[i % separators.len()]);
+            }
+        }
+        formatted
+    }
+
+    #[test]
+    fn masks_unusual_separator_layouts() {
+        let policy = MaskPolicy::from_config("first6last4", "X").unwrap();
+        for formatted in ["4532.0151.1283.0366", "4532 015112 830366", "4532-0151 1283.0366", "453201511283 0366"] {
+            let line = format!("card: {} end", formatted);
+            let cards = scan(&line);
+            assert_eq!(cards.len(), 1, "not detected: {}", formatted);
+
+            let masked = cards[0].mask_line_content(&policy);
+            assert!(!leaks(&masked, "4532015112830366"), "leaked: {}", masked);
+            assert_eq!(masked.len(), line.len());
+        }
+    }
+
+    #[test]
+    fn masks_every_card_on_the_line() {
+        let policy = MaskPolicy::from_config("last4", "X").unwrap();
+        let cards = scan("a 4532015112830366 b 3782 822463 10005 c");
+        assert_eq!(cards.len(), 2);
+
+        for card in &cards {
+            let masked = card.mask_line_content(&policy);
+            assert!(!leaks(&masked, "4532015112830366"));
+            assert!(!leaks(&masked, "378282246310005"));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn masked_output_never_contains_a_detected_pan(
+            bodies in prop::collection::vec(prop::collection::vec(0u8..10, 12..19), 1..4),
+            separators in prop::collection::vec(prop::sample::select(vec!["", "", "", " ", "-", ".", "  ", " - "]), 1..6),
+            prefix in "[a-zA-Z:=# ]{0,12}",
+            joiner in "[a-zA-Z,;=]{1,6}",
+            suffix in "[a-zA-Z.;) ]{0,12}",
+        ) {
+            let formatted: Vec<String> = bodies.iter()
+                .map(|body| layout(&with_check_digit(body), &separators))
+                .collect();
+            let line = format!("{}{}{}", prefix, formatted.join(&joiner), suffix);
+
+            let cards = scan(&line);
+            for policy in policies() {
+                for card in &cards {
+                    let masked = card.mask_line_content(&policy);
+                    prop_assert_eq!(masked.chars().count(), line.chars().count());
+                    for other in &cards {
+                        prop_assert!(!leaks(&masked, &other.full_pan), "{} leaked in {}", other.full_pan, masked);
+                        prop_assert!(!leaks(&card.mask_pan_in(&line, &policy), &other.full_pan));
+                        prop_assert!(!leaks(&card.masked_copy(&policy).context, &other.full_pan));
+                    }
+
+                    // Matches masked during the scan come out of the output path unchanged
+                    let masked = card.masked_copy(&policy);
+                    let remasked = masked.masked_copy(&policy);
+                    prop_assert_eq!(&remasked.full_pan, &masked.full_pan);
+                    prop_assert_eq!(&remasked.bin, &masked.bin);
+                    prop_assert_eq!(&remasked.last_four, &masked.last_four);
+                    prop_assert_eq!(&remasked.line_content, &masked.line_content);
+                    prop_assert_eq!(&remasked.context, &masked.context);
+                }
+            }
+        }
+
+        #[test]
+        fn masking_keeps_separators_and_visible_digits(body in prop::collection::vec(0u8..10, 15..16)) {
+            let pan = with_check_digit(&body);
+            let formatted = layout(&pan, &["", "", "", "-"]);
+            let policy = MaskPolicy::from_config("first6last4", "*").unwrap();
+
+            let masked = policy.mask_formatted(&formatted);
+            prop_assert_eq!(masked.replace(['-', '*'], "").len(), 10);
+            prop_assert!(masked.starts_with(&formatted[..7]));
+            prop_assert!(masked.ends_with(&formatted[formatted.len() - 4..]));
+            prop_assert_eq!(masked.matches('-').count(), formatted.matches('-').count());
+        }
+    }
+}
@@ -0,0 +1,667 @@
+// Native PDF report output
+//
+// A small PDF writer for text reports, so no external HTML-to-PDF converter
+// is needed. Pages are A4 and use the standard Helvetica fonts, which every
+// PDF viewer provides, so nothing has to be embedded. Headings are collected
+// into a linked table of contents on the front page and into the document
+// outline (bookmarks), and every page gets a running header and footer.
+use std::fmt::Write as _;
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+const CONTENT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+const CONTENT_TOP: f32 = PAGE_HEIGHT - 70.0;
+const CONTENT_BOTTOM: f32 = 65.0;
+
+const BODY_SIZE: f32 = 10.0;
+const TABLE_SIZE: f32 = 9.0;
+const LINE_SPACING: f32 = 1.35;
+const CELL_PADDING: f32 = 4.0;
+const TOC_LINE: f32 = 18.0;
+
+#[derive(Clone, Copy)]
+pub struct Rgb(pub f32, pub f32, pub f32);
+
+// Colours of the HTML report
+const TEXT: Rgb = Rgb(0.2, 0.2, 0.2);
+const HEADING: Rgb = Rgb(0.173, 0.243, 0.314);
+const ACCENT: Rgb = Rgb(0.204, 0.596, 0.859);
+const MUTED: Rgb = Rgb(0.498, 0.549, 0.553);
+const RULE: Rgb = Rgb(0.867, 0.867, 0.867);
+const HEADER_FILL: Rgb = Rgb(0.949, 0.949, 0.949);
+const BOX_FILL: Rgb = Rgb(0.973, 0.976, 0.98);
+pub const RISK_HIGH: Rgb = Rgb(0.906, 0.298, 0.235);
+pub const RISK_MEDIUM: Rgb = Rgb(0.953, 0.612, 0.071);
+pub const RISK_LOW: Rgb = Rgb(0.18, 0.8, 0.443);
+
+// Advance widths (1/1000 em) of the printable ASCII characters, from the
+// Adobe font metrics of the standard fonts
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+
+    fn char_width(self, c: char, size: f32) -> f32 {
+        let widths = match self {
+            Font::Regular => &HELVETICA_WIDTHS,
+            Font::Bold => &HELVETICA_BOLD_WIDTHS,
+        };
+        let units = match c as u32 {
+            code @ 32..=126 => widths[(code - 32) as usize],
+            _ => 556,
+        };
+        units as f32 * size / 1000.0
+    }
+
+    fn text_width(self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c, size)).sum()
+    }
+}
+
+#[derive(Default)]
+struct Page {
+    content: String,
+    // Link rectangles and the content page and height they jump to
+    links: Vec<([f32; 4], usize, f32)>,
+}
+
+// A heading waiting for the content that follows it, so the two are never
+// split across pages
+struct PendingHeading {
+    text: String,
+    level: u8,
+    size: f32,
+    color: Rgb,
+    space_before: f32,
+}
+
+impl PendingHeading {
+    fn height(&self) -> f32 {
+        self.space_before + self.size + 8.0
+    }
+}
+
+// A heading, remembered for the table of contents and outline
+struct TocEntry {
+    title: String,
+    level: u8,
+    page: usize,
+    y: f32,
+}
+
+pub struct PdfReport {
+    title: String,
+    details: Vec<(String, String)>,
+    pages: Vec<Page>,
+    toc: Vec<TocEntry>,
+    pending_heading: Option<PendingHeading>,
+    y: f32,
+}
+
+impl PdfReport {
+    // Start a report; `details` are shown on the front page above the contents
+    pub fn new(title: &str, details: &[(&str, String)]) -> Self {
+        PdfReport {
+            title: title.to_string(),
+            details: details.iter().map(|(label, value)| (label.to_string(), value.clone())).collect(),
+            pages: vec![Page::default()],
+            toc: Vec::new(),
+            pending_heading: None,
+            y: CONTENT_TOP,
+        }
+    }
+
+    // Section heading, listed in the table of contents and the outline
+    pub fn heading(&mut self, text: &str) {
+        self.push_heading(text, 1, 15.0, HEADING, 14.0);
+    }
+
+    // Heading within a section, listed in the table of contents
+    pub fn subheading(&mut self, text: &str, color: Rgb) {
+        self.push_heading(text, 2, 12.0, color, 6.0);
+    }
+
+    fn push_heading(&mut self, text: &str, level: u8, size: f32, color: Rgb, space_before: f32) {
+        self.flush_heading();
+        self.pending_heading = Some(PendingHeading { text: text.to_string(), level, size, color, space_before });
+    }
+
+    // Draw the pending heading at the current position
+    fn flush_heading(&mut self) {
+        let Some(heading) = self.pending_heading.take() else {
+            return;
+        };
+        if self.y < CONTENT_TOP {
+            self.y -= heading.space_before;
+        }
+
+        self.toc.push(TocEntry {
+            title: heading.text.clone(),
+            level: heading.level,
+            page: self.pages.len() - 1,
+            y: self.y + 4.0,
+        });
+        let baseline = self.y - heading.size;
+        self.text(MARGIN, baseline, Font::Bold, heading.size, heading.color, &heading.text);
+        self.y = baseline - 8.0;
+    }
+
+    pub fn paragraph(&mut self, text: &str) {
+        let line_height = BODY_SIZE * LINE_SPACING;
+        for line in wrap(text, Font::Regular, BODY_SIZE, CONTENT_WIDTH) {
+            self.ensure_space(line_height);
+            self.y -= line_height;
+            self.text(MARGIN, self.y + 3.0, Font::Regular, BODY_SIZE, TEXT, &line);
+        }
+        self.y -= 6.0;
+    }
+
+    // Text on a shaded background, like the HTML summary boxes
+    pub fn callout(&mut self, text: &str) {
+        let line_height = BODY_SIZE * LINE_SPACING;
+        let lines = wrap(text, Font::Regular, BODY_SIZE, CONTENT_WIDTH - 2.0 * CELL_PADDING);
+        let height = lines.len() as f32 * line_height + 2.0 * CELL_PADDING;
+        self.ensure_space(height);
+
+        self.fill_rect(MARGIN, self.y - height, CONTENT_WIDTH, height, BOX_FILL);
+        let mut baseline = self.y - CELL_PADDING;
+        for line in &lines {
+            baseline -= line_height;
+            self.text(MARGIN + CELL_PADDING, baseline + 3.0, Font::Regular, BODY_SIZE, TEXT, line);
+        }
+        self.y -= height + 10.0;
+    }
+
+    // Grid of labelled figures, three per row
+    pub fn stats(&mut self, items: &[(&str, String)]) {
+        const COLUMNS: usize = 3;
+        const GAP: f32 = 10.0;
+        const HEIGHT: f32 = 42.0;
+        let width = (CONTENT_WIDTH - GAP * (COLUMNS - 1) as f32) / COLUMNS as f32;
+
+        for row in items.chunks(COLUMNS) {
+            self.ensure_space(HEIGHT + GAP);
+            let top = self.y;
+            for (index, (label, value)) in row.iter().enumerate() {
+                let x = MARGIN + index as f32 * (width + GAP);
+                self.fill_rect(x, top - HEIGHT, width, HEIGHT, BOX_FILL);
+                self.fill_rect(x, top - HEIGHT, 3.0, HEIGHT, ACCENT);
+                self.text(x + 10.0, top - 20.0, Font::Bold, 16.0, HEADING, value);
+                self.text(x + 10.0, top - 34.0, Font::Regular, 9.0, MUTED, label);
+            }
+            self.y = top - HEIGHT - GAP;
+        }
+        self.y -= 4.0;
+    }
+
+    // Table with a shaded header row that is repeated on every page the
+    // table runs onto. `widths` are fractions of the content width.
+    pub fn table(&mut self, headers: &[&str], widths: &[f32], rows: &[Vec<String>]) {
+        let columns: Vec<f32> = widths.iter().map(|fraction| fraction * CONTENT_WIDTH).collect();
+        let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+
+        // Keep the header row together with the first row
+        let first_row = rows.first().map(|row| self.row_height(row, &columns, Font::Regular)).unwrap_or(0.0);
+        self.ensure_space(self.row_height(&headers, &columns, Font::Bold) + first_row);
+        self.table_row(&headers, &columns, Font::Bold, Some(HEADER_FILL));
+
+        for row in rows {
+            if self.y - self.row_height(row, &columns, Font::Regular) < CONTENT_BOTTOM {
+                self.new_page();
+                self.table_row(&headers, &columns, Font::Bold, Some(HEADER_FILL));
+            }
+            self.table_row(row, &columns, Font::Regular, None);
+        }
+        self.y -= 12.0;
+    }
+
+    // Plain list of lines, e.g. file paths
+    pub fn list(&mut self, items: &[String]) {
+        let line_height = TABLE_SIZE * LINE_SPACING;
+        for item in items {
+            for (index, line) in wrap(item, Font::Regular, TABLE_SIZE, CONTENT_WIDTH - 12.0).iter().enumerate() {
+                self.ensure_space(line_height);
+                self.y -= line_height;
+                let indent = if index == 0 { 0.0 } else { 12.0 };
+                self.text(MARGIN + indent, self.y + 3.0, Font::Regular, TABLE_SIZE, TEXT, line);
+            }
+        }
+        self.y -= 10.0;
+    }
+
+    fn row_height(&self, cells: &[String], columns: &[f32], font: Font) -> f32 {
+        let lines = cells.iter()
+            .zip(columns)
+            .map(|(cell, width)| wrap(cell, font, TABLE_SIZE, width - 2.0 * CELL_PADDING).len())
+            .max()
+            .unwrap_or(1)
+            .min(max_row_lines());
+        lines as f32 * TABLE_SIZE * LINE_SPACING + 2.0 * CELL_PADDING
+    }
+
+    fn table_row(&mut self, cells: &[String], columns: &[f32], font: Font, fill: Option<Rgb>) {
+        let height = self.row_height(cells, columns, font);
+        let top = self.y;
+        if let Some(color) = fill {
+            self.fill_rect(MARGIN, top - height, CONTENT_WIDTH, height, color);
+        }
+
+        let line_height = TABLE_SIZE * LINE_SPACING;
+        let mut x = MARGIN;
+        for (cell, width) in cells.iter().zip(columns) {
+            let mut lines = wrap(cell, font, TABLE_SIZE, width - 2.0 * CELL_PADDING);
+            // A cell taller than a page is cut short
+            if lines.len() > max_row_lines() {
+                lines.truncate(max_row_lines());
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(" ...");
+                }
+            }
+            for (index, line) in lines.iter().enumerate() {
+                let baseline = top - CELL_PADDING - (index + 1) as f32 * line_height + 3.0;
+                self.text(x + CELL_PADDING, baseline, font, TABLE_SIZE, TEXT, line);
+            }
+            x += width;
+        }
+
+        self.y = top - height;
+        self.line(MARGIN, self.y, MARGIN + CONTENT_WIDTH, self.y, RULE);
+    }
+
+    // Start a new page unless `height` fits below the current position, along
+    // with any pending heading, then draw that heading
+    fn ensure_space(&mut self, height: f32) {
+        let heading_height = self.pending_heading.as_ref().map(PendingHeading::height).unwrap_or(0.0);
+        if self.y - heading_height - height < CONTENT_BOTTOM {
+            self.new_page();
+        }
+        self.flush_heading();
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page::default());
+        self.y = CONTENT_TOP;
+    }
+
+    fn text(&mut self, x: f32, y: f32, font: Font, size: f32, color: Rgb, text: &str) {
+        let page = self.pages.last_mut().expect("a report always has a page");
+        draw_text(page, x, y, font, size, color, text);
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Rgb) {
+        let page = self.pages.last_mut().expect("a report always has a page");
+        let _ = writeln!(page.content, "{:.3} {:.3} {:.3} rg {:.2} {:.2} {:.2} {:.2} re f",
+                         color.0, color.1, color.2, x, y, width, height);
+    }
+
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: Rgb) {
+        let page = self.pages.last_mut().expect("a report always has a page");
+        draw_line(page, x1, y1, x2, y2, color);
+    }
+
+    // Lay out the front page with the table of contents, add headers and
+    // footers, and serialize the document
+    pub fn finish(mut self) -> Vec<u8> {
+        self.flush_heading();
+        let front_pages = self.front_page_count();
+        let mut pages = self.front_pages(front_pages);
+        pages.extend(self.pages);
+        let total = pages.len();
+
+        for (index, page) in pages.iter_mut().enumerate() {
+            if index > 0 {
+                draw_text(page, MARGIN, PAGE_HEIGHT - 40.0, Font::Regular, 8.0, MUTED, &self.title);
+                draw_line(page, MARGIN, PAGE_HEIGHT - 46.0, PAGE_WIDTH - MARGIN, PAGE_HEIGHT - 46.0, RULE);
+            }
+            draw_line(page, MARGIN, 48.0, PAGE_WIDTH - MARGIN, 48.0, RULE);
+            draw_text(page, MARGIN, 34.0, Font::Regular, 8.0, MUTED, "Generated by Luhnoxide - Credit Card Scanner");
+            let number = format!("Page {} of {}", index + 1, total);
+            let width = Font::Regular.text_width(&number, 8.0);
+            draw_text(page, PAGE_WIDTH - MARGIN - width, 34.0, Font::Regular, 8.0, MUTED, &number);
+        }
+
+        serialize(&self.title, &pages, &self.toc, front_pages)
+    }
+
+    // Space left for contents entries on the front page after the title block
+    fn front_page_toc_top(&self) -> f32 {
+        CONTENT_TOP - 40.0 - self.details.len() as f32 * 16.0 - 40.0
+    }
+
+    fn front_page_count(&self) -> usize {
+        let first = ((self.front_page_toc_top() - CONTENT_BOTTOM) / TOC_LINE) as usize;
+        let per_page = ((CONTENT_TOP - CONTENT_BOTTOM) / TOC_LINE) as usize;
+        1 + self.toc.len().saturating_sub(first).div_ceil(per_page)
+    }
+
+    fn front_pages(&self, count: usize) -> Vec<Page> {
+        let mut pages: Vec<Page> = (0..count).map(|_| Page::default()).collect();
+        let first = &mut pages[0];
+
+        let mut y = CONTENT_TOP - 24.0;
+        draw_text(first, MARGIN, y, Font::Bold, 22.0, HEADING, &self.title);
+        draw_line(first, MARGIN, y - 10.0, PAGE_WIDTH - MARGIN, y - 10.0, ACCENT);
+        y -= 16.0;
+        for (label, value) in &self.details {
+            y -= 16.0;
+            let label = format!("{}: ", label);
+            draw_text(first, MARGIN, y, Font::Bold, BODY_SIZE, TEXT, &label);
+            let offset = Font::Bold.text_width(&label, BODY_SIZE);
+            draw_text(first, MARGIN + offset, y, Font::Regular, BODY_SIZE, TEXT, value);
+        }
+        y -= 34.0;
+        draw_text(first, MARGIN, y, Font::Bold, 15.0, HEADING, "Contents");
+
+        let mut page_index = 0;
+        let mut y = self.front_page_toc_top();
+        for entry in &self.toc {
+            if y - TOC_LINE < CONTENT_BOTTOM {
+                page_index += 1;
+                y = CONTENT_TOP;
+            }
+            y -= TOC_LINE;
+            let page = &mut pages[page_index];
+
+            let (font, indent) = if entry.level == 1 { (Font::Bold, 0.0) } else { (Font::Regular, 16.0) };
+            let number = (count + entry.page + 1).to_string();
+            let number_width = font.text_width(&number, BODY_SIZE);
+            let title_x = MARGIN + indent;
+            let number_x = PAGE_WIDTH - MARGIN - number_width;
+            draw_text(page, title_x, y, font, BODY_SIZE, TEXT, &entry.title);
+            draw_text(page, number_x, y, font, BODY_SIZE, TEXT, &number);
+
+            // Dot leaders between the title and the page number
+            let leader_start = title_x + font.text_width(&entry.title, BODY_SIZE) + 6.0;
+            let dots = ((number_x - 6.0 - leader_start) / Font::Regular.char_width('.', BODY_SIZE)).max(0.0) as usize;
+            draw_text(page, leader_start, y, Font::Regular, BODY_SIZE, MUTED, &".".repeat(dots));
+
+            page.links.push(([MARGIN, y - 4.0, PAGE_WIDTH - MARGIN, y + BODY_SIZE], count + entry.page, entry.y));
+        }
+
+        pages
+    }
+}
+
+// Rows never grow beyond what fits on an empty page below a heading and the
+// header row
+fn max_row_lines() -> usize {
+    const HEADING_ALLOWANCE: f32 = 40.0;
+    let header_row = TABLE_SIZE * LINE_SPACING + 2.0 * CELL_PADDING;
+    let available = CONTENT_TOP - CONTENT_BOTTOM - HEADING_ALLOWANCE - header_row;
+    ((available - 2.0 * CELL_PADDING) / (TABLE_SIZE * LINE_SPACING)) as usize
+}
+
+fn draw_text(page: &mut Page, x: f32, y: f32, font: Font, size: f32, color: Rgb, text: &str) {
+    let _ = writeln!(page.content, "BT /{} {} Tf {:.3} {:.3} {:.3} rg {:.2} {:.2} Td ({}) Tj ET",
+                     font.resource(), size, color.0, color.1, color.2, x, y, escape(text));
+}
+
+fn draw_line(page: &mut Page, x1: f32, y1: f32, x2: f32, y2: f32, color: Rgb) {
+    let _ = writeln!(page.content, "{:.3} {:.3} {:.3} RG 0.75 w {:.2} {:.2} m {:.2} {:.2} l S",
+                     color.0, color.1, color.2, x1, y1, x2, y2);
+}
+
+// Break text into lines no wider than `width`, at spaces where possible and
+// anywhere inside words that are too long on their own (e.g. file paths)
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let space = font.char_width(' ', size);
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut line_width = 0.0;
+
+        for word in paragraph.split(' ') {
+            let word_width = font.text_width(word, size);
+            if line.is_empty() && word_width <= width {
+                line.push_str(word);
+                line_width = word_width;
+                continue;
+            }
+            if !line.is_empty() && line_width + space + word_width <= width {
+                line.push(' ');
+                line.push_str(word);
+                line_width += space + word_width;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0.0;
+            }
+            for c in word.chars() {
+                let char_width = font.char_width(c, size);
+                if !line.is_empty() && line_width + char_width > width {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0.0;
+                }
+                line.push(c);
+                line_width += char_width;
+            }
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+// PDF string literal contents in WinAnsiEncoding. Latin-1 characters are
+// written as octal escapes; anything the standard fonts cannot show becomes '?'.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            '\t' => escaped.push(' '),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(escaped, "\\{:03o}", c as u32);
+            }
+            _ => match win_ansi_extra(c) {
+                Some(code) => {
+                    let _ = write!(escaped, "\\{:03o}", code);
+                }
+                None => escaped.push('?'),
+            },
+        }
+    }
+    escaped
+}
+
+// Common characters WinAnsiEncoding places in 0x80-0x9f
+fn win_ansi_extra(c: char) -> Option<u8> {
+    match c {
+        '\u{20ac}' => Some(0x80), // euro sign
+        '\u{2026}' => Some(0x85), // ellipsis
+        '\u{2018}' => Some(0x91),
+        '\u{2019}' => Some(0x92),
+        '\u{201c}' => Some(0x93),
+        '\u{201d}' => Some(0x94),
+        '\u{2022}' => Some(0x95), // bullet
+        '\u{2013}' => Some(0x96), // en dash
+        '\u{2014}' => Some(0x97), // em dash
+        '\u{2122}' => Some(0x99), // trade mark
+        _ => None,
+    }
+}
+
+// Write the document: catalog, page tree, fonts, info, outline, then each
+// page with its content stream and link annotations
+fn serialize(title: &str, pages: &[Page], toc: &[TocEntry], front_pages: usize) -> Vec<u8> {
+    const CATALOG: usize = 1;
+    const PAGES: usize = 2;
+    const REGULAR: usize = 3;
+    const BOLD: usize = 4;
+    const INFO: usize = 5;
+    const OUTLINES: usize = 6;
+
+    let page_id = |index: usize| 7 + 2 * index;
+    let content_id = |index: usize| 8 + 2 * index;
+    let mut objects: Vec<String> = vec![String::new(); 6 + 2 * pages.len()];
+
+    objects[CATALOG - 1] = format!("<< /Type /Catalog /Pages {} 0 R /Outlines {} 0 R /PageMode /UseOutlines >>",
+                                   PAGES, OUTLINES);
+    let kids: Vec<String> = (0..pages.len()).map(|index| format!("{} 0 R", page_id(index))).collect();
+    objects[PAGES - 1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len());
+    objects[REGULAR - 1] = "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string();
+    objects[BOLD - 1] = "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string();
+    objects[INFO - 1] = format!("<< /Title ({}) /Producer (Luhnoxide) /CreationDate ({}) >>",
+                                escape(title), chrono::Local::now().format("D:%Y%m%d%H%M%S"));
+
+    let destination = |page: usize, y: f32| format!("[{} 0 R /XYZ 0 {:.2} 0]", page_id(page), y);
+
+    // Top-level headings become bookmarks
+    let sections: Vec<&TocEntry> = toc.iter().filter(|entry| entry.level == 1).collect();
+    let first_item = objects.len() + 1;
+    for (index, entry) in sections.iter().enumerate() {
+        let mut item = format!("<< /Title ({}) /Parent {} 0 R /Dest {}",
+                               escape(&entry.title), OUTLINES, destination(front_pages + entry.page, entry.y));
+        if index > 0 {
+            let _ = write!(item, " /Prev {} 0 R", first_item + index - 1);
+        }
+        if index + 1 < sections.len() {
+            let _ = write!(item, " /Next {} 0 R", first_item + index + 1);
+        }
+        item.push_str(" >>");
+        objects.push(item);
+    }
+    objects[OUTLINES - 1] = if sections.is_empty() {
+        "<< /Type /Outlines /Count 0 >>".to_string()
+    } else {
+        format!("<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+                first_item, first_item + sections.len() - 1, sections.len())
+    };
+
+    for (index, page) in pages.iter().enumerate() {
+        let mut annotations = Vec::new();
+        for (rect, target, y) in &page.links {
+            objects.push(format!("<< /Type /Annot /Subtype /Link /Rect [{:.2} {:.2} {:.2} {:.2}] /Border [0 0 0] /Dest {} >>",
+                                 rect[0], rect[1], rect[2], rect[3], destination(*target, *y)));
+            annotations.push(format!("{} 0 R", objects.len()));
+        }
+
+        objects[page_id(index) - 1] = format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 {} 0 R /F2 {} 0 R >> >> /Contents {} 0 R /Annots [{}] >>",
+            PAGES, PAGE_WIDTH, PAGE_HEIGHT, REGULAR, BOLD, content_id(index), annotations.join(" ")
+        );
+        objects[content_id(index) - 1] = format!("<< /Length {} >>\nstream\n{}endstream",
+                                                 page.content.len(), page.content);
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", index + 1, object).as_bytes());
+    }
+
+    let xref_offset = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(trailer, "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+                   objects.len() + 1, CATALOG, INFO, xref_offset);
+    pdf.extend_from_slice(trailer.as_bytes());
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_report;
+
+    fn contains(pdf: &[u8], text: &str) -> bool {
+        pdf.windows(text.len()).any(|window| window == text.as_bytes())
+    }
+
+    #[test]
+    fn reports_show_masked_pans_and_escape_file_names() {
+        let (summary, _) = test_report(&[
+            ("/srv/a) Tj (x.txt", "pay 4532015112830366"),
+            ("/srv/b.txt", "again 4532015112830366"),
+        ]);
+        let pdf = summary.to_pdf();
+
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(contains(&pdf, "453201XXXXXX0366"));
+        assert!(!contains(&pdf, "4532015112830366"));
+        assert!(contains(&pdf, "(/srv/a\\) Tj \\(x.txt)"));
+        assert!(!contains(&pdf, "a) Tj (x"));
+    }
+
+    #[test]
+    fn long_reports_get_contents_bookmarks_and_numbered_pages() {
+        let mut report = PdfReport::new("Report", &[("Scan Date", "2026-10-18".to_string())]);
+        for section in 0..40 {
+            report.heading(&format!("Section {}", section));
+            let rows: Vec<Vec<String>> = (0..10).map(|row| vec![format!("/srv/{}/{}.txt", section, row)]).collect();
+            report.table(&["File"], &[1.0], &rows);
+        }
+        let pdf = report.finish();
+        let text = String::from_utf8_lossy(&pdf);
+
+        let total = text.matches("/Type /Page ").count();
+        assert!(total > 3);
+        assert!(text.contains(&format!("/Type /Pages /Kids [{}] /Count {} >>",
+                                       (0..total).map(|index| format!("{} 0 R", 7 + 2 * index))
+                                           .collect::<Vec<_>>().join(" "), total)));
+        for page in 1..=total {
+            assert!(contains(&pdf, &format!("(Page {} of {}) Tj", page, total)));
+        }
+        assert_eq!(text.matches("(Generated by Luhnoxide - Credit Card Scanner) Tj").count(), total);
+        // The running header is left off the first page only
+        assert_eq!(text.matches("(Report) Tj").count(), total);
+        assert_eq!(text.matches("/Subtype /Link").count(), 40);
+        assert!(text.contains("/Type /Outlines /First ") && text.contains("/Count 40 >>"));
+        // 40 contents entries take two front pages, so Section 0 is on the
+        // third page, object 11
+        assert!(text.contains("<< /Title (Section 0) /Parent 6 0 R /Dest [11 0 R /XYZ 0 "));
+
+        // Every cross-reference entry points at its object
+        let xref = text.rfind("\nxref\n").unwrap();
+        let entries: Vec<usize> = text[xref..].lines().skip(4).take_while(|line| line.ends_with(" n "))
+            .map(|line| line[..10].parse().unwrap())
+            .collect();
+        assert_eq!(entries.len(), text.matches(" 0 obj\n").count());
+        for (index, offset) in entries.into_iter().enumerate() {
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", index + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn text_is_escaped_for_string_literals() {
+        assert_eq!(escape("(a)\\b\tc"), "\\(a\\)\\\\b c");
+        assert_eq!(escape("caf\u{e9} \u{20ac}5 \u{4e2d}"), "caf\\351 \\2005 ?");
+    }
+}
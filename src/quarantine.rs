@@ -0,0 +1,267 @@
+// Quarantine of high-risk files
+//
+// Flagged files are moved under the quarantine directory (mirroring their
+// original path), a tombstone note is left next to where each one used to be,
+// and every move is appended to a JSON-lines manifest in the quarantine root.
+// A quarantined file is never replaced: if its mirrored path is taken (the
+// same file quarantined again, or paths that only differ in `.`/`..` or the
+// root), a numbered suffix is added and the manifest records the real path.
+// Tombstones get the same treatment, so the note pointing at an earlier
+// quarantined copy survives the same path being quarantined again.
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::remediate::{create_private_dir, mirrored_path};
+use crate::{CardMatch, ScanSummary};
+
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+pub const TOMBSTONE_SUFFIX: &str = ".QUARANTINED.txt";
+
+// Numbered alternatives tried when a quarantine path is already taken
+const MAX_DESTINATION_SUFFIX: usize = 10_000;
+
+// Risk tiers in increasing order of severity
+const RISK_LEVELS: &[&str] = &["low", "medium", "high"];
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub quarantined_at: String,
+    pub original_path: String,
+    pub quarantined_path: String,
+    pub tombstone_path: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub risk_level: String,
+    pub findings_count: usize,
+}
+
+// Risk levels at or above `minimum`, e.g. "medium" -> ["medium", "high"]
+pub fn levels_at_or_above(minimum: &str) -> io::Result<Vec<&'static str>> {
+    match RISK_LEVELS.iter().position(|level| *level == minimum) {
+        Some(index) => Ok(RISK_LEVELS[index..].to_vec()),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown risk level '{}' (expected low, medium or high)", minimum),
+        )),
+    }
+}
+
+// Move every file in the selected risk tiers into `quarantine_dir`
+pub fn quarantine_files(summary: &ScanSummary, results: &[CardMatch], quarantine_dir: &Path,
+                        minimum_level: &str, dry_run: bool) -> io::Result<Vec<ManifestEntry>> {
+    let levels = levels_at_or_above(minimum_level)?;
+    let mut entries = Vec::new();
+
+    if !dry_run {
+        create_private_dir(quarantine_dir)?;
+    }
+
+    for level in levels.iter().rev() {
+        let Some(files) = summary.files_by_risk.get(*level) else {
+            continue;
+        };
+
+        for file_path in files {
+            let original = Path::new(file_path);
+            let destination = mirrored_path(quarantine_dir, original);
+            let findings_count = results.iter().filter(|card| &card.file_path == file_path).count();
+
+            if dry_run {
+                println!("Would quarantine {} ({} risk, {} finding(s)) -> {}",
+                         file_path, level, findings_count, destination.display());
+                continue;
+            }
+
+            match quarantine_file(original, &destination, level, findings_count) {
+                Ok(entry) => {
+                    append_manifest(quarantine_dir, &entry)?;
+                    entries.push(entry);
+                }
+                Err(e) => eprintln!("Failed to quarantine {}: {}", file_path, e),
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+fn quarantine_file(original: &Path, mirrored: &Path, risk_level: &str,
+                   findings_count: usize) -> io::Result<ManifestEntry> {
+    let sha256 = sha256_file(original)?;
+    let size_bytes = fs::metadata(original)?.len();
+
+    if let Some(parent) = mirrored.parent() {
+        create_private_dir(parent)?;
+    }
+    let destination = &at_free_path(mirrored, |destination| move_file(original, destination))?;
+
+    let quarantined_at = chrono::Local::now().to_rfc3339();
+    let mut note = String::new();
+    let _ = writeln!(note, "This file was moved to quarantine by Luhnoxide because it contained credit card data.");
+    let _ = writeln!(note);
+    let _ = writeln!(note, "Original file: {}", original.display());
+    let _ = writeln!(note, "Quarantined at: {}", quarantined_at);
+    let _ = writeln!(note, "Risk level: {}", risk_level);
+    let _ = writeln!(note, "Card numbers found: {}", findings_count);
+    let _ = writeln!(note, "SHA-256: {}", sha256);
+    let _ = writeln!(note);
+    let _ = writeln!(note, "Contact your security team to retrieve or review this file.");
+    let tombstone = at_free_path(&tombstone_path(original), |tombstone| {
+        OpenOptions::new().write(true).create_new(true).open(tombstone)?.write_all(note.as_bytes())
+    })?;
+
+    Ok(ManifestEntry {
+        quarantined_at,
+        original_path: original.to_string_lossy().to_string(),
+        quarantined_path: destination.to_string_lossy().to_string(),
+        tombstone_path: tombstone.to_string_lossy().to_string(),
+        sha256,
+        size_bytes,
+        risk_level: risk_level.to_string(),
+        findings_count,
+    })
+}
+
+// Call `place` with `path`, or with `path` plus the first numbered suffix
+// (`a.txt.1`, `a.txt.2`, ...) for which it does not fail with AlreadyExists,
+// and return the path used
+fn at_free_path(path: &Path, mut place: impl FnMut(&Path) -> io::Result<()>) -> io::Result<PathBuf> {
+    for suffix in 0..MAX_DESTINATION_SUFFIX {
+        let candidate = if suffix == 0 {
+            path.to_path_buf()
+        } else {
+            let mut name = path.as_os_str().to_os_string();
+            name.push(format!(".{}", suffix));
+            PathBuf::from(name)
+        };
+        match place(&candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("no free path for {}", path.display()),
+    ))
+}
+
+// Move without ever replacing an existing file: link and unlink when
+// possible, otherwise copy into a newly created file and delete (e.g. across
+// filesystems). Fails with AlreadyExists if `to` is taken.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => return fs::remove_file(from),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(e),
+        Err(_) => {}
+    }
+
+    let mut target = OpenOptions::new().write(true).create_new(true).open(to)?;
+    io::copy(&mut File::open(from)?, &mut target)?;
+    target.set_permissions(fs::metadata(from)?.permissions())?;
+    drop(target);
+    if sha256_file(from)? != sha256_file(to)? {
+        let _ = fs::remove_file(to);
+        return Err(io::Error::other("copy into quarantine did not match the original"));
+    }
+    fs::remove_file(from)
+}
+
+fn tombstone_path(original: &Path) -> PathBuf {
+    let mut name = original.as_os_str().to_os_string();
+    name.push(TOMBSTONE_SUFFIX);
+    PathBuf::from(name)
+}
+
+fn append_manifest(quarantine_dir: &Path, entry: &ManifestEntry) -> io::Result<()> {
+    let mut manifest = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(quarantine_dir.join(MANIFEST_FILE))?;
+    writeln!(manifest, "{}", serde_json::to_string(entry)?)
+}
+
+// Hex-encoded SHA-256 digest of a file's contents
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quarantine(quarantine_dir: &Path, file: &Path) -> Vec<ManifestEntry> {
+        let mut summary = ScanSummary::new();
+        summary.add_file_by_risk("high", &file.to_string_lossy());
+        quarantine_files(&summary, &[], quarantine_dir, "high", false).unwrap()
+    }
+
+    #[test]
+    fn never_replaces_quarantined_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let quarantine_dir = dir.path().join("q");
+        let file = dir.path().join("x").join("d3").join("a.txt");
+        let other = dir.path().join("d3").join("a.txt");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::create_dir_all(other.parent().unwrap()).unwrap();
+
+        // The same path quarantined twice, then a different file whose path
+        // mirrors to the same place once `..` is dropped
+        fs::write(&file, "first").unwrap();
+        let mut entries = quarantine(&quarantine_dir, &file);
+        fs::write(&file, "second").unwrap();
+        entries.extend(quarantine(&quarantine_dir, &file));
+        fs::write(&other, "third").unwrap();
+        entries.extend(quarantine(&quarantine_dir, &dir.path().join("x").join("..").join("d3").join("a.txt")));
+
+        let mirrored = mirrored_path(&quarantine_dir, &file).to_string_lossy().to_string();
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.quarantined_path.as_str()).collect();
+        assert_eq!(paths, [mirrored.clone(), format!("{}.1", mirrored), format!("{}.2", mirrored)]);
+
+        let manifest: Vec<ManifestEntry> = fs::read_to_string(quarantine_dir.join(MANIFEST_FILE)).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(manifest.len(), 3);
+        let mut contents = Vec::new();
+        for entry in &manifest {
+            let path = Path::new(&entry.quarantined_path);
+            assert_eq!(sha256_file(path).unwrap(), entry.sha256, "{}", entry.quarantined_path);
+            contents.push(fs::read_to_string(path).unwrap());
+        }
+        assert_eq!(contents, ["first", "second", "third"]);
+
+        // Each move left its own tombstone
+        let tombstone = tombstone_path(&file).to_string_lossy().to_string();
+        let tombstones: Vec<&str> = manifest.iter().map(|entry| entry.tombstone_path.as_str()).collect();
+        assert_eq!(tombstones[..2], [tombstone.clone(), format!("{}.1", tombstone)]);
+        for entry in &manifest {
+            assert!(fs::read_to_string(&entry.tombstone_path).unwrap().contains(&entry.sha256));
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for dir in Path::new(&mirrored).ancestors().skip(1).take_while(|dir| *dir != quarantine_dir) {
+                assert_eq!(fs::metadata(dir).unwrap().permissions().mode() & 0o777, 0o700, "{}", dir.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+// In-place remediation of files containing card numbers
+//
+// Files are rewritten atomically: the new content goes to a temporary file in
+// the same directory, which takes over the original's permissions, ownership
+// and timestamps before being renamed over it.
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use filetime::FileTime;
+use tempfile::NamedTempFile;
+use zeroize::Zeroizing;
+
+use crate::CardMatch;
+use crate::masking::MaskPolicy;
+
+pub struct RewriteOptions<'a> {
+    pub dry_run: bool,
+    pub backup_dir: Option<&'a Path>,
+    pub show_full: bool,
+}
+
+#[derive(Default)]
+pub struct RewriteStats {
+    pub files_rewritten: usize,
+    pub lines_changed: usize,
+    pub pans_replaced: usize,
+}
+
+// Group findings by the file they were found in, preserving a stable order
+pub fn group_by_file(results: &[CardMatch]) -> BTreeMap<&str, Vec<&CardMatch>> {
+    let mut by_file: BTreeMap<&str, Vec<&CardMatch>> = BTreeMap::new();
+    for card in results {
+        by_file.entry(card.file_path.as_str()).or_default().push(card);
+    }
+    by_file
+}
+
+// Replace every detected PAN with its masked form
+pub fn redact_files(results: &[CardMatch], options: &RewriteOptions,
+                    policy: &MaskPolicy) -> io::Result<RewriteStats> {
+    rewrite_files(results, options, |card, line| card.mask_pan_in(line, policy))
+}
+
+// Rewrite every file with findings, passing each affected line through
+// `replace` once per card found on it
+pub fn rewrite_files<F>(results: &[CardMatch], options: &RewriteOptions, replace: F) -> io::Result<RewriteStats>
+where
+    F: Fn(&CardMatch, &str) -> String,
+{
+    let mut stats = RewriteStats::default();
+
+    for (file_path, cards) in group_by_file(results) {
+        let path = Path::new(file_path);
+        // The original content is wiped from memory once the file is done
+        let original = match fs::read_to_string(path) {
+            Ok(content) => Zeroizing::new(content),
+            Err(e) => {
+                eprintln!("Cannot read {} for remediation: {}", file_path, e);
+                continue;
+            }
+        };
+
+        // Original lines and every intermediate rewrite hold PANs, so they
+        // are wiped too. Redaction and tokenization keep the length of each
+        // line, so the reserved room is never outgrown.
+        let mut rewritten = Zeroizing::new(String::with_capacity(original.len()));
+        let mut changes = Vec::new();
+
+        for (index, raw_line) in original.split_inclusive('\n').enumerate() {
+            let line_number = index + 1;
+            let (line, ending) = split_line_ending(raw_line);
+            let line_cards: Vec<&&CardMatch> = cards.iter()
+                .filter(|card| card.line_number == line_number)
+                .collect();
+
+            let mut new_line = Zeroizing::new(line.to_string());
+            for card in &line_cards {
+                new_line = Zeroizing::new(replace(card, &new_line));
+            }
+
+            if new_line.as_str() != line {
+                stats.pans_replaced += line_cards.len();
+                changes.push((line_number, Zeroizing::new(line.to_string()), new_line.clone()));
+            }
+
+            rewritten.push_str(&new_line);
+            rewritten.push_str(ending);
+        }
+
+        if changes.is_empty() {
+            continue;
+        }
+
+        stats.files_rewritten += 1;
+        stats.lines_changed += changes.len();
+
+        if options.dry_run {
+            print_diff_preview(file_path, &changes, options.show_full);
+            continue;
+        }
+
+        if let Some(backup_dir) = options.backup_dir {
+            backup_file(path, backup_dir)?;
+        }
+
+        replace_atomically(path, rewritten.as_bytes())?;
+    }
+
+    Ok(stats)
+}
+
+// Write `content` to a temp file next to `path`, copy over permissions,
+// ownership and timestamps, then rename it into place
+pub fn replace_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut temp_file = NamedTempFile::new_in(parent)?;
+    temp_file.write_all(content)?;
+    temp_file.as_file().sync_all()?;
+
+    fs::set_permissions(temp_file.path(), metadata.permissions())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Only possible when running with sufficient privileges
+        let _ = std::os::unix::fs::chown(temp_file.path(), Some(metadata.uid()), Some(metadata.gid()));
+    }
+
+    temp_file.persist(path).map_err(|e| e.error)?;
+
+    filetime::set_file_times(
+        path,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )
+}
+
+// Copy the original file into the backup directory, mirroring its path.
+// Backups hold full PANs, so directories are created owner-only and each copy
+// is readable and writable by the owner only.
+fn backup_file(path: &Path, backup_dir: &Path) -> io::Result<()> {
+    create_private_dir(backup_dir)?;
+    let destination = mirrored_path(backup_dir, path);
+    if let Some(parent) = destination.parent() {
+        create_private_dir(parent)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut backup = options.open(&destination)?;
+    #[cfg(unix)]
+    {
+        // An earlier backup at the same path may have been created looser
+        use std::os::unix::fs::PermissionsExt;
+        backup.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    io::copy(&mut File::open(path)?, &mut backup)?;
+    backup.sync_all()?;
+
+    let metadata = fs::metadata(path)?;
+    filetime::set_file_times(
+        &destination,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )
+}
+
+// Create `dir` and any missing parents accessible by the owner only. A
+// directory that already exists is used as it is, never chmodded, but must
+// not be accessible by other users: `--backup-dir /tmp` is refused rather
+// than locked down under everyone else using it.
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    match fs::metadata(dir) {
+        Ok(metadata) => return check_private_dir(dir, &metadata),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        Err(_) => {}
+    }
+
+    if let Some(parent) = dir.parent() && !parent.as_os_str().is_empty() && !parent.exists() {
+        create_private_dir(parent)?;
+    }
+
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    match builder.create(dir) {
+        Ok(()) => Ok(()),
+        // Created by someone else in the meantime
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => check_private_dir(dir, &fs::metadata(dir)?),
+        Err(e) => Err(e),
+    }
+}
+
+fn check_private_dir(dir: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    if !metadata.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} exists and is not a directory", dir.display()),
+        ));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is accessible by other users; use a directory only its owner can access \
+                         (mode 0700) or one that does not exist yet", dir.display()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Location of `file` under `base`, keeping the file's own directory structure
+// (absolute paths lose their root, `.` and `..` components are dropped)
+pub fn mirrored_path(base: &Path, file: &Path) -> PathBuf {
+    let mut destination = base.to_path_buf();
+    for component in file.components() {
+        if let Component::Normal(part) = component {
+            destination.push(part);
+        }
+    }
+    destination
+}
+
+// Show what would change without touching the file. Original lines contain
+// the PANs, so they are only shown when masking is disabled.
+fn print_diff_preview(file_path: &str, changes: &[(usize, Zeroizing<String>, Zeroizing<String>)], show_full: bool) {
+    println!("--- {}", file_path);
+    println!("+++ {} (remediated)", file_path);
+    for (line_number, before, after) in changes {
+        println!("@@ line {} @@", line_number);
+        if show_full {
+            println!("-{}", before.as_str());
+        }
+        println!("+{}", after.as_str());
+    }
+}
+
+fn split_line_ending(raw_line: &str) -> (&str, &str) {
+    if let Some(line) = raw_line.strip_suffix("\r\n") {
+        (line, "\r\n")
+    } else if let Some(line) = raw_line.strip_suffix('\n') {
+        (line, "\n")
+    } else {
+        (raw_line, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scan_line, test_scan_config};
+
+    // Full-PAN findings for every line of `path`, as a --redact run has them
+    fn findings(path: &Path) -> Vec<CardMatch> {
+        let config = test_scan_config(None);
+        let content = fs::read_to_string(path).unwrap();
+        content.split('\n').enumerate()
+            .flat_map(|(index, line)| {
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                scan_line(&config, line, index + 1, 0, path.to_str().unwrap()).0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn redaction_rewrites_only_the_pans_and_keeps_the_file_as_it_was() {
+        let policy = MaskPolicy::from_config("first6last4", "X").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.csv");
+        fs::write(&path, "a,4532015112830366,b\r\nno card\r\nlast 5555-5555-5555-4444").unwrap();
+        let mtime = FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(&path, mtime).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        let results = findings(&path);
+        assert_eq!(results.len(), 2);
+
+        // A dry run reports the changes and leaves the file alone
+        let options = RewriteOptions { dry_run: true, backup_dir: None, show_full: false };
+        let stats = redact_files(&results, &options, &policy).unwrap();
+        assert_eq!((stats.files_rewritten, stats.lines_changed, stats.pans_replaced), (1, 2, 2));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a,4532015112830366,b\r\nno card\r\nlast 5555-5555-5555-4444");
+        assert_eq!(FileTime::from_last_modification_time(&fs::metadata(&path).unwrap()), mtime);
+
+        let options = RewriteOptions { dry_run: false, ..options };
+        let stats = redact_files(&results, &options, &policy).unwrap();
+        assert_eq!((stats.files_rewritten, stats.lines_changed, stats.pans_replaced), (1, 2, 2));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a,453201XXXXXX0366,b\r\nno card\r\nlast 5555-55XX-XXXX-4444");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), mtime);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // Nothing is left to redact
+        assert!(findings(&path).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn backups_are_private_to_the_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("logs").join("app.log");
+        fs::create_dir_all(original.parent().unwrap()).unwrap();
+        fs::write(&original, "pay 4532015112830366\n").unwrap();
+        fs::set_permissions(&original, fs::Permissions::from_mode(0o644)).unwrap();
+        let backup_dir = dir.path().join("backups");
+
+        backup_file(&original, &backup_dir).unwrap();
+
+        let backup = mirrored_path(&backup_dir, &original);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "pay 4532015112830366\n");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&backup), 0o600);
+        assert_eq!(mode(&backup_dir), 0o700);
+        assert_eq!(mode(backup.parent().unwrap()), 0o700);
+
+        // Existing directories are never chmodded; shared ones are refused
+        let shared = dir.path().join("shared");
+        fs::create_dir(&shared).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o1777)).unwrap();
+        let error = backup_file(&original, &shared).unwrap_err();
+        assert!(error.to_string().contains(shared.to_str().unwrap()), "{}", error);
+        assert_eq!(fs::metadata(&shared).unwrap().permissions().mode() & 0o7777, 0o1777);
+        assert!(fs::read_dir(&shared).unwrap().next().is_none());
+
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o700)).unwrap();
+        backup_file(&original, &shared).unwrap();
+        assert_eq!(mode(&shared), 0o700);
+    }
+}
@@ -0,0 +1,522 @@
+// SIEM integration: CEF and LEEF events and an RFC 5424 syslog sink
+//
+// A scan becomes one event per finding plus a summary event. The same events
+// are either written out as a CEF or LEEF output format, one per line, or
+// sent to a syslog collector over UDP, TCP (octet-counted framing, RFC 6587)
+// or a Unix socket such as /dev/log. A scan comparison from `luhnoxide diff`
+// becomes an event per added or resolved finding plus a comparison summary.
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::diff::{ScanDiff, Status};
+use crate::{CardMatch, ScanSummary};
+
+const VENDOR: &str = "Luhnoxide";
+const PRODUCT: &str = "Luhnoxide";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Syslog facility for every message (user-level messages)
+const FACILITY: u8 = 1;
+
+const DEFAULT_UDP_PORT: u16 = 514;
+const DEFAULT_TCP_PORT: u16 = 601;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventFormat {
+    Cef,
+    Leef,
+}
+
+impl EventFormat {
+    pub fn from_str(s: &str) -> io::Result<EventFormat> {
+        match s.to_lowercase().as_str() {
+            "cef" => Ok(EventFormat::Cef),
+            "leef" => Ok(EventFormat::Leef),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown event format: {}", s))),
+        }
+    }
+}
+
+// One formatted event with what syslog needs to frame it
+pub struct Event {
+    // Syslog severity, 0 (emergency) to 7 (debug)
+    pub severity: u8,
+    pub msgid: &'static str,
+    pub message: String,
+}
+
+// Severities of a finding by its file's risk tier: (syslog, CEF/LEEF 0-10)
+fn finding_severity(risk: &str) -> (u8, u8) {
+    match risk {
+        "high" => (2, 9),
+        "medium" => (3, 7),
+        _ => (4, 5),
+    }
+}
+
+// Events for every finding, then the summary
+pub fn events(format: EventFormat, summary: &ScanSummary, findings: &[CardMatch]) -> Vec<Event> {
+    let now = Utc::now();
+    let risk_of: HashMap<&str, &str> = summary.files_by_risk.iter()
+        .flat_map(|(level, files)| files.iter().map(move |file| (file.as_str(), level.as_str())))
+        .collect();
+
+    let mut events: Vec<Event> = findings.iter()
+        .map(|card| {
+            let risk = risk_of.get(card.file_path.as_str()).copied().unwrap_or("low");
+            let (severity, event_severity) = finding_severity(risk);
+            let message = match format {
+                EventFormat::Cef => cef_finding(card, risk, event_severity, now),
+                EventFormat::Leef => leef_finding(card, risk, event_severity, now),
+            };
+            Event { severity, msgid: "CARD_FOUND", message }
+        })
+        .collect();
+
+    let (severity, event_severity) = if summary.total_cards_found > 0 { (5, 5) } else { (6, 1) };
+    let message = match format {
+        EventFormat::Cef => cef_summary(summary, event_severity, now),
+        EventFormat::Leef => leef_summary(summary, event_severity, now),
+    };
+    events.push(Event { severity, msgid: "SCAN_SUMMARY", message });
+    events
+}
+
+// Events for every added and resolved finding, then the comparison summary.
+// Resolved findings are informational; added ones keep their risk severity.
+pub fn diff_events(format: EventFormat, diff: &ScanDiff) -> Vec<Event> {
+    let now = Utc::now();
+    let mut events: Vec<Event> = diff.changes.iter()
+        .filter(|change| change.status != Status::Unchanged)
+        .map(|change| {
+            let risk = diff.risk(&change.card.file_path);
+            let (severity, event_severity) = match change.status {
+                Status::Resolved => (6, 1),
+                _ => finding_severity(risk),
+            };
+            let (msgid, signature, name, event_id) = match change.status {
+                Status::Resolved => ("CARD_RESOLVED", "card-resolved", "Payment card number resolved", "CardResolved"),
+                _ => ("CARD_ADDED", "card-added", "Payment card number added", "CardAdded"),
+            };
+            let message = match format {
+                EventFormat::Cef => cef_card(signature, name, &change.card, risk, event_severity, now),
+                EventFormat::Leef => leef_card(event_id, &change.card, risk, event_severity, now),
+            };
+            Event { severity, msgid, message }
+        })
+        .collect();
+
+    let fields = vec![
+        ("added", diff.count(Status::Added).to_string()),
+        ("resolved", diff.count(Status::Resolved).to_string()),
+        ("unchanged", diff.count(Status::Unchanged).to_string()),
+        ("oldScan", diff.old.clone()),
+        ("newScan", diff.new.clone()),
+    ];
+    let (severity, event_severity) = if diff.count(Status::Added) > 0 { (5, 5) } else { (6, 1) };
+    let message = match format {
+        EventFormat::Cef => {
+            let mut extension = vec![("rt".to_string(), now.timestamp_millis().to_string())];
+            let (numbers, strings) = fields.split_at(3);
+            for (index, (label, value)) in numbers.iter().enumerate() {
+                extension.push((format!("cn{}Label", index + 1), label.to_string()));
+                extension.push((format!("cn{}", index + 1), value.clone()));
+            }
+            for (index, (label, value)) in strings.iter().enumerate() {
+                extension.push((format!("cs{}Label", index + 1), label.to_string()));
+                extension.push((format!("cs{}", index + 1), value.clone()));
+            }
+            cef("scan-diff", "Card data scans compared", event_severity, &extension)
+        }
+        EventFormat::Leef => {
+            let mut attributes = vec![
+                ("cat", "DataDiscovery".to_string()),
+                ("sev", event_severity.to_string()),
+                ("devTime", leef_time(now)),
+                ("devTimeFormat", LEEF_TIME_FORMAT.to_string()),
+            ];
+            attributes.extend(fields);
+            leef("ScanDiff", &attributes)
+        }
+    };
+    events.push(Event { severity, msgid: "SCAN_DIFF", message });
+    events
+}
+
+fn finding_fields(card: &CardMatch, risk: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("brand", card.brand.clone()),
+        ("pan", card.full_pan.clone()),
+        ("fingerprint", card.fingerprint.clone()),
+        ("risk", risk.to_string()),
+        ("line", card.line_number.to_string()),
+        ("column", card.column_start.to_string()),
+        ("context", card.context.clone()),
+    ]
+}
+
+fn summary_fields(summary: &ScanSummary) -> Vec<(&'static str, String)> {
+    vec![
+        ("filesScanned", summary.total_files_scanned.to_string()),
+        ("filesWithCards", summary.total_files_with_cards.to_string()),
+        ("cardsFound", summary.total_cards_found.to_string()),
+        ("uniqueCards", summary.unique_cards_found.to_string()),
+        ("highRiskFiles", summary.files_by_risk.get("high").map_or(0, Vec::len).to_string()),
+        ("mediumRiskFiles", summary.files_by_risk.get("medium").map_or(0, Vec::len).to_string()),
+        ("lowRiskFiles", summary.files_by_risk.get("low").map_or(0, Vec::len).to_string()),
+        ("skippedFiles", summary.skipped_files.len().to_string()),
+        ("scanDuration", summary.scan_duration.clone()),
+    ]
+}
+
+// CEF:Version|Vendor|Product|Version|Signature ID|Name|Severity|Extension
+// Values that have no standard CEF key go in labelled custom strings.
+fn cef_finding(card: &CardMatch, risk: &str, severity: u8, time: DateTime<Utc>) -> String {
+    cef_card("card-found", "Payment card number found", card, risk, severity, time)
+}
+
+fn cef_card(signature: &str, name: &str, card: &CardMatch, risk: &str, severity: u8, time: DateTime<Utc>) -> String {
+    let mut extension = vec![
+        ("rt".to_string(), time.timestamp_millis().to_string()),
+        ("filePath".to_string(), card.file_path.clone()),
+        ("cn1Label".to_string(), "line".to_string()),
+        ("cn1".to_string(), card.line_number.to_string()),
+        ("cn2Label".to_string(), "column".to_string()),
+        ("cn2".to_string(), card.column_start.to_string()),
+    ];
+    let custom = finding_fields(card, risk).into_iter()
+        .filter(|(name, _)| !matches!(*name, "line" | "column"));
+    for (index, (label, value)) in custom.enumerate() {
+        extension.push((format!("cs{}Label", index + 1), label.to_string()));
+        extension.push((format!("cs{}", index + 1), value));
+    }
+    cef(signature, name, severity, &extension)
+}
+
+fn cef_summary(summary: &ScanSummary, severity: u8, time: DateTime<Utc>) -> String {
+    let mut extension = vec![
+        ("rt".to_string(), time.timestamp_millis().to_string()),
+        ("cnt".to_string(), summary.total_cards_found.to_string()),
+    ];
+    // CEF has three numeric and six string custom fields
+    let fields = summary_fields(summary);
+    let (numbers, strings) = fields.split_at(3);
+    for (index, (label, value)) in numbers.iter().enumerate() {
+        extension.push((format!("cn{}Label", index + 1), label.to_string()));
+        extension.push((format!("cn{}", index + 1), value.clone()));
+    }
+    for (index, (label, value)) in strings.iter().enumerate() {
+        extension.push((format!("cs{}Label", index + 1), label.to_string()));
+        extension.push((format!("cs{}", index + 1), value.clone()));
+    }
+    cef("scan-summary", "Card data scan completed", severity, &extension)
+}
+
+fn cef(signature: &str, name: &str, severity: u8, extension: &[(String, String)]) -> String {
+    let header: Vec<String> = [VENDOR, PRODUCT, VERSION, signature, name].iter()
+        .map(|field| cef_header(field))
+        .collect();
+    let extension: Vec<String> = extension.iter()
+        .map(|(key, value)| format!("{}={}", key, cef_value(value)))
+        .collect();
+    format!("CEF:0|{}|{}|{}", header.join("|"), severity, extension.join(" "))
+}
+
+// Header fields escape '\' and '|'
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+// Extension values escape '\' and '=' and encode line breaks
+fn cef_value(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+// LEEF:1.0|Vendor|Product|Version|EventID| then tab-separated key=value
+fn leef_finding(card: &CardMatch, risk: &str, severity: u8, time: DateTime<Utc>) -> String {
+    leef_card("CardFound", card, risk, severity, time)
+}
+
+fn leef_card(event_id: &str, card: &CardMatch, risk: &str, severity: u8, time: DateTime<Utc>) -> String {
+    let mut attributes = vec![
+        ("cat", "DataDiscovery".to_string()),
+        ("sev", severity.to_string()),
+        ("devTime", leef_time(time)),
+        ("devTimeFormat", LEEF_TIME_FORMAT.to_string()),
+        ("filePath", card.file_path.clone()),
+    ];
+    attributes.extend(finding_fields(card, risk));
+    leef(event_id, &attributes)
+}
+
+fn leef_summary(summary: &ScanSummary, severity: u8, time: DateTime<Utc>) -> String {
+    let mut attributes = vec![
+        ("cat", "DataDiscovery".to_string()),
+        ("sev", severity.to_string()),
+        ("devTime", leef_time(time)),
+        ("devTimeFormat", LEEF_TIME_FORMAT.to_string()),
+    ];
+    attributes.extend(summary_fields(summary));
+    leef("ScanSummary", &attributes)
+}
+
+// devTime layout, given in Java SimpleDateFormat syntax as LEEF expects
+const LEEF_TIME_FORMAT: &str = "yyyy-MM-dd'T'HH:mm:ss.SSSZ";
+
+fn leef_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3f%z").to_string()
+}
+
+fn leef(event_id: &str, attributes: &[(&str, String)]) -> String {
+    let header: Vec<String> = [VENDOR, PRODUCT, VERSION, event_id].iter()
+        .map(|field| cef_header(field))
+        .collect();
+    let attributes: Vec<String> = attributes.iter()
+        .map(|(key, value)| format!("{}={}", key, value.replace(['\t', '\r', '\n'], " ")))
+        .collect();
+    format!("LEEF:1.0|{}|{}", header.join("|"), attributes.join("\t"))
+}
+
+enum Transport {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    UnixDatagram(UnixDatagram),
+    #[cfg(unix)]
+    UnixStream(UnixStream),
+}
+
+// Syslog collector that events are sent to as RFC 5424 messages
+pub struct SyslogSink {
+    transport: Transport,
+    hostname: String,
+    procid: u32,
+}
+
+impl SyslogSink {
+    // Connect to `udp://host[:port]`, `tcp://host[:port]` or `unix:///path`.
+    // UDP defaults to port 514 and TCP to 601; IPv6 hosts go in brackets.
+    pub fn connect(target: &str) -> io::Result<SyslogSink> {
+        let invalid = || io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid syslog target {} (expected udp://host:port, tcp://host:port or unix:///path)", target),
+        );
+        let (scheme, address) = target.split_once("://").ok_or_else(invalid)?;
+
+        let transport = match scheme.to_lowercase().as_str() {
+            "udp" => {
+                let address = with_default_port(address, DEFAULT_UDP_PORT);
+                let bind = if address.starts_with('[') { "[::]:0" } else { "0.0.0.0:0" };
+                let socket = UdpSocket::bind(bind)?;
+                socket.connect(&address)?;
+                Transport::Udp(socket)
+            }
+            "tcp" => {
+                let stream = TcpStream::connect(with_default_port(address, DEFAULT_TCP_PORT))?;
+                stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+                Transport::Tcp(stream)
+            }
+            #[cfg(unix)]
+            "unix" => {
+                // /dev/log is usually a datagram socket, but some daemons listen on a stream
+                let socket = UnixDatagram::unbound()?;
+                match socket.connect(address) {
+                    Ok(()) => Transport::UnixDatagram(socket),
+                    Err(_) => Transport::UnixStream(UnixStream::connect(address)?),
+                }
+            }
+            _ => return Err(invalid()),
+        };
+
+        Ok(SyslogSink {
+            transport,
+            hostname: gethostname::gethostname().to_string_lossy().to_string(),
+            procid: std::process::id(),
+        })
+    }
+
+    pub fn send(&mut self, event: &Event) -> io::Result<()> {
+        let message = format_message(event, Utc::now(), &self.hostname, self.procid);
+        match &mut self.transport {
+            Transport::Udp(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            // Octet counting, so messages may safely contain line breaks
+            Transport::Tcp(stream) => write!(stream, "{} {}", message.len(), message),
+            #[cfg(unix)]
+            Transport::UnixDatagram(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            #[cfg(unix)]
+            Transport::UnixStream(stream) => stream.write_all(format!("{}\n", message).as_bytes()),
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self.transport {
+            Transport::Tcp(mut stream) => stream.flush(),
+            #[cfg(unix)]
+            Transport::UnixStream(mut stream) => stream.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn with_default_port(address: &str, port: u16) -> String {
+    let has_port = address.rsplit_once(':')
+        .is_some_and(|(host, port)| port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']')));
+    if has_port { address.to_string() } else { format!("{}:{}", address, port) }
+}
+
+// <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+fn format_message(event: &Event, time: DateTime<Utc>, hostname: &str, procid: u32) -> String {
+    format!("<{}>1 {} {} luhnoxide {} {} - {}",
+            FACILITY * 8 + event.severity,
+            time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            header_field(hostname),
+            procid,
+            event.msgid,
+            event.message)
+}
+
+// Header fields are printable ASCII without spaces, or "-" when empty
+fn header_field(value: &str) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).take(255).collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::test_report;
+
+    #[test]
+    fn cef_escapes_headers_and_extension_values() {
+        let (summary, cards) = test_report(&[("/data/a|b=c.txt", "pan=4532015112830366 \\ end")]);
+        let events = events(EventFormat::Cef, &summary, &cards);
+        assert_eq!(events.len(), 2);
+
+        let finding = &events[0].message;
+        assert!(finding.starts_with(&format!("CEF:0|Luhnoxide|Luhnoxide|{}|card-found|Payment card number found|5|", VERSION)));
+        assert!(finding.contains("filePath=/data/a|b\\=c.txt"));
+        assert!(finding.contains("cs2Label=pan cs2=453201XXXXXX0366"));
+        assert!(finding.contains("cs5=pan\\=453201XXXXXX0366 \\\\ end"));
+        assert!(!finding.contains("4532015112830366"));
+
+        assert!(events[1].message.contains("|scan-summary|Card data scan completed|5|"));
+        assert!(events[1].message.contains("cn1Label=filesScanned cn1=1"));
+    }
+
+    #[test]
+    fn leef_uses_tab_separated_attributes() {
+        let (summary, cards) = test_report(&[("a.txt", "a\t4532015112830366")]);
+        let events = events(EventFormat::Leef, &summary, &cards);
+
+        let finding = &events[0].message;
+        assert!(finding.starts_with(&format!("LEEF:1.0|Luhnoxide|Luhnoxide|{}|CardFound|cat=DataDiscovery\tsev=5\t", VERSION)));
+        assert!(finding.contains("\tpan=453201XXXXXX0366\t"));
+        assert!(finding.contains("\tcontext=a 453201XXXXXX0366"));
+        assert!(events[1].message.contains("|ScanSummary|"));
+    }
+
+    #[test]
+    fn formats_rfc5424_messages() {
+        let event = Event { severity: 3, msgid: "CARD_FOUND", message: "CEF:0|x".to_string() };
+        let time = DateTime::parse_from_rfc3339("2024-05-01T10:20:30.123Z").unwrap().with_timezone(&Utc);
+        assert_eq!(format_message(&event, time, "scan host", 42),
+                   "<11>1 2024-05-01T10:20:30.123Z scanhost luhnoxide 42 CARD_FOUND - CEF:0|x");
+    }
+
+    #[test]
+    fn adds_default_ports() {
+        assert_eq!(with_default_port("collector", 514), "collector:514");
+        assert_eq!(with_default_port("collector:1514", 514), "collector:1514");
+        assert_eq!(with_default_port("[::1]", 601), "[::1]:601");
+        assert_eq!(with_default_port("[::1]:6514", 601), "[::1]:6514");
+    }
+
+    #[test]
+    fn sends_over_udp() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let (summary, cards) = test_report(&[("a.txt", "4532015112830366")]);
+
+        let mut sink = SyslogSink::connect(&format!("udp://{}", listener.local_addr().unwrap())).unwrap();
+        for event in events(EventFormat::Cef, &summary, &cards) {
+            sink.send(&event).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let mut buffer = [0u8; 4096];
+        let length = listener.recv(&mut buffer).unwrap();
+        let message = String::from_utf8_lossy(&buffer[..length]);
+        assert!(message.starts_with("<12>1 "), "{}", message);
+        assert!(message.contains(" luhnoxide ") && message.contains(" CARD_FOUND - CEF:0|"));
+
+        let length = listener.recv(&mut buffer).unwrap();
+        assert!(String::from_utf8_lossy(&buffer[..length]).contains(" SCAN_SUMMARY - CEF:0|"));
+    }
+
+    #[test]
+    fn sends_octet_counted_frames_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (summary, cards) = test_report(&[("line\nbreak.txt", "4532015112830366")]);
+
+        let mut sink = SyslogSink::connect(&format!("tcp://{}", address)).unwrap();
+        for event in events(EventFormat::Leef, &summary, &cards) {
+            sink.send(&event).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut frames = Vec::new();
+        loop {
+            let mut length = Vec::new();
+            if reader.read_until(b' ', &mut length).unwrap() == 0 {
+                break;
+            }
+            let length: usize = String::from_utf8(length).unwrap().trim().parse().unwrap();
+            let mut frame = vec![0u8; length];
+            reader.read_exact(&mut frame).unwrap();
+            frames.push(String::from_utf8(frame).unwrap());
+        }
+
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].contains(" CARD_FOUND - LEEF:1.0|"));
+        assert!(frames[0].contains("filePath=line break.txt"));
+        assert!(frames[1].contains(" SCAN_SUMMARY - LEEF:1.0|"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sends_to_a_unix_datagram_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.sock");
+        let listener = UnixDatagram::bind(&path).unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let (summary, cards) = test_report(&[("a.txt", "4532015112830366")]);
+
+        let mut sink = SyslogSink::connect(&format!("unix://{}", path.display())).unwrap();
+        sink.send(&events(EventFormat::Cef, &summary, &cards)[0]).unwrap();
+
+        let mut buffer = [0u8; 4096];
+        let length = listener.recv(&mut buffer).unwrap();
+        assert!(String::from_utf8_lossy(&buffer[..length]).contains(" CARD_FOUND - CEF:0|"));
+    }
+
+    #[test]
+    fn rejects_unknown_targets() {
+        assert!(SyslogSink::connect("http://collector").is_err());
+        assert!(SyslogSink::connect("collector:514").is_err());
+    }
+}
@@ -0,0 +1,311 @@
+// Format-preserving tokenization of PANs
+//
+// A token keeps the PAN's length, BIN (first six) and last four digits and
+// passes the Luhn check, so downstream systems that validate card numbers
+// keep working. The digits in between are encrypted with a keyed Feistel
+// network over decimal digits (HMAC-SHA256 round function, FF1-style
+// alternating halves) tweaked with the BIN, last four and length, and the
+// final middle digit is chosen to make the token Luhn-valid. The same key
+// always maps the same PAN to the same token.
+//
+// The Luhn digit of a PAN is determined by its other digits, so within one
+// BIN/last four/length bucket the encrypted digits identify the PAN. They are
+// mapped by E^-1(E(x) + 1), with E the Feistel permutation: a permutation of
+// the bucket, so no two PANs share a token, and one without fixed points, so
+// no token equals its PAN.
+//
+// The token -> PAN mapping is written to a vault file readable only by its
+// owner; without the vault (or the key) tokens cannot be mapped back. The
+// vault is not encrypted: it holds the cleartext PANs, and file permissions
+// are its only protection.
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::masking;
+use crate::remediate::{self, RewriteOptions, RewriteStats};
+use crate::{is_valid_luhn, CardMatch};
+
+pub const KEY_ENV_VAR: &str = "LUHNOXIDE_TOKEN_KEY";
+
+const FEISTEL_ROUNDS: u8 = 10;
+const VISIBLE_PREFIX: usize = 6;
+const VISIBLE_SUFFIX: usize = 4;
+
+#[derive(Serialize, Deserialize)]
+pub struct VaultEntry {
+    pub token: String,
+    pub pan: String,
+    pub brand: String,
+    pub tokenized_at: String,
+}
+
+pub struct Tokenizer {
+    key: Vec<u8>,
+}
+
+impl Tokenizer {
+    pub fn new(key: Vec<u8>) -> Self {
+        Tokenizer { key }
+    }
+
+    // Produce the token for a PAN (digits only, 13-19 long)
+    pub fn tokenize(&self, pan: &str) -> String {
+        let digits: Vec<u8> = pan.bytes().map(|b| b - b'0').collect();
+        let middle_start = VISIBLE_PREFIX;
+        let middle_end = digits.len() - VISIBLE_SUFFIX;
+        // The last middle digit becomes the Luhn check adjustment
+        let encrypted_end = middle_end - 1;
+
+        let tweak = format!("{}:{}:{}", &pan[..VISIBLE_PREFIX], &pan[middle_end..], digits.len());
+        let middle = &digits[middle_start..encrypted_end];
+
+        let shifted = increment_digits(&self.encrypt_digits(&tweak, middle));
+        let mut token: Vec<u8> = digits[..middle_start].to_vec();
+        token.extend(self.decrypt_digits(&tweak, &shifted));
+        token.push(0);
+        token.extend_from_slice(&digits[middle_end..]);
+
+        fix_luhn_digit(&mut token, encrypted_end)
+    }
+
+    // Feistel encryption of a decimal digit string of at least two digits
+    fn encrypt_digits(&self, tweak: &str, digits: &[u8]) -> Vec<u8> {
+        let u = digits.len() / 2;
+        let v = digits.len() - u;
+        let mut a = digits_to_number(&digits[..u]);
+        let mut b = digits_to_number(&digits[u..]);
+
+        for round in 0..FEISTEL_ROUNDS {
+            let (m, b_len) = if round % 2 == 0 { (u, v) } else { (v, u) };
+            let modulus = 10u64.pow(m as u32);
+            let f = self.round_function(tweak, round, b, b_len) % modulus;
+            let c = (a + f) % modulus;
+            a = b;
+            b = c;
+        }
+
+        let mut output = number_to_digits(a, u);
+        output.extend(number_to_digits(b, v));
+        output
+    }
+
+    // Inverse of encrypt_digits
+    fn decrypt_digits(&self, tweak: &str, digits: &[u8]) -> Vec<u8> {
+        let u = digits.len() / 2;
+        let v = digits.len() - u;
+        let mut a = digits_to_number(&digits[..u]);
+        let mut b = digits_to_number(&digits[u..]);
+
+        for round in (0..FEISTEL_ROUNDS).rev() {
+            let (m, b_len) = if round % 2 == 0 { (u, v) } else { (v, u) };
+            let modulus = 10u64.pow(m as u32);
+            let f = self.round_function(tweak, round, a, b_len) % modulus;
+            let c = (b + modulus - f) % modulus;
+            b = a;
+            a = c;
+        }
+
+        let mut output = number_to_digits(a, u);
+        output.extend(number_to_digits(b, v));
+        output
+    }
+
+    fn round_function(&self, tweak: &str, round: u8, value: u64, width: usize) -> u64 {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(tweak.as_bytes());
+        mac.update(&[round]);
+        mac.update(format!("{:0width$}", value, width = width).as_bytes());
+        let digest = mac.finalize().into_bytes();
+
+        let mut prefix = [0u8; 8];
+        prefix.copy_from_slice(&digest[..8]);
+        u64::from_be_bytes(prefix)
+    }
+}
+
+// Set the digit at `position` so the whole number passes the Luhn check
+fn fix_luhn_digit(digits: &mut [u8], position: usize) -> String {
+    for candidate in 0..10 {
+        digits[position] = candidate;
+        let number: String = digits.iter().map(|d| (b'0' + d) as char).collect();
+        if is_valid_luhn(&number) {
+            return number;
+        }
+    }
+    unreachable!("one of ten digits always satisfies the Luhn check")
+}
+
+// Add one to a decimal digit string, wrapping around to all zeros
+fn increment_digits(digits: &[u8]) -> Vec<u8> {
+    let mut output = digits.to_vec();
+    for digit in output.iter_mut().rev() {
+        if *digit == 9 {
+            *digit = 0;
+        } else {
+            *digit += 1;
+            break;
+        }
+    }
+    output
+}
+
+fn digits_to_number(digits: &[u8]) -> u64 {
+    digits.iter().fold(0, |acc, &d| acc * 10 + d as u64)
+}
+
+fn number_to_digits(mut value: u64, width: usize) -> Vec<u8> {
+    let mut digits = vec![0u8; width];
+    for slot in digits.iter_mut().rev() {
+        *slot = (value % 10) as u8;
+        value /= 10;
+    }
+    digits
+}
+
+// Replace the card's PAN in `text` with `token`, keeping any separators the
+// PAN was written with in place
+pub fn replace_pan_digits(text: &str, pan: &str, token: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut last_end = 0;
+
+    for (start, end) in masking::find_pan(text, pan) {
+        replaced.push_str(&text[last_end..start]);
+        let mut token_digits = token.chars();
+        for c in text[start..end].chars() {
+            if c.is_ascii_digit() {
+                replaced.push(token_digits.next().unwrap_or(c));
+            } else {
+                replaced.push(c);
+            }
+        }
+        last_end = end;
+    }
+
+    replaced.push_str(&text[last_end..]);
+    replaced
+}
+
+// Tokenize every finding, record new tokens in the vault, then rewrite the
+// affected files. The vault is written first so no token is ever lost.
+pub fn tokenize_files(results: &[CardMatch], tokenizer: &Tokenizer, vault_path: &Path,
+                      options: &RewriteOptions) -> io::Result<RewriteStats> {
+    let tokens: BTreeMap<&str, String> = results.iter()
+        .map(|card| (card.full_pan.as_str(), tokenizer.tokenize(&card.full_pan)))
+        .collect();
+
+    if !options.dry_run {
+        let mut known = read_vault_tokens(vault_path)?;
+        let mut vault = open_vault(vault_path)?;
+        let tokenized_at = chrono::Local::now().to_rfc3339();
+
+        for card in results {
+            let token = &tokens[card.full_pan.as_str()];
+            if !known.insert(token.clone()) {
+                continue;
+            }
+            let entry = VaultEntry {
+                token: token.clone(),
+                pan: card.full_pan.clone(),
+                brand: card.brand.clone(),
+                tokenized_at: tokenized_at.clone(),
+            };
+            writeln!(vault, "{}", serde_json::to_string(&entry)?)?;
+        }
+        vault.sync_all()?;
+    }
+
+    let stats = remediate::rewrite_files(results, options, |card, line| {
+        replace_pan_digits(line, &card.full_pan, &tokens[card.full_pan.as_str()])
+    })?;
+
+    Ok(stats)
+}
+
+// Tokens already present in the vault, so repeated runs don't duplicate them
+fn read_vault_tokens(vault_path: &Path) -> io::Result<HashSet<String>> {
+    let mut tokens = HashSet::new();
+    if !vault_path.exists() {
+        return Ok(tokens);
+    }
+
+    for line in BufReader::new(File::open(vault_path)?).lines() {
+        let line = line?;
+        if let Ok(entry) = serde_json::from_str::<VaultEntry>(&line) {
+            tokens.insert(entry.token);
+        }
+    }
+    Ok(tokens)
+}
+
+// Open the vault for appending, readable and writable by the owner only
+fn open_vault(vault_path: &Path) -> io::Result<File> {
+    if let Some(parent) = vault_path.parent() && !parent.as_os_str().is_empty() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let vault = options.open(vault_path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(vault_path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(vault)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every Luhn-valid PAN with the given BIN, last four and length
+    fn bucket(bin: &str, last_four: &str, length: usize) -> Vec<String> {
+        let middle = length - bin.len() - last_four.len();
+        (0..10u64.pow(middle as u32))
+            .map(|value| format!("{}{:0width$}{}", bin, value, last_four, width = middle))
+            .filter(|pan| is_valid_luhn(pan))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_are_luhn_valid_keep_bin_and_last_four_and_never_collide() {
+        let tokenizer = Tokenizer::new(b"0123456789abcdef0123456789abcdef".to_vec());
+
+        // 13 digits leave only two encrypted digits, the easiest case to collide
+        for (bin, last_four, length) in [("422222", "1234", 13), ("301234", "5678", 14)] {
+            let pans = bucket(bin, last_four, length);
+            let mut tokens = HashSet::new();
+            for pan in &pans {
+                let token = tokenizer.tokenize(pan);
+                assert_eq!(token.len(), length);
+                assert!(is_valid_luhn(&token), "{} -> {}", pan, token);
+                assert!(token.starts_with(bin) && token.ends_with(last_four), "{} -> {}", pan, token);
+                assert_ne!(&token, pan);
+                assert_eq!(tokenizer.tokenize(pan), token);
+                assert!(tokens.insert(token), "{} shares its token", pan);
+            }
+            assert_eq!(tokens.len(), pans.len());
+        }
+    }
+
+    #[test]
+    fn decryption_inverts_encryption() {
+        let tokenizer = Tokenizer::new(b"another key of sixteen bytes".to_vec());
+        for digits in [vec![0, 0], vec![9, 9], vec![1, 2, 3, 4, 5], vec![9, 8, 7, 6, 5, 4, 3, 2]] {
+            let encrypted = tokenizer.encrypt_digits("tweak", &digits);
+            assert_eq!(tokenizer.decrypt_digits("tweak", &encrypted), digits);
+        }
+    }
+}
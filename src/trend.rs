@@ -0,0 +1,233 @@
+// Trends across stored scans
+//
+// `luhnoxide report trend --db FILE` reads the scans recorded with --db and
+// shows how the headline figures move over time: card numbers found, unique
+// cards, files with cards and the share of clean files. HTML output charts
+// them, and a scan run with both --db and -f html gets the same charts in
+// its report, with the scan itself as the latest point.
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::ArgMatches;
+use serde::Serialize;
+
+use crate::db::Database;
+use crate::encrypt::ReportWriter;
+use crate::{html, markdown, ScanSummary};
+
+#[derive(Clone, Serialize)]
+pub struct TrendPoint {
+    // None for a scan that has not been stored yet
+    pub scan_id: Option<i64>,
+    pub scan_date: String,
+    pub files_scanned: usize,
+    pub files_with_cards: usize,
+    pub cards_found: usize,
+    pub unique_cards: usize,
+    // Percentage of scanned files free of card data
+    pub clean_percentage: f64,
+}
+
+impl TrendPoint {
+    pub fn from_summary(summary: &ScanSummary) -> TrendPoint {
+        TrendPoint {
+            scan_id: None,
+            scan_date: summary.scan_date.clone(),
+            files_scanned: summary.total_files_scanned,
+            files_with_cards: summary.total_files_with_cards,
+            cards_found: summary.total_cards_found,
+            unique_cards: summary.unique_cards_found,
+            clean_percentage: summary.clean_percentage(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self.scan_id {
+            Some(id) => format!("#{} {}", id, self.scan_date),
+            None => format!("current {}", self.scan_date),
+        }
+    }
+}
+
+// Reads one charted figure from a point
+pub type Series = fn(&TrendPoint) -> f64;
+
+// The charted series, as (title, value of a point)
+pub const SERIES: &[(&str, Series)] = &[
+    ("Card Numbers Found", |point| point.cards_found as f64),
+    ("Unique Cards", |point| point.unique_cards as f64),
+    ("Files Containing Cards", |point| point.files_with_cards as f64),
+    ("Clean Files (%)", |point| point.clean_percentage),
+];
+
+// Change between the first and last point of each series, as (label, text)
+pub fn changes(points: &[TrendPoint]) -> Vec<(&'static str, String)> {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return Vec::new();
+    };
+    SERIES.iter()
+        .map(|(title, value)| {
+            let (from, to) = (value(first), value(last));
+            let text = if title.ends_with("(%)") {
+                format!("{:.1}% ({:+.1} points)", to, to - from)
+            } else {
+                format!("{} ({:+})", to, to as i64 - from as i64)
+            };
+            (*title, text)
+        })
+        .collect()
+}
+
+// Run `report trend`
+pub fn run(matches: &ArgMatches) -> io::Result<()> {
+    let path = Path::new(matches.value_of("db").unwrap());
+    let last = matches.value_of("last")
+        .map(|value| value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "--last must be a positive number")
+        }))
+        .transpose()?;
+    let inputs = matches.value_of("inputs");
+
+    let database = Database::open_read_only(path)?;
+    let points = database.scan_history(inputs, last)?;
+    if points.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} holds no scans{}", path.display(),
+                    inputs.map(|inputs| format!(" of {}", inputs)).unwrap_or_default()),
+        ));
+    }
+
+    let format_str = matches.value_of("format").unwrap_or("text");
+    let source = path.display().to_string();
+    let report = match format_str {
+        "json" => {
+            let mut json = serde_json::to_vec_pretty(&serde_json::json!({ "database": source, "scans": points }))?;
+            json.push(b'\n');
+            json
+        }
+        "csv" => csv(&points)?,
+        "markdown" => markdown::render_trend(&source, &points).into_bytes(),
+        "html" => html::render_trend(&source, &points)?.into_bytes(),
+        _ => text(&points).into_bytes(),
+    };
+
+    match matches.value_of("output") {
+        Some(output) => {
+            let mut writer = ReportWriter::create(Path::new(output), &[])?;
+            writer.write_all(&report)?;
+            writer.finish()?;
+            println!("Trend of {} scan(s) written to {} in {} format", points.len(), output, format_str);
+        }
+        None => io::stdout().write_all(&report)?,
+    }
+    Ok(())
+}
+
+fn text(points: &[TrendPoint]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{:>5}  {:<19}  {:>8}  {:>10}  {:>7}  {:>7}  {:>7}",
+                     "Scan", "Date", "Files", "With Cards", "Cards", "Unique", "Clean");
+    for point in points {
+        let _ = writeln!(out, "{:>5}  {:<19}  {:>8}  {:>10}  {:>7}  {:>7}  {:>6.1}%",
+                         point.scan_id.map(|id| id.to_string()).unwrap_or_default(), point.scan_date,
+                         point.files_scanned, point.files_with_cards, point.cards_found, point.unique_cards,
+                         point.clean_percentage);
+    }
+    let _ = writeln!(out, "\nChange since the first scan:");
+    for (label, change) in changes(points) {
+        let _ = writeln!(out, "  {}: {}", label, change);
+    }
+    out
+}
+
+fn csv(points: &[TrendPoint]) -> io::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Scan", "Scan Date", "Files Scanned", "Files With Cards", "Cards Found", "Unique Cards", "Clean Percentage",
+    ])?;
+    for point in points {
+        writer.write_record([
+            point.scan_id.map(|id| id.to_string()).unwrap_or_default(),
+            point.scan_date.clone(),
+            point.files_scanned.to_string(),
+            point.files_with_cards.to_string(),
+            point.cards_found.to_string(),
+            point.unique_cards.to_string(),
+            format!("{:.1}", point.clean_percentage),
+        ])?;
+    }
+    writer.into_inner().map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(scan_id: i64, cards_found: usize, clean_percentage: f64) -> TrendPoint {
+        TrendPoint {
+            scan_id: Some(scan_id),
+            scan_date: format!("2026-10-0{} 09:00:00", scan_id),
+            files_scanned: 10,
+            files_with_cards: cards_found.min(10),
+            cards_found,
+            unique_cards: cards_found / 2,
+            clean_percentage,
+        }
+    }
+
+    #[test]
+    fn reports_show_changes_and_escape_the_database_name() {
+        let points = [point(1, 12, 40.0), point(2, 5, 55.5), point(3, 4, 62.5)];
+        assert_eq!(changes(&points), [
+            ("Card Numbers Found", "4 (-8)".to_string()),
+            ("Unique Cards", "2 (-4)".to_string()),
+            ("Files Containing Cards", "4 (-6)".to_string()),
+            ("Clean Files (%)", "62.5% (+22.5 points)".to_string()),
+        ]);
+        assert!(changes(&[]).is_empty());
+
+        let database = "/data/<script>x</script>|*.db";
+        let html = html::render_trend(database, &points).unwrap();
+        assert!(html.contains("&lt;script&gt;x&lt;"));
+        assert!(!html.contains("<script>x</script>"));
+        assert!(html.contains("#3 2026-10-03 09:00:00"));
+
+        let md = markdown::render_trend(database, &points);
+        assert!(md.contains("**Database:** /data/\\<script\\>x\\</script\\>\\|\\*.db"));
+        assert!(md.contains("| Clean Files (%) | 62.5% (+22.5 points) |"));
+        assert!(md.contains("| 3 | 2026-10-03 09:00:00 | 10 | 4 | 4 | 2 | 62.5% |"));
+
+        let csv = String::from_utf8(csv(&points).unwrap()).unwrap();
+        assert_eq!(csv.lines().nth(3), Some("3,2026-10-03 09:00:00,10,4,4,2,62.5"));
+        assert!(text(&points).contains("  Card Numbers Found: 4 (-8)\n"));
+    }
+
+    #[test]
+    fn changes_compare_the_latest_scan_with_the_first() {
+        // Increases are signed too, and points in between do not matter
+        let points = [point(1, 2, 80.0), point(2, 30, 10.0), point(3, 7, 72.5)];
+        assert_eq!(changes(&points), [
+            ("Card Numbers Found", "7 (+5)".to_string()),
+            ("Unique Cards", "3 (+2)".to_string()),
+            ("Files Containing Cards", "7 (+5)".to_string()),
+            ("Clean Files (%)", "72.5% (-7.5 points)".to_string()),
+        ]);
+
+        // A single scan has nothing to compare with
+        let changes = changes(&points[..1]);
+        assert!(changes.iter().all(|(_, change)| change.ends_with("(+0)") || change.ends_with("(+0.0 points)")),
+                "{:?}", changes);
+
+        // A scan's own report charts it after the stored scans, as "current"
+        let (summary, findings) = crate::test_report(&[("/srv/a.txt", "4532015112830366"), ("/srv/b.txt", "none")]);
+        let current = TrendPoint::from_summary(&summary);
+        assert_eq!((current.cards_found, current.files_with_cards, current.clean_percentage), (1, 1, 50.0));
+        assert_eq!(current.label(), format!("current {}", summary.scan_date));
+        let html = html::render_report(&summary, &findings, None, &[points[0].clone(), current]).unwrap();
+        assert!(html.contains("<h2>Trend</h2>"));
+        assert!(html.contains("<td>current</td>"));
+        assert!(!html::render_report(&summary, &findings, None, &[]).unwrap().contains("<h2>Trend</h2>"));
+    }
+}
@@ -0,0 +1,463 @@
+// Webhook notification when a scan completes
+//
+// POSTs a JSON summary of the scan to an HTTP endpoint: either luhnoxide's
+// own payload, or a Slack or Microsoft Teams message for incoming webhooks.
+// Top findings are always masked, whatever the output options. With a secret
+// the request is signed: X-Luhnoxide-Signature carries "sha256=" and the hex
+// HMAC-SHA256 of "<X-Luhnoxide-Timestamp>.<body>", so receivers can check
+// both origin and freshness. Failed deliveries (connection errors, 429 and
+// 5xx responses) are retried with exponential backoff.
+use std::collections::HashMap;
+use std::io;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use ureq::Agent;
+
+use crate::masking::MaskPolicy;
+use crate::{CardMatch, ScanSummary};
+
+pub const SECRET_ENV_VAR: &str = "LUHNOXIDE_WEBHOOK_SECRET";
+pub const SIGNATURE_HEADER: &str = "X-Luhnoxide-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Luhnoxide-Timestamp";
+
+// Findings listed in a notification, riskiest files first
+const TOP_FINDINGS: usize = 10;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadFormat {
+    Json,
+    Slack,
+    Teams,
+}
+
+impl PayloadFormat {
+    pub fn from_str(s: &str) -> io::Result<PayloadFormat> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(PayloadFormat::Json),
+            "slack" => Ok(PayloadFormat::Slack),
+            "teams" => Ok(PayloadFormat::Teams),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown webhook format: {}", s))),
+        }
+    }
+}
+
+pub struct Notifier {
+    url: String,
+    format: PayloadFormat,
+    secret: Option<Vec<u8>>,
+    // Attempts after the first one, and the delay before the first retry,
+    // doubled for each retry after it
+    retries: u32,
+    backoff: Duration,
+    agent: Agent,
+}
+
+impl Notifier {
+    pub fn new(url: &str, format: PayloadFormat, secret: Option<Vec<u8>>, retries: u32) -> io::Result<Notifier> {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Webhook URL must start with http:// or https://: {}", url),
+            ));
+        }
+
+        let agent: Agent = Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .build()
+            .into();
+        Ok(Notifier {
+            url: url.to_string(),
+            format,
+            secret,
+            retries,
+            backoff: Duration::from_secs(1),
+            agent,
+        })
+    }
+
+    // Send the notification, retrying transient failures. Returns the number
+    // of attempts it took.
+    pub fn notify(&self, summary: &ScanSummary, results: &[CardMatch], policy: &MaskPolicy) -> io::Result<u32> {
+        let body = serde_json::to_string(&self.payload(summary, results, policy))?;
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let error = match self.send(&body) {
+                Ok(status) if (200..300).contains(&status) => return Ok(attempt),
+                Ok(status) if status == 429 || status >= 500 => format!("HTTP {}", status),
+                Ok(status) => {
+                    return Err(io::Error::other(format!("Webhook {} rejected the notification: HTTP {}", self.url, status)));
+                }
+                Err(e) => e.to_string(),
+            };
+
+            if attempt > self.retries {
+                return Err(io::Error::other(format!(
+                    "Webhook {} failed after {} attempt(s): {}", self.url, attempt, error
+                )));
+            }
+            eprintln!("Webhook attempt {} failed ({}), retrying", attempt, error);
+            thread::sleep(self.backoff * 2u32.saturating_pow(attempt - 1));
+        }
+    }
+
+    fn send(&self, body: &str) -> Result<u16, ureq::Error> {
+        let mut request = self.agent.post(&self.url)
+            .header("Content-Type", "application/json")
+            .header("User-Agent", concat!("luhnoxide/", env!("CARGO_PKG_VERSION")));
+
+        if let Some(ref secret) = self.secret {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()).to_string();
+            request = request
+                .header(TIMESTAMP_HEADER, &timestamp)
+                .header(SIGNATURE_HEADER, &sign(secret, &timestamp, body));
+        }
+
+        Ok(request.send(body)?.status().as_u16())
+    }
+
+    fn payload(&self, summary: &ScanSummary, results: &[CardMatch], policy: &MaskPolicy) -> Value {
+        let top = top_findings(summary, results, policy);
+        match self.format {
+            PayloadFormat::Json => json_payload(summary, &top),
+            PayloadFormat::Slack => slack_payload(summary, &top),
+            PayloadFormat::Teams => teams_payload(summary, &top),
+        }
+    }
+}
+
+// "sha256=" and the hex HMAC-SHA256 of "<timestamp>.<body>"
+pub fn sign(secret: &[u8], timestamp: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", digest)
+}
+
+struct TopFinding {
+    file: String,
+    line: usize,
+    brand: String,
+    pan: String,
+    fingerprint: String,
+    risk: &'static str,
+}
+
+// Masked findings from the riskiest files, in file and line order
+fn top_findings(summary: &ScanSummary, results: &[CardMatch], policy: &MaskPolicy) -> Vec<TopFinding> {
+    let risk_of: HashMap<&str, &'static str> = ["high", "medium", "low"].into_iter()
+        .flat_map(|level| {
+            summary.files_by_risk.get(level).into_iter().flatten().map(move |file| (file.as_str(), level))
+        })
+        .collect();
+    let rank = |risk: &str| match risk {
+        "high" => 0,
+        "medium" => 1,
+        _ => 2,
+    };
+
+    let mut ordered: Vec<(&CardMatch, &'static str)> = results.iter()
+        .map(|card| (card, risk_of.get(card.file_path.as_str()).copied().unwrap_or("low")))
+        .collect();
+    ordered.sort_by(|(a, a_risk), (b, b_risk)| {
+        rank(a_risk).cmp(&rank(b_risk))
+            .then_with(|| a.file_path.cmp(&b.file_path))
+            .then_with(|| a.line_number.cmp(&b.line_number))
+    });
+
+    ordered.into_iter()
+        .take(TOP_FINDINGS)
+        .map(|(card, risk)| TopFinding {
+            file: card.file_path.clone(),
+            line: card.line_number,
+            brand: card.brand.clone(),
+            pan: card.masked_pan(policy),
+            fingerprint: card.fingerprint.clone(),
+            risk,
+        })
+        .collect()
+}
+
+fn tier(summary: &ScanSummary, level: &str) -> usize {
+    summary.files_by_risk.get(level).map_or(0, Vec::len)
+}
+
+fn headline(summary: &ScanSummary) -> String {
+    if summary.total_cards_found == 0 {
+        format!("Luhnoxide scan complete: no card numbers found in {} files", summary.total_files_scanned)
+    } else {
+        format!("Luhnoxide scan complete: {} card numbers ({} unique) found in {} of {} files",
+                summary.total_cards_found, summary.unique_cards_found,
+                summary.total_files_with_cards, summary.total_files_scanned)
+    }
+}
+
+fn json_payload(summary: &ScanSummary, top: &[TopFinding]) -> Value {
+    json!({
+        "event": "scan.completed",
+        "host": gethostname::gethostname().to_string_lossy(),
+        "scan_date": summary.scan_date,
+        "duration": summary.scan_duration,
+        "duration_seconds": summary.scan_duration_seconds,
+        "counts": {
+            "files_scanned": summary.total_files_scanned,
+            "directories_scanned": summary.total_directories_scanned,
+            "files_with_cards": summary.total_files_with_cards,
+            "clean_files": summary.clean_files,
+            "skipped_files": summary.skipped_files.len(),
+            "cards_found": summary.total_cards_found,
+            "unique_cards": summary.unique_cards_found,
+        },
+        "risk_tiers": {
+            "high": tier(summary, "high"),
+            "medium": tier(summary, "medium"),
+            "low": tier(summary, "low"),
+        },
+        "card_types": summary.card_type_counts,
+        "top_findings": top.iter().map(|finding| json!({
+            "file": finding.file,
+            "line": finding.line,
+            "brand": finding.brand,
+            "masked_pan": finding.pan,
+            "fingerprint": finding.fingerprint,
+            "risk": finding.risk,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+// Slack incoming webhook message using Block Kit, with `text` as fallback
+fn slack_payload(summary: &ScanSummary, top: &[TopFinding]) -> Value {
+    let mut blocks = vec![
+        json!({ "type": "header", "text": { "type": "plain_text", "text": "Luhnoxide scan complete" } }),
+        json!({
+            "type": "section",
+            "fields": summary_facts(summary).into_iter()
+                .map(|(name, value)| json!({ "type": "mrkdwn", "text": format!("*{}*\n{}", name, value) }))
+                .collect::<Vec<_>>(),
+        }),
+    ];
+    if !top.is_empty() {
+        let lines: Vec<String> = top.iter()
+            .map(|finding| format!("• `{}` {} in {}:{} ({} risk)",
+                                   finding.pan, finding.brand, slack_escape(&finding.file), finding.line, finding.risk))
+            .collect();
+        blocks.push(json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*Top findings*\n{}", lines.join("\n")) },
+        }));
+    }
+
+    json!({ "text": headline(summary), "blocks": blocks })
+}
+
+// Teams incoming webhook message as a legacy MessageCard
+fn teams_payload(summary: &ScanSummary, top: &[TopFinding]) -> Value {
+    let theme = if tier(summary, "high") > 0 {
+        "E74C3C"
+    } else if summary.total_cards_found > 0 {
+        "F39C12"
+    } else {
+        "2ECC71"
+    };
+
+    let mut sections = vec![json!({
+        "facts": summary_facts(summary).into_iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect::<Vec<_>>(),
+    })];
+    if !top.is_empty() {
+        let lines: Vec<String> = top.iter()
+            .map(|finding| format!("- {} {} in {}:{} ({} risk)",
+                                   finding.pan, finding.brand, finding.file, finding.line, finding.risk))
+            .collect();
+        sections.push(json!({ "title": "Top findings", "text": lines.join("\n\n") }));
+    }
+
+    json!({
+        "@type": "MessageCard",
+        "@context": "https://schema.org/extensions",
+        "summary": headline(summary),
+        "themeColor": theme,
+        "title": "Luhnoxide scan complete",
+        "text": headline(summary),
+        "sections": sections,
+    })
+}
+
+fn summary_facts(summary: &ScanSummary) -> Vec<(&'static str, String)> {
+    vec![
+        ("Files Scanned", summary.total_files_scanned.to_string()),
+        ("Files Containing Cards", summary.total_files_with_cards.to_string()),
+        ("Card Numbers Found", summary.total_cards_found.to_string()),
+        ("Unique Cards", summary.unique_cards_found.to_string()),
+        ("Risk Tiers", format!("{} high, {} medium, {} low",
+                               tier(summary, "high"), tier(summary, "medium"), tier(summary, "low"))),
+        ("Scan Duration", summary.scan_duration.clone()),
+    ]
+}
+
+// Slack treats '&', '<' and '>' as control characters in message text
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::{scan_line, test_scan_config};
+
+    struct Request {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    // Serve one connection per status code, in order, and hand back what was
+    // received
+    fn serve(statuses: Vec<u16>) -> (String, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = HashMap::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => { headers.insert(name.to_lowercase(), value.to_string()); }
+                        None => break,
+                    }
+                }
+                let length: usize = headers.get("content-length").map_or(0, |value| value.parse().unwrap());
+                let mut body = vec![0u8; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut stream = reader.into_inner();
+                write!(stream, "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+                sender.send(Request { headers, body: String::from_utf8(body).unwrap() }).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn scan() -> (ScanSummary, Vec<CardMatch>) {
+        let config = test_scan_config(None);
+        let cards = scan_line(&config, "4532015112830366 and 5555555555554444", 3, 0, "/data/<a>.txt").0;
+
+        let mut summary = ScanSummary::new();
+        summary.total_files_scanned = 4;
+        summary.total_files_with_cards = 1;
+        summary.total_cards_found = cards.len();
+        summary.unique_cards_found = cards.len();
+        summary.add_file_by_risk("high", "/data/<a>.txt");
+        summary.update_duration(Duration::from_secs(75));
+        (summary, cards)
+    }
+
+    fn notifier(url: &str, format: PayloadFormat, secret: Option<&[u8]>, retries: u32) -> Notifier {
+        let mut notifier = Notifier::new(url, format, secret.map(<[u8]>::to_vec), retries).unwrap();
+        notifier.backoff = Duration::from_millis(10);
+        notifier
+    }
+
+    fn policy() -> MaskPolicy {
+        MaskPolicy::from_config("first6last4", "X").unwrap()
+    }
+
+    #[test]
+    fn posts_signed_summary_with_masked_findings() {
+        let (url, requests) = serve(vec![200]);
+        let (summary, cards) = scan();
+        let secret = b"0123456789abcdef0123";
+
+        let attempts = notifier(&url, PayloadFormat::Json, Some(secret), 0).notify(&summary, &cards, &policy()).unwrap();
+        assert_eq!(attempts, 1);
+
+        let request = requests.recv().unwrap();
+        let timestamp = &request.headers[&TIMESTAMP_HEADER.to_lowercase()];
+        assert_eq!(request.headers[&SIGNATURE_HEADER.to_lowercase()], sign(secret, timestamp, &request.body));
+        assert_eq!(request.headers["content-type"], "application/json");
+
+        let payload: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(payload["event"], "scan.completed");
+        assert_eq!(payload["duration"], "1m 15s");
+        assert_eq!(payload["duration_seconds"], 75.0);
+        assert_eq!(payload["counts"]["cards_found"], 2);
+        assert_eq!(payload["risk_tiers"]["high"], 1);
+        assert_eq!(payload["top_findings"][0]["masked_pan"], "453201XXXXXX0366");
+        assert_eq!(payload["top_findings"][0]["risk"], "high");
+        assert!(!request.body.contains("4532015112830366"));
+        assert!(!request.body.contains("5555555555554444"));
+    }
+
+    #[test]
+    fn retries_server_errors_then_succeeds() {
+        let (url, requests) = serve(vec![503, 500, 204]);
+        let (summary, cards) = scan();
+
+        let attempts = notifier(&url, PayloadFormat::Json, None, 3).notify(&summary, &cards, &policy()).unwrap();
+        assert_eq!(attempts, 3);
+        let request = requests.recv().unwrap();
+        assert!(!request.headers.contains_key(&SIGNATURE_HEADER.to_lowercase()));
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let (url, _requests) = serve(vec![502, 502]);
+        let (summary, cards) = scan();
+
+        let error = notifier(&url, PayloadFormat::Json, None, 1).notify(&summary, &cards, &policy()).unwrap_err();
+        assert!(error.to_string().contains("after 2 attempt(s): HTTP 502"), "{}", error);
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        let (url, _requests) = serve(vec![400]);
+        let (summary, cards) = scan();
+
+        let error = notifier(&url, PayloadFormat::Json, None, 3).notify(&summary, &cards, &policy()).unwrap_err();
+        assert!(error.to_string().contains("HTTP 400"), "{}", error);
+    }
+
+    #[test]
+    fn builds_slack_and_teams_messages() {
+        let (summary, cards) = scan();
+
+        let (url, requests) = serve(vec![200, 200]);
+        notifier(&url, PayloadFormat::Slack, None, 0).notify(&summary, &cards, &policy()).unwrap();
+        let slack: Value = serde_json::from_str(&requests.recv().unwrap().body).unwrap();
+        assert!(slack["text"].as_str().unwrap().contains("2 card numbers (2 unique) found in 1 of 4 files"));
+        let findings = slack["blocks"][2]["text"]["text"].as_str().unwrap();
+        assert!(findings.contains("`453201XXXXXX0366` Visa in /data/&lt;a&gt;.txt:3 (high risk)"), "{}", findings);
+
+        notifier(&url, PayloadFormat::Teams, None, 0).notify(&summary, &cards, &policy()).unwrap();
+        let teams: Value = serde_json::from_str(&requests.recv().unwrap().body).unwrap();
+        assert_eq!(teams["@type"], "MessageCard");
+        assert_eq!(teams["themeColor"], "E74C3C");
+        assert_eq!(teams["sections"][0]["facts"][4]["value"], "1 high, 0 medium, 0 low");
+    }
+
+    #[test]
+    fn rejects_non_http_urls() {
+        assert!(Notifier::new("ftp://example.com/hook", PayloadFormat::Json, None, 0).is_err());
+    }
+}
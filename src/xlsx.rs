@@ -0,0 +1,318 @@
+// Excel workbook export
+//
+// One workbook with a Summary sheet (the scan summary and brand breakdown),
+// a Findings sheet with every column of the CSV export, a Files sheet with
+// each scanned file's risk tier and a Skipped sheet. Everything is written
+// as plain string or number cells, so text from scanned files that looks
+// like a formula is never evaluated. A scan comparison from `luhnoxide diff`
+// gets its own layout: a Summary sheet, a Files sheet and a Changes sheet.
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+
+use crate::diff::ScanDiff;
+use crate::{CardMatch, ScanSummary};
+
+// Longest string Excel accepts in a cell
+const MAX_CELL_CHARS: usize = 32_767;
+
+// Widest a column is autofitted to, so long lines don't make sheets unwieldy
+const MAX_COLUMN_PIXELS: u32 = 600;
+
+// Build the workbook. `full_pans` only changes the header.
+pub fn render(summary: &ScanSummary, findings: &[CardMatch], full_pans: bool) -> io::Result<Vec<u8>> {
+    build(summary, findings, full_pans)
+        .map_err(|e| io::Error::other(format!("Cannot write workbook: {}", e)))
+}
+
+fn build(summary: &ScanSummary, findings: &[CardMatch], full_pans: bool) -> Result<Vec<u8>, XlsxError> {
+    let header = Format::new().set_bold().set_background_color("#F2F2F2");
+    let percent = Format::new().set_num_format("0.0%");
+    let mut workbook = Workbook::new();
+
+    write_summary(workbook.add_worksheet().set_name("Summary")?, summary, &header, &percent)?;
+    write_findings(workbook.add_worksheet().set_name("Findings")?, findings, full_pans, &header)?;
+    write_files(workbook.add_worksheet().set_name("Files")?, summary, findings, &header)?;
+    write_skipped(workbook.add_worksheet().set_name("Skipped")?, summary, &header)?;
+
+    workbook.save_to_buffer()
+}
+
+// Build the workbook for a scan comparison
+pub fn render_diff(diff: &ScanDiff) -> io::Result<Vec<u8>> {
+    build_diff(diff).map_err(|e| io::Error::other(format!("Cannot write workbook: {}", e)))
+}
+
+fn build_diff(diff: &ScanDiff) -> Result<Vec<u8>, XlsxError> {
+    let header = Format::new().set_bold().set_background_color("#F2F2F2");
+    let mut workbook = Workbook::new();
+
+    let sheet = workbook.add_worksheet().set_name("Summary")?;
+    sheet.write_row_with_format(0, 0, ["Field", "Value"], &header)?;
+    sheet.write(1, 0, "Old Scan")?;
+    sheet.write(1, 1, cell_text(&diff.old))?;
+    sheet.write(2, 0, "New Scan")?;
+    sheet.write(2, 1, cell_text(&diff.new))?;
+    for (index, (label, value)) in diff.key_metrics().into_iter().enumerate() {
+        let row = index as u32 + 3;
+        sheet.write(row, 0, label)?;
+        sheet.write(row, 1, value.parse::<f64>().unwrap_or_default())?;
+    }
+    sheet.set_autofit_max_width(MAX_COLUMN_PIXELS).autofit();
+
+    let sheet = workbook.add_worksheet().set_name("Files")?;
+    let headers = ["File Path", "Risk", "Added", "Resolved", "Unchanged"];
+    sheet.write_row_with_format(0, 0, headers, &header)?;
+    let files = diff.files();
+    for (index, (file, counts)) in files.iter().enumerate() {
+        let row = index as u32 + 1;
+        sheet.write(row, 0, cell_text(file))?;
+        sheet.write(row, 1, diff.risk(file))?;
+        sheet.write(row, 2, counts.added as f64)?;
+        sheet.write(row, 3, counts.resolved as f64)?;
+        sheet.write(row, 4, counts.unchanged as f64)?;
+    }
+    finish_table(sheet, files.len(), headers.len())?;
+
+    let sheet = workbook.add_worksheet().set_name("Changes")?;
+    let headers = [
+        "Status", "File Path", "Line Number", "Previous Line", "Column Start", "Brand", "Masked PAN",
+        "Fingerprint", "Context",
+    ];
+    sheet.write_row_with_format(0, 0, headers, &header)?;
+    for (index, change) in diff.changes.iter().enumerate() {
+        let row = index as u32 + 1;
+        let card = &change.card;
+        sheet.write(row, 0, change.status.label())?;
+        sheet.write(row, 1, cell_text(&card.file_path))?;
+        sheet.write(row, 2, card.line_number as f64)?;
+        if let Some(line) = change.previous_line {
+            sheet.write(row, 3, line as f64)?;
+        }
+        sheet.write(row, 4, card.column_start as f64)?;
+        sheet.write(row, 5, &card.brand)?;
+        sheet.write(row, 6, &card.full_pan)?;
+        sheet.write(row, 7, &card.fingerprint)?;
+        sheet.write(row, 8, cell_text(&card.context))?;
+    }
+    finish_table(sheet, diff.changes.len(), headers.len())?;
+
+    workbook.save_to_buffer()
+}
+
+fn write_summary(sheet: &mut Worksheet, summary: &ScanSummary, header: &Format, percent: &Format) -> Result<(), XlsxError> {
+    sheet.write_row_with_format(0, 0, ["Field", "Value"], header)?;
+    sheet.write(1, 0, "Scan Date")?;
+    sheet.write(1, 1, &summary.scan_date)?;
+    sheet.write(2, 0, "Scan Duration")?;
+    sheet.write(2, 1, &summary.scan_duration)?;
+
+    let figures = [
+        ("Files Scanned", summary.total_files_scanned as f64),
+        ("Directories Scanned", summary.total_directories_scanned as f64),
+        ("Card Numbers Found", summary.total_cards_found as f64),
+        ("Unique Cards", summary.unique_cards_found as f64),
+        ("Files Containing Cards", summary.total_files_with_cards as f64),
+        ("Clean Files", summary.clean_files as f64),
+        ("Total Size (MB)", summary.total_size_scanned_mb),
+        ("Skipped Files", summary.skipped_files.len() as f64),
+    ];
+    let mut row = 3;
+    for (label, value) in figures {
+        sheet.write(row, 0, label)?;
+        sheet.write(row, 1, value)?;
+        row += 1;
+    }
+    for (level, label) in [("high", "High Risk Files"), ("medium", "Medium Risk Files"), ("low", "Low Risk Files")] {
+        sheet.write(row, 0, label)?;
+        sheet.write(row, 1, summary.files_by_risk.get(level).map_or(0, Vec::len) as f64)?;
+        row += 1;
+    }
+    sheet.write(row, 0, "Clean Files (%)")?;
+    sheet.write_with_format(row, 1, summary.clean_percentage() / 100.0, percent)?;
+    row += 2;
+
+    // Brand breakdown below the figures, most common brand first
+    sheet.write_row_with_format(row, 0, ["Card Brand", "Count", "Unique", "Percentage"], header)?;
+    let mut brands = summary.brand_rows();
+    brands.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    for (brand, count, unique, percentage) in brands {
+        row += 1;
+        sheet.write(row, 0, brand)?;
+        sheet.write(row, 1, count as f64)?;
+        sheet.write(row, 2, unique as f64)?;
+        sheet.write_with_format(row, 3, percentage / 100.0, percent)?;
+    }
+
+    if !summary.cards_in_multiple_files.is_empty() {
+        row += 2;
+        sheet.write_row_with_format(row, 0, ["Masked PAN", "Brand", "Fingerprint", "Files", "File Paths"], header)?;
+        for card in &summary.cards_in_multiple_files {
+            row += 1;
+            sheet.write(row, 0, &card.masked_pan)?;
+            sheet.write(row, 1, &card.brand)?;
+            sheet.write(row, 2, &card.fingerprint)?;
+            sheet.write(row, 3, card.files.len() as f64)?;
+            sheet.write(row, 4, cell_text(&card.files.join("; ")))?;
+        }
+    }
+
+    sheet.set_autofit_max_width(MAX_COLUMN_PIXELS).autofit();
+    Ok(())
+}
+
+fn write_findings(sheet: &mut Worksheet, findings: &[CardMatch], full_pans: bool, header: &Format) -> Result<(), XlsxError> {
+    let headers = [
+        "Brand", "PAN Length", "BIN", "Last Four",
+        if full_pans { "Full PAN" } else { "Masked PAN" },
+        "File Path", "Line Number", "Column Start", "Column End", "Byte Offset",
+        "Context", "Line Content", "Fingerprint",
+    ];
+    sheet.write_row_with_format(0, 0, headers, header)?;
+
+    for (index, card) in findings.iter().enumerate() {
+        let row = index as u32 + 1;
+        sheet.write(row, 0, &card.brand)?;
+        sheet.write(row, 1, card.length as f64)?;
+        sheet.write(row, 2, &card.bin)?;
+        sheet.write(row, 3, &card.last_four)?;
+        sheet.write(row, 4, &card.full_pan)?;
+        sheet.write(row, 5, cell_text(&card.file_path))?;
+        sheet.write(row, 6, card.line_number as f64)?;
+        sheet.write(row, 7, card.column_start as f64)?;
+        sheet.write(row, 8, card.column_end as f64)?;
+        sheet.write(row, 9, card.byte_offset as f64)?;
+        sheet.write(row, 10, cell_text(&card.context))?;
+        sheet.write(row, 11, cell_text(&card.line_content))?;
+        sheet.write(row, 12, &card.fingerprint)?;
+    }
+
+    finish_table(sheet, findings.len(), headers.len())
+}
+
+// Every scanned file with its risk tier and finding counts, riskiest first
+fn write_files(sheet: &mut Worksheet, summary: &ScanSummary, findings: &[CardMatch], header: &Format) -> Result<(), XlsxError> {
+    let headers = ["File Path", "Risk", "Findings", "Unique Cards"];
+    sheet.write_row_with_format(0, 0, headers, header)?;
+
+    let risk_of: HashMap<&str, &str> = summary.files_by_risk.iter()
+        .flat_map(|(level, files)| files.iter().map(move |file| (file.as_str(), level.as_str())))
+        .collect();
+    let mut counts: HashMap<&str, (usize, HashSet<&str>)> = HashMap::new();
+    for card in findings {
+        let entry = counts.entry(&card.file_path).or_default();
+        entry.0 += 1;
+        entry.1.insert(&card.fingerprint);
+    }
+
+    let rank = |file: &str| match risk_of.get(file).copied() {
+        Some("high") => 0,
+        Some("medium") => 1,
+        Some(_) => 2,
+        None => 3,
+    };
+    let mut files: Vec<&String> = summary.all_scanned_files.iter().collect();
+    files.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+
+    for (index, file) in files.iter().enumerate() {
+        let row = index as u32 + 1;
+        let (count, unique) = counts.get(file.as_str()).map_or((0, 0), |(count, cards)| (*count, cards.len()));
+        sheet.write(row, 0, cell_text(file))?;
+        sheet.write(row, 1, risk_of.get(file.as_str()).copied().unwrap_or("clean"))?;
+        sheet.write(row, 2, count as f64)?;
+        sheet.write(row, 3, unique as f64)?;
+    }
+
+    finish_table(sheet, files.len(), headers.len())
+}
+
+fn write_skipped(sheet: &mut Worksheet, summary: &ScanSummary, header: &Format) -> Result<(), XlsxError> {
+    sheet.write_with_format(0, 0, "File Path", header)?;
+    for (index, file) in summary.skipped_files.iter().enumerate() {
+        sheet.write(index as u32 + 1, 0, cell_text(file))?;
+    }
+
+    finish_table(sheet, summary.skipped_files.len(), 1)
+}
+
+// Freeze the header row, add filter buttons and size the columns
+fn finish_table(sheet: &mut Worksheet, rows: usize, columns: usize) -> Result<(), XlsxError> {
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofilter(0, 0, rows as u32, columns as u16 - 1)?;
+    sheet.set_autofit_max_width(MAX_COLUMN_PIXELS).autofit();
+    Ok(())
+}
+
+// Text cut to what fits in a cell; only very long lines are affected
+fn cell_text(value: &str) -> &str {
+    match value.char_indices().nth(MAX_CELL_CHARS) {
+        Some((end, _)) => &value[..end],
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use crate::test_report;
+
+    // Every XML part of the workbook, concatenated
+    fn workbook_xml(workbook: Vec<u8>) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(workbook)).unwrap();
+        let mut xml = String::new();
+        for index in 0..archive.len() {
+            archive.by_index(index).unwrap().read_to_string(&mut xml).unwrap();
+        }
+        xml
+    }
+
+    // One XML part of the workbook
+    fn part(workbook: &[u8], name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(workbook)).unwrap();
+        let mut xml = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut xml).unwrap();
+        xml
+    }
+
+    #[test]
+    fn workbooks_hold_masked_pans_and_scanned_text_as_plain_strings() {
+        let (summary, findings) = test_report(&[("=cmd|'/c calc'!A1.txt", "a<b>&c 4532015112830366")]);
+        let xml = workbook_xml(render(&summary, &findings, false).unwrap());
+
+        assert!(xml.contains("453201XXXXXX0366"));
+        assert!(!xml.contains("4532015112830366"));
+        assert!(xml.contains("Masked PAN"));
+        assert!(xml.contains("<t>=cmd|'/c calc'!A1.txt</t>"), "{}", xml);
+        assert!(xml.contains("a&lt;b&gt;&amp;c 453201XXXXXX0366"));
+        assert!(!xml.contains("<f>"));
+    }
+
+    #[test]
+    fn workbooks_have_the_four_sheets_with_figures_as_numbers() {
+        let (mut summary, findings) = test_report(&[("/srv/a.txt", "x 4532015112830366"), ("/srv/clean.txt", "none")]);
+        summary.skipped_files.push("/srv/blob.bin".to_string());
+        let workbook = render(&summary, &findings, false).unwrap();
+
+        assert!(part(&workbook, "xl/workbook.xml").contains(
+            r#"<sheets><sheet name="Summary" sheetId="1" r:id="rId1"/><sheet name="Findings" sheetId="2" r:id="rId2"/><sheet name="Files" sheetId="3" r:id="rId3"/><sheet name="Skipped" sheetId="4" r:id="rId4"/></sheets>"#));
+
+        // Summary: files scanned, then the clean percentage as a fraction
+        let sheet = part(&workbook, "xl/worksheets/sheet1.xml");
+        assert!(sheet.contains(r#"<c r="B4"><v>2</v></c>"#));
+        assert!(sheet.contains(r#"<c r="B15" s="2"><v>0.5</v></c>"#));
+        // Findings: PAN length, line, columns and byte offset
+        let sheet = part(&workbook, "xl/worksheets/sheet2.xml");
+        assert!(sheet.contains(r#"<c r="B2"><v>16</v></c>"#));
+        assert!(sheet.contains(r#"<c r="G2"><v>1</v></c><c r="H2"><v>3</v></c><c r="I2"><v>19</v></c><c r="J2"><v>2</v></c>"#));
+        assert!(sheet.contains(r#"<autoFilter ref="A1:M2"/>"#));
+        // Files: one row per scanned file with its finding counts
+        let sheet = part(&workbook, "xl/worksheets/sheet3.xml");
+        assert!(sheet.contains(r#"<c r="C2"><v>1</v></c><c r="D2"><v>1</v></c>"#));
+        assert!(sheet.contains(r#"<c r="C3"><v>0</v></c><c r="D3"><v>0</v></c>"#));
+        assert!(part(&workbook, "xl/worksheets/sheet4.xml").contains(r#"<dimension ref="A1:A2"/>"#));
+        assert!(workbook_xml(workbook).contains("<t>/srv/blob.bin</t>"));
+    }
+}